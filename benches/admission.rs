@@ -0,0 +1,130 @@
+//! Sustained admission throughput through the queue pipeline: concurrent
+//! producers parse and submit transactions the way the HTTP handlers do,
+//! while the processor seals batches and the builder compresses and stores
+//! them.
+//!
+//! `cargo bench --bench admission`. `BENCH_SECS`, `BENCH_PRODUCERS` and
+//! `BENCH_BATCH_SIZE` override the defaults.
+
+use alloy::providers::ProviderBuilder;
+use sequencer::config::Config;
+use sequencer::services::batch_store::BatchStore;
+use sequencer::services::metrics::Metrics;
+use sequencer::services::parser::{parse_raw_transaction, RawTransactionData};
+use sequencer::services::queue_service::{setup_queue, QueueError};
+use sequencer::services::status::SequencerStatus;
+use serde_json::json;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn raw_transaction(producer: usize, nonce: u64) -> RawTransactionData {
+    serde_json::from_value(json!({
+        "nonce": format!("0x{:x}", nonce),
+        "from": format!("0x{:040x}", producer + 1),
+        "to": "0x2222222222222222222222222222222222222222",
+        "value": "0xde0b6b3a7640000",
+        "data": "0xa9059cbb000000000000000000000000111111111111111111111111111111111111111100000000000000000000000000000000000000000000000000000000000f4240",
+        "gas_limit": "0x5208",
+        "gas_price": "0x6fc23ac00",
+        "chain_id": 42161,
+        "l1_block_number": 0,
+        "submission_fee": "0xf4240"
+    }))
+    .expect("valid raw transaction")
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let duration = Duration::from_secs(env_or("BENCH_SECS", 5));
+    let producers: usize = env_or("BENCH_PRODUCERS", 64);
+    let mut config = Config::default();
+    config.batch.max_transactions = env_or("BENCH_BATCH_SIZE", 50);
+
+    let data_dir = std::env::temp_dir().join(format!("admission-bench-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let store = Arc::new(BatchStore::open(&data_dir)?);
+    let metrics = Arc::new(Metrics::new());
+    let status = Arc::new(SequencerStatus::new(
+        data_dir.clone(),
+        Duration::from_secs(60),
+    ));
+    // Never contacted; nonce checks are off
+    let provider = ProviderBuilder::new().on_http("http://localhost:8545".parse()?);
+
+    let codec = config.batch.codec()?;
+    let (queue, mut processor, mut builder) =
+        setup_queue(provider, &config, store, metrics.clone(), status, codec)?;
+    tokio::spawn(async move { processor.run().await });
+    tokio::spawn(async move { builder.run().await });
+
+    let admitted = Arc::new(AtomicU64::new(0));
+    let shed = Arc::new(AtomicU64::new(0));
+    let start = Instant::now();
+    let deadline = start + duration;
+
+    let tasks: Vec<_> = (0..producers)
+        .map(|producer| {
+            let queue = queue.clone();
+            let admitted = admitted.clone();
+            let shed = shed.clone();
+            tokio::spawn(async move {
+                let mut nonce = 0;
+                while Instant::now() < deadline {
+                    let raw = raw_transaction(producer, nonce);
+                    let tx = parse_raw_transaction(&raw).expect("parses");
+                    match queue.submit_transaction(tx) {
+                        Ok(_) => {
+                            admitted.fetch_add(1, Ordering::Relaxed);
+                            nonce += 1;
+                        }
+                        Err(QueueError::Full) => {
+                            shed.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => panic!("submission failed: {}", e),
+                    }
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.await?;
+    }
+    let admission_time = start.elapsed();
+
+    // Includes compressing and storing everything still in flight
+    let final_batches = queue.shutdown().await?;
+    let total_time = start.elapsed();
+
+    let admitted = admitted.load(Ordering::Relaxed);
+    let shed = shed.load(Ordering::Relaxed);
+    let batches = metrics.batches.get();
+    println!(
+        "producers: {}, batch size: {}",
+        producers, config.batch.max_transactions
+    );
+    println!(
+        "admitted: {} tx in {:.2}s ({:.0} tx/s), shed {} as queue full",
+        admitted,
+        admission_time.as_secs_f64(),
+        admitted as f64 / admission_time.as_secs_f64(),
+        shed
+    );
+    println!(
+        "built: {} batches ({} at shutdown), {:.0} tx/s end to end",
+        batches,
+        final_batches.len(),
+        admitted as f64 / total_time.as_secs_f64()
+    );
+
+    let _ = std::fs::remove_dir_all(&data_dir);
+    Ok(())
+}
@@ -0,0 +1,284 @@
+//! Compression ratio and throughput of each batch codec on batches shaped
+//! like real traffic: a pool of repeat senders making transfers, ERC20
+//! transfers and swaps against a handful of contracts.
+//!
+//! Batches are in the flat RLP format, as the batcher writes them by default.
+//! The `span` and `bincode` columns are the same batch's compressed size in
+//! the span format and in the bincode encoding payloads used before, through
+//! the same codec.
+//!
+//! `cargo bench --bench codecs`. `BENCH_ROUNDS` sets how many times each
+//! batch is encoded and decoded for the timings.
+
+use alloy::primitives::B256;
+use alloy::primitives::{Address, Bytes, U256};
+use sequencer::models::codec::{
+    self, BatchCodec, Brotli, NoCompression, PayloadDecoder, PayloadFormat, Zstd,
+};
+use sequencer::models::{
+    compress_span_batch, BincodeTransaction, Block, BlockFrames, ChannelCompressor, L2Transaction,
+};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const SENDERS: usize = 2_000;
+const BATCH_SIZES: [usize; 3] = [50, 500, 5_000];
+const DICTIONARY_SIZE: usize = 16 * 1024;
+
+/// Deterministic, so runs compare
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn address(&mut self) -> Address {
+        let mut bytes = [0u8; 20];
+        for chunk in bytes.chunks_mut(8) {
+            chunk.copy_from_slice(&self.next().to_be_bytes()[..chunk.len()]);
+        }
+        Address::from(bytes)
+    }
+}
+
+struct Traffic {
+    rng: SplitMix,
+    senders: Vec<(Address, u64)>,
+    tokens: Vec<Address>,
+    router: Address,
+}
+
+impl Traffic {
+    fn new(seed: u64) -> Self {
+        let mut rng = SplitMix(seed);
+        let senders = (0..SENDERS)
+            .map(|_| (rng.address(), rng.next() % 500))
+            .collect();
+        let tokens = (0..8).map(|_| rng.address()).collect();
+        let router = rng.address();
+        Self {
+            rng,
+            senders,
+            tokens,
+            router,
+        }
+    }
+
+    fn transaction(&mut self) -> L2Transaction {
+        // A few busy senders make most of the traffic
+        let sender = self.rng.below(SENDERS).min(self.rng.below(SENDERS));
+        let (from, nonce) = self.senders[sender];
+        self.senders[sender].1 += 1;
+        let amount = U256::from(self.rng.next() % 1_000_000) * U256::from(10u64.pow(12));
+
+        let (to, value, data, gas_limit) = match self.rng.below(10) {
+            0..=2 => (self.rng.address(), amount, Bytes::new(), 21_000),
+            3..=6 => {
+                let token = self.tokens[self.rng.below(self.tokens.len())];
+                let recipient = self.rng.address();
+                (token, U256::ZERO, erc20_transfer(recipient, amount), 65_000)
+            }
+            _ => {
+                let path = [
+                    self.tokens[self.rng.below(self.tokens.len())],
+                    self.tokens[self.rng.below(self.tokens.len())],
+                ];
+                (self.router, U256::ZERO, swap(amount, &path, from), 180_000)
+            }
+        };
+
+        L2Transaction::new(
+            nonce,
+            from,
+            Some(to),
+            value,
+            data,
+            gas_limit,
+            U256::from(100_000_000u64 + self.rng.next() % 50_000_000),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    fn batch(&mut self, size: usize) -> Vec<L2Transaction> {
+        (0..size).map(|_| self.transaction()).collect()
+    }
+}
+
+fn word(bytes: &[u8]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    word
+}
+
+fn erc20_transfer(to: Address, amount: U256) -> Bytes {
+    let mut data = vec![0xa9, 0x05, 0x9c, 0xbb];
+    data.extend(word(to.as_slice()));
+    data.extend(amount.to_be_bytes::<32>());
+    Bytes::from(data)
+}
+
+/// `swapExactTokensForTokens(amountIn, amountOutMin, path, to, deadline)`
+fn swap(amount: U256, path: &[Address], to: Address) -> Bytes {
+    let mut data = vec![0x38, 0xed, 0x17, 0x39];
+    data.extend(amount.to_be_bytes::<32>());
+    data.extend((amount / U256::from(100)).to_be_bytes::<32>());
+    data.extend(word(&[0xa0]));
+    data.extend(word(to.as_slice()));
+    data.extend(word(&1_900_000_000u64.to_be_bytes()));
+    data.extend(word(&(path.len() as u64).to_be_bytes()));
+    for token in path {
+        data.extend(word(token.as_slice()));
+    }
+    Bytes::from(data)
+}
+
+fn encode(codec: &dyn BatchCodec, block: &Block) -> (Vec<u8>, usize) {
+    let mut compressor = ChannelCompressor::new(codec).expect("encoder starts");
+    for tx in &block.transactions {
+        compressor.add(tx).expect("compresses");
+    }
+    let compressed = compressor
+        .finish(&BlockFrames::of(std::slice::from_ref(block)))
+        .expect("finishes");
+    (compressed.data, compressed.uncompressed_size)
+}
+
+/// Compressed size of the batch's bincode encoding
+fn bincode_size(codec: &dyn BatchCodec, txs: &[L2Transaction]) -> usize {
+    let mut encoder = codec
+        .encoder(codec::header(PayloadFormat::Bincode, codec.id()))
+        .expect("encoder starts");
+    for tx in txs {
+        let encoded = bincode::serialize(&BincodeTransaction::from(tx)).expect("encodes");
+        encoder.write_all(&encoded).expect("compresses");
+    }
+    encoder.finish().expect("finishes").len()
+}
+
+fn throughput(bytes: usize, rounds: u32, elapsed: Duration) -> f64 {
+    (bytes as f64 * rounds as f64) / elapsed.as_secs_f64() / 1e6
+}
+
+fn main() {
+    let rounds: u32 = std::env::var("BENCH_ROUNDS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(20);
+
+    // Trained on different traffic from what's measured, like a deployed
+    // dictionary would be
+    let mut training = Traffic::new(1);
+    let samples: Vec<Vec<u8>> = (0..2_000)
+        .map(|_| alloy::rlp::encode(training.transaction()))
+        .collect();
+    let dictionary = zstd::dict::from_samples(&samples, DICTIONARY_SIZE).expect("trains");
+
+    let codecs: Vec<(&str, Box<dyn BatchCodec>)> = vec![
+        ("none", Box::new(NoCompression)),
+        ("brotli q3 w22", Box::new(Brotli::default())),
+        (
+            "brotli q9 w22",
+            Box::new(Brotli {
+                quality: 9,
+                window: 22,
+            }),
+        ),
+        (
+            "brotli q11 w24",
+            Box::new(Brotli {
+                quality: 11,
+                window: 24,
+            }),
+        ),
+        (
+            "zstd 3",
+            Box::new(Zstd {
+                level: 3,
+                dictionary: None,
+            }),
+        ),
+        (
+            "zstd 19",
+            Box::new(Zstd {
+                level: 19,
+                dictionary: None,
+            }),
+        ),
+        (
+            "zstd 3 + dict",
+            Box::new(Zstd {
+                level: 3,
+                dictionary: Some(dictionary.clone()),
+            }),
+        ),
+        (
+            "zstd 19 + dict",
+            Box::new(Zstd {
+                level: 19,
+                dictionary: Some(dictionary.clone()),
+            }),
+        ),
+    ];
+    let plain = PayloadDecoder::default();
+    let with_dictionary = PayloadDecoder::new(Some(dictionary));
+
+    let mut traffic = Traffic::new(2);
+    for size in BATCH_SIZES {
+        let txs = traffic.batch(size);
+        let block = Block::new(1, txs.clone(), B256::ZERO, B256::ZERO, 1_700_000_000);
+        println!("\n{} transactions", size);
+        println!(
+            "{:<16} {:>10} {:>10} {:>7} {:>10} {:>10} {:>12} {:>12}",
+            "codec", "input", "output", "ratio", "span", "bincode", "encode MB/s", "decode MB/s"
+        );
+
+        for (name, codec) in &codecs {
+            let codec = codec.as_ref();
+            let decoder = if name.ends_with("+ dict") {
+                &with_dictionary
+            } else {
+                &plain
+            };
+            let (payload, input) = encode(codec, &block);
+
+            let start = Instant::now();
+            for _ in 0..rounds {
+                std::hint::black_box(encode(codec, &block));
+            }
+            let encode_time = start.elapsed();
+
+            let start = Instant::now();
+            for _ in 0..rounds {
+                let decoded = codec::decode_payload(&payload, decoder).expect("decodes");
+                assert_eq!(decoded.encoded.len(), input);
+            }
+            let decode_time = start.elapsed();
+
+            println!(
+                "{:<16} {:>10} {:>10} {:>7.2} {:>10} {:>10} {:>12.1} {:>12.1}",
+                name,
+                input,
+                payload.len(),
+                input as f64 / payload.len() as f64,
+                compress_span_batch(std::slice::from_ref(&block), codec)
+                    .expect("compresses")
+                    .data
+                    .len(),
+                bincode_size(codec, &txs),
+                throughput(input, rounds, encode_time),
+                throughput(input, rounds, decode_time),
+            );
+        }
+    }
+}
@@ -74,12 +74,16 @@ export default function SendTransactionButton() {
   useEffect(() => {
     setTransactionPreview(generatePreview());
 
-    const ws = new WebSocket(`${WS_BASE_URL}/transaction_feed`);
+    const ws = new WebSocket(`${WS_BASE_URL}/transaction_feed`, [
+      'bearer',
+      `${process.env.NEXT_PUBLIC_BEARER_TOKEN}`,
+    ]);
 
     ws.onopen = () => setTransactionCount(0);
     ws.onmessage = (event) => {
       try {
         const transaction = JSON.parse(event.data);
+        if (transaction.type !== 'transaction') return;
         setTransactions((prev) => [transaction, ...prev].slice(0, 10));
         setTransactionCount((prevCount) => prevCount + 1);
       } catch (err) {
@@ -0,0 +1,140 @@
+use crate::api::error::ApiError;
+use crate::models::L2Transaction;
+use crate::services::batch_submitter::BatchSubmitterHandle;
+use crate::services::queue_service::{BatchParams, BatchParamsUpdate, PendingFilter};
+use crate::types::AppState;
+use alloy::primitives::{Address, B256};
+use axum::{
+    extract::{rejection::JsonRejection, Path, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::info;
+
+const DEFAULT_PENDING_LIMIT: usize = 100;
+const MAX_PENDING_LIMIT: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct PendingQuery {
+    from: Option<Address>,
+    to: Option<Address>,
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct PendingTransaction {
+    hash: B256,
+    #[serde(flatten)]
+    transaction: L2Transaction,
+}
+
+impl From<L2Transaction> for PendingTransaction {
+    fn from(transaction: L2Transaction) -> Self {
+        Self {
+            hash: transaction.hash(),
+            transaction,
+        }
+    }
+}
+
+/// `POST /admin/batch`: seal everything queued into a batch now.
+pub async fn force_batch(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let index = state.queue.batch_transactions().await?;
+    info!(batch_index = ?index, "operator forced a batch");
+    Ok(Json(json!({ "batch_index": index })))
+}
+
+pub async fn pause_admission(State(state): State<AppState>) -> Json<Value> {
+    set_admission_paused(&state, true)
+}
+
+pub async fn resume_admission(State(state): State<AppState>) -> Json<Value> {
+    set_admission_paused(&state, false)
+}
+
+fn set_admission_paused(state: &AppState, paused: bool) -> Json<Value> {
+    state.queue.set_admission_paused(paused);
+    info!(paused, "operator changed admission");
+    Json(json!({ "admission_paused": paused }))
+}
+
+pub async fn pause_l1_posting(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    set_l1_posting_paused(&state, true)
+}
+
+pub async fn resume_l1_posting(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    set_l1_posting_paused(&state, false)
+}
+
+fn set_l1_posting_paused(state: &AppState, paused: bool) -> Result<Json<Value>, ApiError> {
+    let submitter = l1_submitter(state)?;
+    submitter.set_paused(paused);
+    info!(paused, "operator changed L1 posting");
+    Ok(Json(json!({ "l1_posting_paused": paused })))
+}
+
+fn l1_submitter(state: &AppState) -> Result<&BatchSubmitterHandle, ApiError> {
+    state
+        .l1_submitter
+        .as_ref()
+        .ok_or_else(|| ApiError::Conflict("L1 batch posting is not enabled".to_string()))
+}
+
+/// `GET /admin/pending?from=&to=&limit=`
+pub async fn list_pending(
+    State(state): State<AppState>,
+    Query(query): Query<PendingQuery>,
+) -> Result<Json<Vec<PendingTransaction>>, ApiError> {
+    let filter = PendingFilter {
+        from: query.from,
+        to: query.to,
+        limit: query
+            .limit
+            .unwrap_or(DEFAULT_PENDING_LIMIT)
+            .min(MAX_PENDING_LIMIT),
+    };
+    let pending = state.queue.list_pending(filter).await?;
+    Ok(Json(pending.into_iter().map(Into::into).collect()))
+}
+
+/// `DELETE /admin/pending/{from}/{nonce}`
+pub async fn evict_transaction(
+    State(state): State<AppState>,
+    Path((from, nonce)): Path<(Address, u64)>,
+) -> Result<Json<Vec<PendingTransaction>>, ApiError> {
+    let evicted = state.queue.evict_transaction(from, nonce).await?;
+    if evicted.is_empty() {
+        return Err(ApiError::NotFound(format!(
+            "No pending transaction from {} with nonce {}",
+            from, nonce
+        )));
+    }
+    Ok(Json(evicted.into_iter().map(Into::into).collect()))
+}
+
+pub async fn get_batch_params(
+    State(state): State<AppState>,
+) -> Result<Json<BatchParams>, ApiError> {
+    let params = state
+        .queue
+        .update_batch_params(BatchParamsUpdate::default())
+        .await?;
+    Ok(Json(params))
+}
+
+/// `PATCH /admin/batch_params`
+pub async fn update_batch_params(
+    State(state): State<AppState>,
+    payload: Result<Json<BatchParamsUpdate>, JsonRejection>,
+) -> Result<Json<BatchParams>, ApiError> {
+    let Json(update) = payload?;
+    if update.max_transactions == Some(0) {
+        return Err(ApiError::validation(
+            "max_transactions",
+            "max_transactions must be at least 1",
+        ));
+    }
+    let params = state.queue.update_batch_params(update).await?;
+    Ok(Json(params))
+}
@@ -0,0 +1,276 @@
+use crate::api::auth::{AuthRejection, CallerIdentity};
+use crate::api::rate_limit::{RateLimit, TokenBucket};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use subtle::ConstantTimeEq;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Submit,
+    Feed,
+    Admin,
+    Read,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [Scope::Submit, Scope::Feed, Scope::Admin, Scope::Read];
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.to_string() == s)
+            .ok_or_else(|| format!("Unknown scope: {}", s))
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Scope::Submit => "submit",
+            Scope::Feed => "feed",
+            Scope::Admin => "admin",
+            Scope::Read => "read",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiKeyError {
+    #[error("Failed to read API keys file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to parse API keys file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("API key {0} has an invalid hash, expected 64 hex characters")]
+    InvalidHash(String),
+    #[error("API key {0} needs a rate_limit with requests_per_second > 0 and burst >= 1")]
+    InvalidRateLimit(String),
+    #[error("Duplicate API key id: {0}")]
+    DuplicateId(String),
+    #[error("No API keys configured")]
+    Empty,
+}
+
+#[derive(Deserialize)]
+struct ApiKeysFile {
+    keys: Vec<ApiKeyConfig>,
+}
+
+#[derive(Deserialize)]
+struct ApiKeyConfig {
+    id: String,
+    /// Hex SHA-256 of the key, so the file never holds usable secrets
+    hash: String,
+    scopes: Vec<Scope>,
+    rate_limit: Option<RateLimit>,
+}
+
+struct ApiKey {
+    id: String,
+    hash: [u8; 32],
+    scopes: Vec<Scope>,
+    bucket: Option<Mutex<TokenBucket>>,
+}
+
+pub struct ApiKeyRegistry {
+    keys: Vec<ApiKey>,
+}
+
+impl ApiKeyRegistry {
+    /// Loads keys from a TOML file:
+    ///
+    /// ```toml
+    /// [[keys]]
+    /// id = "market-maker"
+    /// hash = "<sha256 hex of the key>"
+    /// scopes = ["submit", "feed"]
+    /// rate_limit = { requests_per_second = 100, burst = 200 }
+    /// ```
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ApiKeyError> {
+        let contents = std::fs::read_to_string(path)?;
+        Self::from_toml(&contents)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self, ApiKeyError> {
+        let file: ApiKeysFile = toml::from_str(contents)?;
+
+        let mut keys: Vec<ApiKey> = Vec::with_capacity(file.keys.len());
+        for config in file.keys {
+            if keys.iter().any(|k| k.id == config.id) {
+                return Err(ApiKeyError::DuplicateId(config.id));
+            }
+            let hash = hex::decode(config.hash.trim_start_matches("0x"))
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                .ok_or_else(|| ApiKeyError::InvalidHash(config.id.clone()))?;
+            if config.rate_limit.is_some_and(|limit| !limit.is_valid()) {
+                return Err(ApiKeyError::InvalidRateLimit(config.id));
+            }
+
+            keys.push(ApiKey {
+                id: config.id,
+                hash,
+                scopes: config.scopes,
+                bucket: config.rate_limit.map(|l| Mutex::new(TokenBucket::new(l))),
+            });
+        }
+
+        if keys.is_empty() {
+            return Err(ApiKeyError::Empty);
+        }
+        Ok(Self { keys })
+    }
+
+    /// A single unlimited key with every scope, for the legacy `AUTH_TOKEN` setup.
+    pub fn single(id: &str, token: &str) -> Self {
+        Self {
+            keys: vec![ApiKey {
+                id: id.to_string(),
+                hash: hash_key(token),
+                scopes: Scope::ALL.to_vec(),
+                bucket: None,
+            }],
+        }
+    }
+
+    /// Resolves `token` to a key holding `scope` and charges its rate limit.
+    pub fn authorize(&self, token: &str, scope: Scope) -> Result<CallerIdentity, AuthRejection> {
+        let key = self.find(token).ok_or(AuthRejection::InvalidToken)?;
+
+        if !key.scopes.contains(&scope) {
+            return Err(AuthRejection::MissingScope(scope));
+        }
+
+        if let Some(bucket) = &key.bucket {
+            let mut bucket = bucket.lock().unwrap_or_else(|e| e.into_inner());
+            bucket.try_acquire().map_err(AuthRejection::RateLimited)?;
+        }
+
+        Ok(CallerIdentity {
+            id: key.id.clone(),
+            scopes: key.scopes.clone(),
+        })
+    }
+
+    fn find(&self, token: &str) -> Option<&ApiKey> {
+        let hash = hash_key(token);
+        // Compare against every key so timing doesn't reveal which one matched
+        let mut found = None;
+        for key in &self.keys {
+            if bool::from(key.hash.ct_eq(&hash)) {
+                found = Some(key);
+            }
+        }
+        found
+    }
+}
+
+pub fn hash_key(token: &str) -> [u8; 32] {
+    Sha256::digest(token.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> ApiKeyRegistry {
+        let contents = format!(
+            r#"
+            [[keys]]
+            id = "bot"
+            hash = "{}"
+            scopes = ["submit"]
+            rate_limit = {{ requests_per_second = 1, burst = 2 }}
+
+            [[keys]]
+            id = "ops"
+            hash = "0x{}"
+            scopes = ["admin", "read"]
+            "#,
+            hex::encode(hash_key("bot-key")),
+            hex::encode(hash_key("ops-key")),
+        );
+        ApiKeyRegistry::from_toml(&contents).unwrap()
+    }
+
+    #[test]
+    fn test_authorize_checks_key_and_scope() {
+        let registry = registry();
+
+        let identity = registry.authorize("bot-key", Scope::Submit).unwrap();
+        assert_eq!(identity.id, "bot");
+
+        assert!(matches!(
+            registry.authorize("bot-key", Scope::Admin),
+            Err(AuthRejection::MissingScope(Scope::Admin))
+        ));
+        assert!(matches!(
+            registry.authorize("wrong", Scope::Submit),
+            Err(AuthRejection::InvalidToken)
+        ));
+        assert_eq!(
+            registry.authorize("ops-key", Scope::Read).unwrap().id,
+            "ops"
+        );
+    }
+
+    #[test]
+    fn test_per_key_rate_limit() {
+        let registry = registry();
+
+        assert!(registry.authorize("bot-key", Scope::Submit).is_ok());
+        assert!(registry.authorize("bot-key", Scope::Submit).is_ok());
+        assert!(matches!(
+            registry.authorize("bot-key", Scope::Submit),
+            Err(AuthRejection::RateLimited(_))
+        ));
+
+        // Other keys have their own budget
+        assert!(registry.authorize("ops-key", Scope::Read).is_ok());
+    }
+
+    #[test]
+    fn test_rejects_invalid_files() {
+        assert!(matches!(
+            ApiKeyRegistry::from_toml("keys = []"),
+            Err(ApiKeyError::Empty)
+        ));
+        assert!(matches!(
+            ApiKeyRegistry::from_toml(
+                r#"
+                [[keys]]
+                id = "bad"
+                hash = "abcd"
+                scopes = []
+                "#
+            ),
+            Err(ApiKeyError::InvalidHash(_))
+        ));
+        for rate_limit in ["0", "-1", "nan", "inf"] {
+            let contents = format!(
+                r#"
+                [[keys]]
+                id = "bad"
+                hash = "{}"
+                scopes = []
+                rate_limit = {{ requests_per_second = {} }}
+                "#,
+                hex::encode(hash_key("bad-key")),
+                rate_limit,
+            );
+            assert!(matches!(
+                ApiKeyRegistry::from_toml(&contents),
+                Err(ApiKeyError::InvalidRateLimit(_))
+            ));
+        }
+    }
+}
@@ -1,64 +1,201 @@
+use crate::api::api_keys::{ApiKeyRegistry, Scope};
+use crate::api::error::ApiError;
+use crate::api::jwt::JwtValidator;
+use crate::config::{AuthConfig, AuthMode};
+use crate::types::AppState;
 use axum::{
-    extract::Request,
-    http::{header, StatusCode},
+    extract::{Query, Request, State},
+    http::header,
     middleware::Next,
     response::{IntoResponse, Response},
-    Json,
 };
-use serde::Serialize;
-use std::env;
+use serde::Deserialize;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::Instrument;
 
-#[derive(Serialize)]
-struct AuthError {
-    error: String,
-    code: String,
+/// Subprotocol browsers use to carry a token, sent as `bearer, <token>`
+pub const BEARER_PROTOCOL: &str = "bearer";
+
+/// Identity of an authenticated caller, attached to the request extensions.
+#[derive(Debug, Clone)]
+pub struct CallerIdentity {
+    pub id: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Verifies bearer tokens, either as static API keys or as signed JWTs.
+pub enum Authenticator {
+    ApiKeys(ApiKeyRegistry),
+    Jwt(JwtValidator),
 }
 
-pub async fn auth_middleware(req: Request, next: Next) -> Response {
-    // Skip auth for WebSocket upgrades
-    if req
-        .headers()
-        .get("upgrade")
-        .map(|v| v == "websocket")
-        .unwrap_or(false)
-    {
-        println!("WebSocket upgrade detected, skipping auth");
-        return next.run(req).await;
+impl Authenticator {
+    pub fn from_config(config: &AuthConfig) -> Result<Self, Box<dyn Error>> {
+        match config.mode {
+            AuthMode::Jwt => {
+                let (Some(jwks_file), Some(issuer), Some(audience)) =
+                    (&config.jwks_file, &config.jwt_issuer, &config.jwt_audience)
+                else {
+                    return Err("jwt mode needs a JWKS file, issuer and audience".into());
+                };
+                let validator = JwtValidator::load(jwks_file, issuer.clone(), audience.clone())?;
+                Ok(Authenticator::Jwt(validator))
+            }
+            AuthMode::ApiKeys => match (&config.api_keys_file, &config.token) {
+                (Some(path), _) => Ok(Authenticator::ApiKeys(ApiKeyRegistry::load(path)?)),
+                (None, Some(token)) => Ok(Authenticator::ApiKeys(ApiKeyRegistry::single(
+                    "default", token,
+                ))),
+                (None, None) => Err("Set an API keys file or an auth token".into()),
+            },
+        }
     }
 
+    pub fn authorize(&self, token: &str, scope: Scope) -> Result<CallerIdentity, AuthRejection> {
+        match self {
+            Authenticator::ApiKeys(registry) => registry.authorize(token, scope),
+            Authenticator::Jwt(validator) => validator.authorize(token, scope),
+        }
+    }
+}
+
+/// Why a request was refused by the auth layer.
+#[derive(Debug)]
+pub enum AuthRejection {
+    Missing,
+    InvalidToken,
+    MissingScope(Scope),
+    RateLimited(Duration),
+}
+
+impl From<AuthRejection> for ApiError {
+    fn from(rejection: AuthRejection) -> Self {
+        match rejection {
+            AuthRejection::Missing => ApiError::Unauthorized {
+                message: "Missing Authorization header".to_string(),
+                code: "AUTH_MISSING",
+            },
+            AuthRejection::InvalidToken => ApiError::Unauthorized {
+                message: "Invalid auth token".to_string(),
+                code: "UNAUTHORIZED",
+            },
+            AuthRejection::MissingScope(scope) => {
+                ApiError::Forbidden(format!("Token lacks the {} scope", scope))
+            }
+            AuthRejection::RateLimited(retry_after) => ApiError::RateLimited {
+                message: "Rate limit exceeded".to_string(),
+                retry_after,
+            },
+        }
+    }
+}
+
+impl IntoResponse for AuthRejection {
+    fn into_response(self) -> Response {
+        ApiError::from(self).into_response()
+    }
+}
+
+/// State for `auth_middleware`: the authenticator and the scope its routes need.
+#[derive(Clone)]
+pub struct ScopedAuth {
+    auth: Arc<Authenticator>,
+    scope: Scope,
+}
+
+impl ScopedAuth {
+    pub fn new(auth: Arc<Authenticator>, scope: Scope) -> Self {
+        Self { auth, scope }
+    }
+}
+
+/// How a feed connection was admitted by `feed_auth_middleware`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedAuth {
+    Authenticated,
+    /// No token on the upgrade request; the client must send an auth message first
+    Pending,
+    /// Admitted without credentials because the feed is configured as public
+    Public,
+}
+
+#[derive(Deserialize)]
+struct FeedAuthQuery {
+    token: Option<String>,
+}
+
+pub async fn auth_middleware(
+    State(scoped): State<ScopedAuth>,
+    mut req: Request,
+    next: Next,
+) -> Response {
     let auth_header = match req.headers().get(header::AUTHORIZATION) {
         Some(header) => header.to_str().unwrap_or(""),
-        None => {
-            return (
-                StatusCode::UNAUTHORIZED,
-                Json(AuthError {
-                    error: "Missing Authorization header".to_string(),
-                    code: "AUTH_MISSING".to_string(),
-                }),
-            )
-                .into_response();
-        }
+        None => return AuthRejection::Missing.into_response(),
     };
 
-    if !is_valid_token(auth_header) {
-        return (
-            StatusCode::UNAUTHORIZED,
-            Json(AuthError {
-                error: "Invalid auth token".to_string(),
-                code: "UNAUTHORIZED".to_string(),
-            }),
-        )
-            .into_response();
-    }
+    let Some(token) = bearer_token(auth_header) else {
+        return AuthRejection::InvalidToken.into_response();
+    };
+
+    let identity = match scoped.auth.authorize(token, scoped.scope) {
+        Ok(identity) => identity,
+        Err(rejection) => return rejection.into_response(),
+    };
 
-    next.run(req).await
+    let span = tracing::info_span!("request", key_id = %identity.id);
+    req.extensions_mut().insert(identity);
+    next.run(req).instrument(span).await
 }
 
-fn is_valid_token(auth_header: &str) -> bool {
-    let expected_token = env::var("AUTH_TOKEN").unwrap_or_else(|_| "default_token".to_string());
+/// Authenticates WebSocket upgrades for the transaction feed.
+///
+/// A token may be passed as a `token` query parameter, through the
+/// `Sec-WebSocket-Protocol` header or in the `Authorization` header. Upgrades
+/// without any token are let through as `FeedAuth::Pending` so the client can
+/// authenticate with its first message, unless the feed is public.
+pub async fn feed_auth_middleware(
+    State(state): State<AppState>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let (feed_auth, key_id) = match feed_token(&req) {
+        Some(token) => match state.auth.authorize(&token, Scope::Feed) {
+            Ok(identity) => (FeedAuth::Authenticated, Some(identity.id)),
+            Err(rejection) => return rejection.into_response(),
+        },
+        None if state.public_feed => (FeedAuth::Public, None),
+        None => (FeedAuth::Pending, None),
+    };
+
+    let span = tracing::info_span!("feed", key_id = key_id.as_deref().unwrap_or("anonymous"));
+    req.extensions_mut().insert(feed_auth);
+    next.run(req).instrument(span).await
+}
+
+fn feed_token(req: &Request) -> Option<String> {
+    let query = Query::<FeedAuthQuery>::try_from_uri(req.uri()).ok();
+    if let Some(token) = query.and_then(|q| q.0.token) {
+        return Some(token);
+    }
+
+    if let Some(protocols) = req.headers().get(header::SEC_WEBSOCKET_PROTOCOL) {
+        let protocols = protocols.to_str().unwrap_or("");
+        let mut parts = protocols.split(',').map(str::trim);
+        if parts.next() == Some(BEARER_PROTOCOL) {
+            return parts.next().map(str::to_string);
+        }
+    }
+
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(bearer_token)
+        .map(str::to_string)
+}
 
-    auth_header
-        .strip_prefix("Bearer ")
-        .map(|token| token.trim() == expected_token)
-        .unwrap_or(false)
+fn bearer_token(auth_header: &str) -> Option<&str> {
+    auth_header.strip_prefix("Bearer ").map(str::trim)
 }
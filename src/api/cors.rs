@@ -1,9 +1,19 @@
 use http::HeaderValue;
-use tower_http::cors::{Any, CorsLayer};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+pub fn create_cors_middleware(origins: &[String]) -> CorsLayer {
+    let allow_origin = if origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+        )
+    };
 
-pub fn create_cors_middleware() -> CorsLayer {
     CorsLayer::new()
-        .allow_origin(HeaderValue::from_static("http://localhost:3000"))
+        .allow_origin(allow_origin)
         .allow_methods([axum::http::Method::GET, axum::http::Method::POST])
         .allow_headers(Any)
 }
@@ -0,0 +1,210 @@
+use crate::services::parser::ParseError;
+use crate::services::queue_service::QueueError;
+use axum::{
+    extract::rejection::JsonRejection,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A single field-level problem in a rejected request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Every error the HTTP API returns. Each variant maps to one status code and
+/// a stable machine-readable `code`.
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    /// The body isn't valid JSON or doesn't have the expected shape
+    #[error("{0}")]
+    InvalidRequest(String),
+    /// The body is over the size limit
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    /// A field couldn't be decoded (bad hex, wrong length, ...)
+    #[error("{message}")]
+    Parse {
+        message: String,
+        details: Vec<FieldError>,
+    },
+    /// The transaction decoded but breaks a sequencer rule
+    #[error("{message}")]
+    Validation {
+        message: String,
+        details: Vec<FieldError>,
+    },
+    #[error("{message}")]
+    Unauthorized { message: String, code: &'static str },
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    /// The request conflicts with how the sequencer is configured or running
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{message}")]
+    RateLimited {
+        message: String,
+        retry_after: Duration,
+    },
+    #[error("Transaction queue is full, try again shortly")]
+    QueueFull,
+    #[error("Transaction queue is unavailable")]
+    QueueUnavailable,
+    #[error("Transaction admission is paused")]
+    AdmissionPaused,
+    #[error("Sequencer is shutting down")]
+    ShuttingDown,
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// Attached to every error response so outer middleware (metrics) can see the
+/// code without parsing the body.
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
+/// The JSON body of every error response, and of each rejected item in a
+/// `POST /send_transactions` response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: String,
+    pub code: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<FieldError>,
+}
+
+impl From<ApiError> for ErrorBody {
+    fn from(e: ApiError) -> Self {
+        let code = e.code().to_string();
+        let error = e.to_string();
+        let details = match e {
+            ApiError::Parse { details, .. } | ApiError::Validation { details, .. } => details,
+            _ => Vec::new(),
+        };
+        Self {
+            error,
+            code,
+            details,
+        }
+    }
+}
+
+impl ApiError {
+    pub fn validation(field: &str, message: impl Into<String>) -> Self {
+        let message = message.into();
+        ApiError::Validation {
+            details: vec![FieldError::new(field, message.clone())],
+            message,
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_) | ApiError::Parse { .. } => StatusCode::BAD_REQUEST,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::QueueFull
+            | ApiError::QueueUnavailable
+            | ApiError::AdmissionPaused
+            | ApiError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::InvalidRequest(_) => "INVALID_REQUEST",
+            ApiError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            ApiError::Parse { .. } => "PARSE_ERROR",
+            ApiError::Validation { .. } => "VALIDATION_ERROR",
+            ApiError::Unauthorized { code, .. } => code,
+            ApiError::Forbidden(_) => "FORBIDDEN",
+            ApiError::NotFound(_) => "NOT_FOUND",
+            ApiError::Conflict(_) => "CONFLICT",
+            ApiError::RateLimited { .. } => "RATE_LIMITED",
+            ApiError::QueueFull => "QUEUE_FULL",
+            ApiError::QueueUnavailable => "QUEUE_UNAVAILABLE",
+            ApiError::AdmissionPaused => "ADMISSION_PAUSED",
+            ApiError::ShuttingDown => "SHUTTING_DOWN",
+            ApiError::Internal(_) => "INTERNAL_ERROR",
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        let code = self.code();
+        let retry_after = match &self {
+            ApiError::RateLimited { retry_after, .. } => Some(retry_after_secs(*retry_after)),
+            ApiError::QueueFull => Some(1),
+            _ => None,
+        };
+
+        let mut response = (status, Json(ErrorBody::from(self))).into_response();
+        response.extensions_mut().insert(ErrorCode(code));
+
+        if let Some(secs) = retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, secs.into());
+        }
+        response
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return ApiError::PayloadTooLarge(rejection.body_text());
+        }
+        ApiError::InvalidRequest(rejection.body_text())
+    }
+}
+
+impl From<ParseError> for ApiError {
+    fn from(e: ParseError) -> Self {
+        ApiError::Parse {
+            details: vec![FieldError::new(e.field, e.message.clone())],
+            message: e.to_string(),
+        }
+    }
+}
+
+impl From<QueueError> for ApiError {
+    fn from(e: QueueError) -> Self {
+        match e {
+            QueueError::Full => ApiError::QueueFull,
+            QueueError::Closed => ApiError::QueueUnavailable,
+            QueueError::Paused => ApiError::AdmissionPaused,
+            QueueError::ShuttingDown => ApiError::ShuttingDown,
+            QueueError::Unsequenced => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+/// Whole seconds for a `Retry-After` header, rounded up.
+pub fn retry_after_secs(wait: Duration) -> u64 {
+    wait.as_secs()
+        .saturating_add(u64::from(wait.subsec_nanos() > 0))
+}
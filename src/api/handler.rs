@@ -1,117 +1,465 @@
-use crate::models::L2Transaction;
+use crate::api::api_keys::Scope;
+use crate::api::auth::{FeedAuth, BEARER_PROTOCOL};
+use crate::api::error::{ApiError, ErrorBody};
+use crate::api::metrics::SubmissionsCounted;
+use crate::api::rate_limit::SkippedSenders;
+use crate::config::SubmissionMode;
+use crate::models::{Confirmation, L2Transaction};
+use crate::services::feed::{ClientMessage, Subscription};
 use crate::services::parser::{parse_raw_transaction, RawTransactionData};
+use crate::services::queue_service::{QueueError, Sequenced};
 use crate::types::AppState;
-use alloy::primitives::U256;
+use alloy::primitives::{B256, U256};
 use alloy::providers::Provider;
-use axum::extract::ws::{Message, WebSocket};
+use alloy::signers::local::PrivateKeySigner;
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
 use axum::{
-    extract::{State, WebSocketUpgrade},
-    response::IntoResponse,
+    extract::{rejection::JsonRejection, Extension, State, WebSocketUpgrade},
+    http::StatusCode,
+    response::{IntoResponse, Response},
     Json,
 };
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, field, info, info_span, warn, Instrument, Span};
+
+/// How long an unauthenticated feed client has to send its auth message
+const FEED_AUTH_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Deserialize)]
 pub struct TransactionRequest {
     raw_tx: RawTransactionData,
 }
 
-#[derive(Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionResponse {
-    status: String,
+    pub status: String,
+    /// Set when the sequencer has a signing key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<Confirmation>,
+}
+
+#[derive(Deserialize)]
+pub struct TransactionsRequest {
+    /// Decoded one by one so a malformed item only rejects itself
+    transactions: Vec<serde_json::Value>,
+    /// Overrides `submission.mode` for this request
+    mode: Option<SubmissionMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemStatus {
+    Queued,
+    Rejected,
+    /// Valid, but not queued because another item in an atomic request
+    /// was rejected
+    Skipped,
+}
+
+/// The outcome for one transaction of a `POST /send_transactions` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ItemResult {
+    pub status: ItemStatus,
+    /// Missing when the transaction couldn't be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<B256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorBody>,
+    /// Set on queued items when the sequencer has a signing key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confirmation: Option<Confirmation>,
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    error: String,
-    code: String,
+impl ItemResult {
+    fn rejected(tx_hash: Option<B256>, error: ApiError) -> Self {
+        Self {
+            status: ItemStatus::Rejected,
+            tx_hash,
+            error: Some(error.into()),
+            confirmation: None,
+        }
+    }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionsResponse {
+    pub mode: SubmissionMode,
+    pub queued: usize,
+    /// One per submitted transaction, in request order
+    pub results: Vec<ItemResult>,
+}
+
+#[tracing::instrument(name = "send_transaction", skip_all, fields(tx_hash, from))]
 pub async fn send_transaction(
     State(state): State<AppState>,
-    Json(payload): Json<TransactionRequest>,
-) -> Result<Json<TransactionResponse>, Json<ErrorResponse>> {
-    println!("Received transaction request");
-
-    let transaction = parse_raw_transaction(&payload.raw_tx).map_err(|e| {
-        println!("Parse error: {}", e);
-        Json(ErrorResponse {
-            error: e.to_string(),
-            code: "PARSE_ERROR".to_string(),
-        })
+    payload: Result<Json<TransactionRequest>, JsonRejection>,
+) -> Result<Json<TransactionResponse>, ApiError> {
+    let Json(payload) = payload?;
+
+    let mut transaction = parse_transaction(&payload.raw_tx)?;
+    check_l1_block_number(&state, &mut transaction)?;
+    check_transaction(&state, &transaction).await?;
+
+    let admitted = state.signer.is_some().then(|| transaction.clone());
+    let sequenced = state.queue.submit_transaction(transaction).map_err(|e| {
+        warn!(error = %e, "failed to queue transaction");
+        ApiError::from(e)
     })?;
 
-    println!("Transaction parsed successfully");
+    info!("transaction queued");
+
+    let confirmation = match (&state.signer, admitted) {
+        (Some(signer), Some(tx)) => {
+            let sequenced = sequenced.await.map_err(|_| QueueError::Closed)??;
+            Some(confirm(signer, &tx, sequenced)?)
+        }
+        _ => None,
+    };
+    let response = Json(TransactionResponse {
+        status: "queued".to_string(),
+        confirmation,
+    });
+
+    Ok(response)
+}
+
+/// Signs the sequencer's promise about where `tx` was put.
+fn confirm(
+    signer: &PrivateKeySigner,
+    tx: &L2Transaction,
+    sequenced: Sequenced,
+) -> Result<Confirmation, ApiError> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    Confirmation::new(
+        tx,
+        sequenced.sequence_number,
+        sequenced.target_block,
+        timestamp,
+    )
+    .sign(signer)
+    .map_err(|e| ApiError::Internal(format!("Failed to sign confirmation: {}", e)))
+}
+
+/// `POST /send_transactions`: validates each transaction on its own, then
+/// queues the valid ones (individual mode) or all of them only if every one
+/// is valid (atomic mode). Responds 422 when rejections left nothing queued.
+#[tracing::instrument(name = "send_transactions", skip_all, fields(count, mode))]
+pub async fn send_transactions(
+    State(state): State<AppState>,
+    payload: Result<Json<TransactionsRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(payload) = payload?;
+    let mode = payload.mode.unwrap_or(state.submission.mode);
+    let span = Span::current();
+    span.record("count", payload.transactions.len());
+    span.record("mode", field::debug(mode));
+
+    let max = state.submission.max_transactions_per_request;
+    if payload.transactions.is_empty() || payload.transactions.len() > max {
+        return Err(ApiError::validation(
+            "transactions",
+            format!("Between 1 and {} transactions are allowed per request", max),
+        ));
+    }
 
-    validate_gas_limit(&transaction).map_err(|e| {
-        println!("Gas limit error: {}", e);
-        Json(ErrorResponse {
-            error: e.to_string(),
-            code: "VALIDATION_ERROR".to_string(),
+    // Validation is independent per item, so it runs concurrently; results
+    // stay in request order
+    let items: Vec<_> = stream::iter(payload.transactions.into_iter().enumerate())
+        .map(|(index, raw_tx)| {
+            let span = info_span!(
+                "validate_transaction",
+                index,
+                tx_hash = field::Empty,
+                from = field::Empty
+            );
+            validate_item(&state, raw_tx).instrument(span)
         })
-    })?;
-    validate_gas_price(&transaction).map_err(|e| {
-        println!("Gas price error: {}", e);
-        Json(ErrorResponse {
-            error: e.to_string(),
-            code: "VALIDATION_ERROR".to_string(),
+        .buffered(state.submission.validation_concurrency)
+        .collect()
+        .await;
+
+    let rejected = items.iter().filter(|item| item.is_err()).count();
+    let queue_valid = mode == SubmissionMode::Individual || rejected == 0;
+    let transactions: Vec<L2Transaction> = if queue_valid {
+        items
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .cloned()
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let queued = transactions.len();
+
+    let mut confirmations = Vec::new();
+    if !transactions.is_empty() {
+        let admitted = state.signer.is_some().then(|| transactions.clone());
+        let sequenced = state.queue.submit_transactions(transactions).map_err(|e| {
+            warn!(error = %e, "failed to queue transactions");
+            ApiError::from(e)
+        })?;
+        if let (Some(signer), Some(txs)) = (&state.signer, admitted) {
+            let sequenced = sequenced.await.map_err(|_| QueueError::Closed)??;
+            for (tx, sequenced) in txs.iter().zip(sequenced) {
+                confirmations.push(confirm(signer, tx, sequenced)?);
+            }
+        }
+    }
+    info!(queued, rejected, "transactions submitted");
+
+    let accepted_status = if queue_valid {
+        ItemStatus::Queued
+    } else {
+        ItemStatus::Skipped
+    };
+    // Held back by the atomic rejection, so they don't use up sender quota
+    let skipped = SkippedSenders(if queue_valid {
+        Vec::new()
+    } else {
+        items
+            .iter()
+            .filter_map(|item| item.as_ref().ok())
+            .map(|tx| tx.from)
+            .collect()
+    });
+    let mut confirmations = confirmations.into_iter();
+    let results: Vec<ItemResult> = items
+        .into_iter()
+        .map(|item| match item {
+            Ok(tx) => ItemResult {
+                status: accepted_status,
+                tx_hash: Some(tx.hash()),
+                error: None,
+                confirmation: confirmations.next(),
+            },
+            Err(rejected) => rejected,
         })
-    })?;
-    validate_nonce(state.provider, &transaction)
+        .collect();
+
+    for result in &results {
+        let (outcome, code) = match (&result.error, result.status) {
+            (Some(error), _) => ("rejected", error.code.as_str()),
+            (None, ItemStatus::Skipped) => ("rejected", "BATCH_REJECTED"),
+            (None, _) => ("accepted", "OK"),
+        };
+        state
+            .metrics
+            .submissions
+            .with_label_values(&[outcome, code])
+            .inc();
+    }
+
+    let status = if queued == 0 {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+    let body = TransactionsResponse {
+        mode,
+        queued,
+        results,
+    };
+    Ok((
+        status,
+        Extension(SubmissionsCounted),
+        Extension(skipped),
+        Json(body),
+    )
+        .into_response())
+}
+
+/// Parses one item of a `POST /send_transactions` request and checks it.
+async fn validate_item(
+    state: &AppState,
+    raw_tx: serde_json::Value,
+) -> Result<L2Transaction, ItemResult> {
+    let raw_tx: RawTransactionData = serde_json::from_value(raw_tx)
+        .map_err(|e| ItemResult::rejected(None, ApiError::InvalidRequest(e.to_string())))?;
+    let mut transaction = parse_transaction(&raw_tx).map_err(|e| ItemResult::rejected(None, e))?;
+    check_l1_block_number(state, &mut transaction)
+        .map_err(|e| ItemResult::rejected(Some(transaction.hash()), e))?;
+    check_transaction(state, &transaction)
         .await
+        .map_err(|e| ItemResult::rejected(Some(transaction.hash()), e))?;
+    Ok(transaction)
+}
+
+/// Decodes a raw transaction, recording its hash and sender on the current
+/// span.
+fn parse_transaction(raw_tx: &RawTransactionData) -> Result<L2Transaction, ApiError> {
+    let transaction = parse_raw_transaction(raw_tx).map_err(|e| {
+        debug!(field = e.field, error = %e, "rejected unparseable transaction");
+        ApiError::from(e)
+    })?;
+
+    let span = Span::current();
+    span.record("tx_hash", field::display(transaction.hash()));
+    span.record("from", field::display(transaction.from));
+    Ok(transaction)
+}
+
+/// Keeps `l1_block_number` inside the sequencing window behind the L1 head,
+/// clamping or rejecting it as configured. Unchecked until the head is known.
+fn check_l1_block_number(
+    state: &AppState,
+    transaction: &mut L2Transaction,
+) -> Result<(), ApiError> {
+    let Some(head) = state.l1_head.sequencing_head() else {
+        return Ok(());
+    };
+    let admitted = state
+        .sequencing_window
+        .admit(transaction.l1_block_number, head)
         .map_err(|e| {
-            println!("Nonce validation error: {}", e);
-            Json(ErrorResponse {
-                error: e.to_string(),
-                code: "VALIDATION_ERROR".to_string(),
-            })
+            debug!(error = %e, "rejected transaction l1_block_number");
+            ApiError::validation("l1_block_number", e.to_string())
         })?;
-    // validate_addresses(&transaction)?;
-    // validate_contract_creation(&transaction)?;
+    if admitted != transaction.l1_block_number {
+        debug!(
+            requested = transaction.l1_block_number,
+            admitted, "clamped transaction l1_block_number"
+        );
+        state.metrics.l1_block_clamped.inc();
+        transaction.l1_block_number = admitted;
+        Span::current().record("tx_hash", field::display(transaction.hash()));
+    }
+    Ok(())
+}
 
-    state
-        .queue
-        .submit_transaction(transaction)
+/// The sequencer's admission rules for a decoded transaction.
+async fn check_transaction(state: &AppState, transaction: &L2Transaction) -> Result<(), ApiError> {
+    validate_gas_limit(transaction).map_err(|e| {
+        debug!(error = e, "rejected transaction gas limit");
+        ApiError::validation("gas_limit", e)
+    })?;
+    validate_gas_price(transaction).map_err(|e| {
+        debug!(error = e, "rejected transaction gas price");
+        ApiError::validation("gas_price", e)
+    })?;
+    validate_nonce(state.provider.clone(), transaction)
         .await
         .map_err(|e| {
-            println!("Queue error: {}", e);
-            Json(ErrorResponse {
-                error: e.to_string(),
-                code: "QUEUE_ERROR".to_string(),
-            })
+            debug!(error = %e, "rejected transaction nonce");
+            ApiError::validation("nonce", e.to_string())
         })?;
-
-    println!("Transaction queued successfully");
-
-    let response = Json(TransactionResponse {
-        status: "queued".to_string(),
-    });
-
-    Ok(response)
+    // validate_addresses(&transaction)?;
+    // validate_contract_creation(&transaction)?;
+    Ok(())
 }
 
 pub async fn transaction_feed(
     State(state): State<AppState>,
+    Extension(feed_auth): Extension<FeedAuth>,
     ws: WebSocketUpgrade,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_websocket(socket, state))
+    ws.protocols([BEARER_PROTOCOL])
+        .on_upgrade(move |socket| handle_websocket(socket, state, feed_auth))
 }
 
-async fn handle_websocket(mut socket: WebSocket, state: AppState) {
+async fn handle_websocket(mut socket: WebSocket, state: AppState, feed_auth: FeedAuth) {
+    if feed_auth == FeedAuth::Pending && !authenticate_first_message(&mut socket, &state).await {
+        return;
+    }
+
+    let _subscriber = state.metrics.feed_subscriber();
     let mut feed_rx = state.queue.subscribe();
+    let mut subscription = Subscription::default();
+    let mut shutdown = state.shutdown.clone();
+
+    loop {
+        tokio::select! {
+            _ = async { shutdown.wait_for(|shutting_down| *shutting_down).await.is_ok() } => {
+                let _ = socket
+                    .send(Message::Close(Some(CloseFrame {
+                        code: close_code::AWAY,
+                        reason: "Sequencer shutting down".into(),
+                    })))
+                    .await;
+                break;
+            }
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
 
-    // Stream transactions to the client
-    while let Ok(transaction) = feed_rx.recv().await {
-        let serialized =
-            serde_json::to_string(&transaction).expect("Failed to serialize transaction"); // Ensure L2Transaction is serializable
-        if socket.send(Message::Text(serialized.into())).await.is_err() {
-            // Client disconnected
-            break;
+                let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe(requested)) => {
+                        subscription = requested;
+                        json!({ "type": "subscribed", "streams": subscription.streams })
+                    }
+                    Ok(ClientMessage::Auth { .. }) => {
+                        json!({ "type": "error", "error": "Already authenticated" })
+                    }
+                    Err(e) => json!({ "type": "error", "error": e.to_string() }),
+                };
+                if socket.send(Message::Text(reply.to_string().into())).await.is_err() {
+                    break;
+                }
+            }
+            event = feed_rx.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    // Slow clients skip what they missed rather than being dropped
+                    Err(RecvError::Lagged(skipped)) => {
+                        debug!(skipped, "feed subscriber lagged");
+                        state.metrics.feed_lagged_events.inc_by(skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if !subscription.matches(&event) {
+                    continue;
+                }
+
+                let serialized =
+                    serde_json::to_string(&event).expect("Failed to serialize feed event");
+                if socket.send(Message::Text(serialized.into())).await.is_err() {
+                    // Client disconnected
+                    break;
+                }
+            }
         }
     }
 }
 
+/// Waits for `{"type":"auth","token":...}` as the first message, closing the
+/// socket with a policy violation if it doesn't arrive or the token is invalid.
+async fn authenticate_first_message(socket: &mut WebSocket, state: &AppState) -> bool {
+    let message = tokio::time::timeout(FEED_AUTH_TIMEOUT, socket.recv()).await;
+
+    let authenticated = match message {
+        Ok(Some(Ok(Message::Text(text)))) => matches!(
+            serde_json::from_str::<ClientMessage>(&text),
+            Ok(ClientMessage::Auth { token })
+                if state.auth.authorize(&token, Scope::Feed).is_ok()
+        ),
+        _ => false,
+    };
+
+    if authenticated {
+        let reply = json!({ "type": "authenticated" }).to_string();
+        return socket.send(Message::Text(reply.into())).await.is_ok();
+    }
+
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code: close_code::POLICY,
+            reason: "Authentication required".into(),
+        })))
+        .await;
+    false
+}
+
 fn validate_gas_limit(tx: &L2Transaction) -> Result<(), &'static str> {
     if tx.gas_limit < 21000 {
         return Err("Gas limit too low");
@@ -127,10 +475,10 @@ fn validate_gas_price(tx: &L2Transaction) -> Result<(), &'static str> {
 }
 
 async fn validate_nonce<T: Provider>(
-    provider: T,
+    _provider: T,
     tx: &L2Transaction,
 ) -> Result<(), Box<dyn Error>> {
-    let sender = tx.from;
+    let _sender = tx.from;
 
     // ignore for load test
 
@@ -146,3 +494,190 @@ async fn validate_nonce<T: Provider>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::api_keys::ApiKeyRegistry;
+    use crate::api::auth::{feed_auth_middleware, Authenticator};
+    use crate::config::Config;
+    use crate::services::batch_store::BatchStore;
+    use crate::services::l1_head::L1Head;
+    use crate::services::metrics::Metrics;
+    use crate::services::outbox::WithdrawalIndex;
+    use crate::services::outputs::OutputStore;
+    use crate::services::queue_service::QueueHandle;
+    use crate::services::status::SequencerStatus;
+    use alloy::providers::ProviderBuilder;
+    use axum::http::header::{AUTHORIZATION, SEC_WEBSOCKET_PROTOCOL};
+    use axum::{middleware, routing::get, Router};
+    use futures::SinkExt;
+    use std::sync::Arc;
+    use tokio::net::TcpStream;
+    use tokio::sync::{broadcast, mpsc, watch};
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::handshake::client::Request as ClientRequest;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::{self, Message as Frame};
+    use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+    const TOKEN: &str = "feed-token";
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    #[derive(Debug, PartialEq)]
+    enum Reply {
+        /// A text reply's `type`
+        Message(String),
+        Closed(CloseCode),
+    }
+
+    /// Serves the feed route behind `feed_auth_middleware`, as `main` does,
+    /// and returns its URL. Dropping the sender shuts the feed down.
+    async fn serve_feed(name: &str, public_feed: bool) -> (String, watch::Sender<bool>) {
+        let config = Config::default();
+        let data_dir = std::env::temp_dir().join(format!("feed-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let store = Arc::new(BatchStore::open(&data_dir).unwrap());
+        let (command_tx, _) = mpsc::channel(1);
+        let (feed_tx, _) = broadcast::channel(16);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let state = AppState {
+            queue: QueueHandle::new(command_tx, feed_tx),
+            // Never contacted by the feed
+            provider: ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap()),
+            auth: Arc::new(Authenticator::ApiKeys(ApiKeyRegistry::single(
+                "feed", TOKEN,
+            ))),
+            public_feed,
+            submission: config.submission.clone(),
+            signer: None,
+            l1_head: Arc::new(L1Head::new()),
+            sequencing_window: config.l1.sequencing_window(),
+            metrics: Arc::new(Metrics::new()),
+            status: Arc::new(SequencerStatus::new(
+                data_dir.clone(),
+                Duration::from_secs(60),
+            )),
+            withdrawals: Arc::new(WithdrawalIndex::load(store).unwrap()),
+            outputs: Arc::new(OutputStore::open(&data_dir).unwrap()),
+            l1_submitter: None,
+            shutdown: shutdown_rx,
+        };
+        let app = Router::new()
+            .route("/transaction_feed", get(transaction_feed))
+            .layer(middleware::from_fn_with_state(
+                state.clone(),
+                feed_auth_middleware,
+            ))
+            .with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/transaction_feed", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, shutdown_tx)
+    }
+
+    fn request(
+        url: &str,
+        header: impl axum::http::header::IntoHeaderName,
+        value: &str,
+    ) -> ClientRequest {
+        let mut request = url.into_client_request().unwrap();
+        request.headers_mut().insert(header, value.parse().unwrap());
+        request
+    }
+
+    /// Sends `message` and waits for the server's answer.
+    async fn reply(client: &mut Client, message: serde_json::Value) -> Reply {
+        client
+            .send(Frame::Text(message.to_string().into()))
+            .await
+            .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(5), client.next())
+            .await
+            .expect("no reply from the feed");
+        match frame {
+            Some(Ok(Frame::Text(text))) => {
+                let reply: serde_json::Value = serde_json::from_str(&text).unwrap();
+                Reply::Message(reply["type"].as_str().unwrap().to_string())
+            }
+            Some(Ok(Frame::Close(Some(frame)))) => Reply::Closed(frame.code),
+            other => panic!("unexpected frame: {:?}", other),
+        }
+    }
+
+    fn subscribed() -> Reply {
+        Reply::Message("subscribed".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_feed_token_on_upgrade() {
+        let (url, _shutdown) = serve_feed("upgrade", false).await;
+        let subscribe = json!({ "type": "subscribe" });
+
+        for valid in [
+            request(&url, AUTHORIZATION, &format!("Bearer {}", TOKEN)),
+            request(&url, SEC_WEBSOCKET_PROTOCOL, &format!("bearer, {}", TOKEN)),
+            format!("{}?token={}", url, TOKEN)
+                .into_client_request()
+                .unwrap(),
+        ] {
+            let (mut client, _) = connect_async(valid).await.unwrap();
+            assert_eq!(reply(&mut client, subscribe.clone()).await, subscribed());
+        }
+
+        // A wrong token is refused before the upgrade
+        for wrong in [
+            request(&url, AUTHORIZATION, "Bearer wrong"),
+            request(&url, SEC_WEBSOCKET_PROTOCOL, "bearer, wrong"),
+            format!("{}?token=wrong", url)
+                .into_client_request()
+                .unwrap(),
+        ] {
+            match connect_async(wrong).await {
+                Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+                other => panic!("expected a 401, got {:?}", other.map(|(_, r)| r)),
+            }
+        }
+
+        // Without one, nothing is served until the first message authenticates
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        assert_eq!(
+            reply(&mut client, subscribe.clone()).await,
+            Reply::Closed(CloseCode::Policy)
+        );
+
+        // unless the feed is public
+        let (url, _shutdown) = serve_feed("public", true).await;
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        assert_eq!(reply(&mut client, subscribe).await, subscribed());
+    }
+
+    #[tokio::test]
+    async fn test_feed_first_message_auth() {
+        let (url, _shutdown) = serve_feed("first-message", false).await;
+
+        let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+        assert_eq!(
+            reply(&mut client, json!({ "type": "auth", "token": TOKEN })).await,
+            Reply::Message("authenticated".to_string())
+        );
+        assert_eq!(
+            reply(&mut client, json!({ "type": "subscribe" })).await,
+            subscribed()
+        );
+
+        for refused in [
+            json!({ "type": "auth", "token": "wrong" }),
+            json!({ "type": "auth" }),
+        ] {
+            let (mut client, _) = connect_async(url.as_str()).await.unwrap();
+            assert_eq!(
+                reply(&mut client, refused).await,
+                Reply::Closed(CloseCode::Policy)
+            );
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use crate::services::status::Probes;
+use crate::types::AppState;
+use alloy::providers::Provider;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// How long `/readyz` waits on the RPC provider
+const RPC_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long `/readyz` reuses its RPC and storage probes, so polling it can't
+/// hammer the provider or the disk
+const PROBE_TTL: Duration = Duration::from_secs(2);
+
+#[derive(Serialize)]
+pub struct Check {
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Check {
+    fn from_result(result: Result<(), String>) -> Self {
+        match result {
+            Ok(()) => Check {
+                ok: true,
+                error: None,
+            },
+            Err(error) => Check {
+                ok: false,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Readiness {
+    ready: bool,
+    queue_processor: Check,
+    rpc: Check,
+    storage: Check,
+    l1_submitter: Check,
+}
+
+#[derive(Serialize)]
+pub struct StatusResponse {
+    head_block: u64,
+    pending_transactions: usize,
+    last_batch_index: Option<u64>,
+    uptime_secs: u64,
+    /// Seconds since a batch was last posted to L1
+    last_l1_post_secs_ago: Option<u64>,
+    admission_paused: bool,
+    /// `None` when L1 posting isn't enabled
+    l1_posting_paused: Option<bool>,
+}
+
+/// Liveness: the process is up and serving requests.
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness: every dependency needed to accept and batch transactions works.
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let queue_processor = Check::from_result(if state.queue.is_processor_alive() {
+        Ok(())
+    } else {
+        Err("Queue processor has stopped".to_string())
+    });
+
+    let probes = state
+        .status
+        .probes(PROBE_TTL, async {
+            let (rpc, storage) = tokio::join!(check_rpc(&state), check_storage(&state));
+            Probes { rpc, storage }
+        })
+        .await;
+    let rpc = Check::from_result(probes.rpc);
+    let storage = Check::from_result(probes.storage);
+
+    let l1_submitter = Check::from_result(match state.status.l1_stalled_for() {
+        Some(stalled) => Err(format!("Stuck on one batch for {}s", stalled.as_secs())),
+        None => Ok(()),
+    });
+
+    let ready = queue_processor.ok && rpc.ok && storage.ok && l1_submitter.ok;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(Readiness {
+            ready,
+            queue_processor,
+            rpc,
+            storage,
+            l1_submitter,
+        }),
+    )
+}
+
+pub async fn status(State(state): State<AppState>) -> Json<StatusResponse> {
+    let status = &state.status;
+    Json(StatusResponse {
+        head_block: status.head_block(),
+        pending_transactions: status.pending_transactions(),
+        last_batch_index: status.last_batch_index(),
+        uptime_secs: status.uptime().as_secs(),
+        last_l1_post_secs_ago: status.last_l1_post().map(|t| t.elapsed().as_secs()),
+        admission_paused: state.queue.is_admission_paused(),
+        l1_posting_paused: state.l1_submitter.as_ref().map(|s| s.is_paused()),
+    })
+}
+
+async fn check_rpc(state: &AppState) -> Result<(), String> {
+    match tokio::time::timeout(RPC_CHECK_TIMEOUT, state.provider.get_block_number()).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err("RPC provider timed out".to_string()),
+    }
+}
+
+async fn check_storage(state: &AppState) -> Result<(), String> {
+    let probe = state.status.data_dir().join(".readyz");
+    async {
+        tokio::fs::write(&probe, b"ok").await?;
+        tokio::fs::remove_file(&probe).await
+    }
+    .await
+    .map_err(|e| format!("Data directory not writable: {}", e))
+}
@@ -0,0 +1,56 @@
+use crate::api::error::ErrorCode;
+use crate::services::metrics::Metrics;
+use crate::types::AppState;
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Prometheus scrape endpoint.
+pub async fn prometheus_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+/// Set on a response whose handler already counted each submitted
+/// transaction, so the request isn't counted again as a whole.
+#[derive(Debug, Clone, Copy)]
+pub struct SubmissionsCounted;
+
+/// Counts submissions by outcome and error code and times admission. Sits
+/// outside the auth and rate limit layers so their rejections are counted too.
+pub async fn submission_metrics_middleware(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let response = next.run(req).await;
+    metrics
+        .admission_latency
+        .observe(start.elapsed().as_secs_f64());
+
+    if response.extensions().get::<SubmissionsCounted>().is_some() {
+        return response;
+    }
+
+    let status = response.status();
+    let (outcome, code) = match response.extensions().get::<ErrorCode>() {
+        Some(ErrorCode(code)) => ("rejected", *code),
+        None if status.is_success() => ("accepted", "OK"),
+        // Rejections produced by axum itself rather than `ApiError`
+        None => ("rejected", status.as_str()),
+    };
+    metrics
+        .submissions
+        .with_label_values(&[outcome, code])
+        .inc();
+
+    response
+}
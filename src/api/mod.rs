@@ -1,3 +1,12 @@
+pub mod admin;
+pub mod api_keys;
 pub mod auth;
 pub mod cors;
+pub mod error;
 pub mod handler;
+pub mod health;
+pub mod jwt;
+pub mod metrics;
+pub mod outbox;
+pub mod outputs;
+pub mod rate_limit;
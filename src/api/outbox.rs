@@ -0,0 +1,22 @@
+use crate::api::error::ApiError;
+use crate::services::outbox::WithdrawalProof;
+use crate::types::AppState;
+use alloy::primitives::B256;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+/// `GET /withdrawal/{hash}/proof`: the Merkle proof for a withdrawal, by
+/// its hash or its L2 transaction's hash.
+pub async fn withdrawal_proof(
+    State(state): State<AppState>,
+    Path(hash): Path<B256>,
+) -> Result<Json<WithdrawalProof>, ApiError> {
+    state
+        .withdrawals
+        .proof(hash)
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No withdrawal {} in a built batch", hash)))
+}
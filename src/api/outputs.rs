@@ -0,0 +1,21 @@
+use crate::api::error::ApiError;
+use crate::services::outputs::Output;
+use crate::types::AppState;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+/// `GET /output/{block}`: the output root computed at an L2 block, with its
+/// L1 proposal and whether that's finalized.
+pub async fn get_output(
+    State(state): State<AppState>,
+    Path(block): Path<u64>,
+) -> Result<Json<Output>, ApiError> {
+    state
+        .outputs
+        .load(block)
+        .map_err(|e| ApiError::Internal(e.to_string()))?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("No output at L2 block {}", block)))
+}
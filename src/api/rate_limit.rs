@@ -0,0 +1,455 @@
+use crate::api::error::ApiError;
+use alloy::primitives::Address;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::LengthLimitError;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Matches axum's default body limit for `Json` extractors
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Most keys a limiter tracks; idle buckets go first, then the least
+/// recently used
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimit {
+    pub requests_per_second: f64,
+    /// Requests allowed in a burst; defaults to one second's worth
+    pub burst: Option<f64>,
+}
+
+impl RateLimit {
+    /// A finite positive rate, and a finite burst of at least one request.
+    pub fn is_valid(&self) -> bool {
+        self.requests_per_second.is_finite()
+            && self.requests_per_second > 0.0
+            && self.burst.is_none_or(|b| b.is_finite() && b >= 1.0)
+    }
+}
+
+/// Classic token bucket: refills continuously, each request takes one token.
+#[derive(Debug)]
+pub struct TokenBucket {
+    capacity: f64,
+    refill_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    pub fn new(limit: RateLimit) -> Self {
+        let capacity = limit.burst.unwrap_or(limit.requests_per_second).max(1.0);
+        Self {
+            capacity,
+            refill_per_sec: limit.requests_per_second,
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns how long until one is available.
+    pub fn try_acquire(&mut self) -> Result<(), Duration> {
+        self.try_acquire_at(Instant::now())
+    }
+
+    /// Gives back a token taken for a request that was never served.
+    pub fn refund(&mut self) {
+        self.tokens = (self.tokens + 1.0).min(self.capacity);
+    }
+
+    fn is_full_at(&self, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens + elapsed * self.refill_per_sec >= self.capacity
+    }
+
+    fn try_acquire_at(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if self.refill_per_sec <= 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(
+            Duration::try_from_secs_f64((1.0 - self.tokens) / self.refill_per_sec)
+                .unwrap_or(Duration::MAX),
+        )
+    }
+}
+
+/// One token bucket per key, e.g. per client IP or per sender address.
+pub struct KeyedRateLimiter<K> {
+    limit: RateLimit,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+    max_keys: usize,
+}
+
+impl<K: Hash + Eq> KeyedRateLimiter<K> {
+    pub fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+            max_keys: MAX_TRACKED_KEYS,
+        }
+    }
+
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buckets.len() >= self.max_keys && !buckets.contains_key(&key) {
+            // A full bucket behaves exactly like a fresh one, so it can be dropped
+            buckets.retain(|_, bucket| !bucket.is_full_at(now));
+            if buckets.len() >= self.max_keys {
+                evict_least_recent(&mut buckets, self.max_keys - self.max_keys / 10);
+            }
+        }
+
+        buckets
+            .entry(key)
+            .or_insert_with(|| TokenBucket::new(self.limit))
+            .try_acquire_at(now)
+    }
+
+    /// Takes a token for every key, or none: if one key is over its limit,
+    /// the tokens already taken for the others are given back.
+    pub fn check_all(&self, keys: &[K]) -> Result<(), Duration>
+    where
+        K: Clone,
+    {
+        for (i, key) in keys.iter().enumerate() {
+            if let Err(retry_after) = self.check(key.clone()) {
+                for taken in &keys[..i] {
+                    self.refund(taken);
+                }
+                return Err(retry_after);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn refund(&self, key: &K) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.refund();
+        }
+    }
+}
+
+/// Senders of transactions a response held back without queueing, as when
+/// an atomic request is rejected. Their per-sender tokens are given back.
+#[derive(Debug, Clone, Default)]
+pub struct SkippedSenders(pub Vec<Address>);
+
+/// Drops the buckets used longest ago until `keep` are left. Under key churn
+/// this forgets some partly drained buckets, which is the price of bounded
+/// memory.
+fn evict_least_recent<K: Hash + Eq>(buckets: &mut HashMap<K, TokenBucket>, keep: usize) {
+    let mut refills: Vec<Instant> = buckets.values().map(|bucket| bucket.last_refill).collect();
+    let evict = refills.len().saturating_sub(keep);
+    if evict == 0 {
+        return;
+    }
+    let (_, cutoff, _) = refills.select_nth_unstable(evict - 1);
+    let cutoff = *cutoff;
+    let mut evicted = 0;
+    buckets.retain(|_, bucket| {
+        if evicted < evict && bucket.last_refill <= cutoff {
+            evicted += 1;
+            return false;
+        }
+        true
+    });
+}
+
+/// Limits for transaction submission, checked before the body is parsed.
+pub struct SubmissionRateLimiter {
+    per_ip: Option<KeyedRateLimiter<IpAddr>>,
+    per_sender: Option<KeyedRateLimiter<Address>>,
+    /// Proxies in front of the server, each appending to `X-Forwarded-For`;
+    /// with none the header is ignored
+    trusted_proxies: usize,
+}
+
+impl SubmissionRateLimiter {
+    pub fn new(
+        per_ip: Option<RateLimit>,
+        per_sender: Option<RateLimit>,
+        trusted_proxies: usize,
+    ) -> Self {
+        Self {
+            per_ip: per_ip.map(KeyedRateLimiter::new),
+            per_sender: per_sender.map(KeyedRateLimiter::new),
+            trusted_proxies,
+        }
+    }
+
+    /// The address the outermost trusted proxy saw. Entries to its left were
+    /// written by the client, so they can't be trusted.
+    fn client_ip(&self, req: &Request, peer: SocketAddr) -> IpAddr {
+        if self.trusted_proxies == 0 {
+            return peer.ip();
+        }
+        let forwarded: Vec<&str> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect();
+        forwarded
+            .iter()
+            .rev()
+            .nth(self.trusted_proxies - 1)
+            .or(forwarded.first())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or_else(|| peer.ip())
+    }
+}
+
+/// Just enough of a submission body to find the senders without validating
+/// the transactions. Items are read one by one, so a malformed item can't
+/// hide the senders of the others.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SenderProbe {
+    Single { raw_tx: Value },
+    Many { transactions: Vec<Value> },
+}
+
+impl SenderProbe {
+    fn senders(self) -> Vec<Address> {
+        let items = match self {
+            SenderProbe::Single { raw_tx } => vec![raw_tx],
+            SenderProbe::Many { transactions } => transactions,
+        };
+        items
+            .iter()
+            .filter_map(|item| item.get("from")?.as_str()?.parse().ok())
+            .collect()
+    }
+}
+
+/// Per-IP limit, applied ahead of authentication.
+pub async fn ip_rate_limit_middleware(
+    State(limiter): State<Arc<SubmissionRateLimiter>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(per_ip) = &limiter.per_ip {
+        let ip = limiter.client_ip(&req, peer);
+        if let Err(retry_after) = per_ip.check(ip) {
+            return ApiError::RateLimited {
+                message: "Too many requests from this IP".to_string(),
+                retry_after,
+            }
+            .into_response();
+        }
+    }
+
+    next.run(req).await
+}
+
+/// Per-sender limit. Runs after authentication, so anonymous callers can't
+/// use up another sender's budget, but before the transaction is parsed.
+/// Each transaction in a multi-transaction request takes its own token, and a
+/// refused request takes none. Tokens for transactions an atomic rejection
+/// held back are given back.
+pub async fn sender_rate_limit_middleware(
+    State(limiter): State<Arc<SubmissionRateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(per_sender) = &limiter.per_sender else {
+        return next.run(req).await;
+    };
+
+    let (parts, body) = req.into_parts();
+    let bytes = match to_bytes(body, MAX_BODY_BYTES).await {
+        Ok(bytes) => bytes,
+        Err(e) => return body_error(e).into_response(),
+    };
+
+    // Bodies without a readable sender are left for the handler to reject
+    let senders = serde_json::from_slice::<SenderProbe>(&bytes)
+        .map(SenderProbe::senders)
+        .unwrap_or_default();
+    if let Err(retry_after) = per_sender.check_all(&senders) {
+        return ApiError::RateLimited {
+            message: "Too many transactions from this sender".to_string(),
+            retry_after,
+        }
+        .into_response();
+    }
+
+    let response = next
+        .run(Request::from_parts(parts, Body::from(bytes)))
+        .await;
+    if let Some(SkippedSenders(skipped)) = response.extensions().get() {
+        for sender in skipped {
+            per_sender.refund(sender);
+        }
+    }
+    response
+}
+
+fn body_error(e: axum::Error) -> ApiError {
+    let over_limit =
+        std::error::Error::source(&e).is_some_and(|source| source.is::<LengthLimitError>());
+    if over_limit {
+        ApiError::PayloadTooLarge("Request body too large".to_string())
+    } else {
+        ApiError::InvalidRequest(format!("Failed to read request body: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::error::retry_after_secs;
+
+    #[test]
+    fn test_burst_then_refill() {
+        let mut bucket = TokenBucket::new(RateLimit {
+            requests_per_second: 2.0,
+            burst: Some(3.0),
+        });
+        let start = bucket.last_refill;
+
+        for _ in 0..3 {
+            assert!(bucket.try_acquire_at(start).is_ok());
+        }
+        let wait = bucket.try_acquire_at(start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+        assert_eq!(retry_after_secs(wait), 1);
+
+        assert!(bucket
+            .try_acquire_at(start + Duration::from_millis(500))
+            .is_ok());
+        assert!(bucket
+            .try_acquire_at(start + Duration::from_millis(500))
+            .is_err());
+
+        // A wait too long to represent saturates rather than panicking
+        let mut slow = TokenBucket::new(RateLimit {
+            requests_per_second: 1e-300,
+            burst: None,
+        });
+        let start = slow.last_refill;
+        assert!(slow.try_acquire_at(start).is_ok());
+        let wait = slow.try_acquire_at(start).unwrap_err();
+        assert_eq!(wait, Duration::MAX);
+        assert_eq!(retry_after_secs(wait), u64::MAX);
+    }
+
+    #[test]
+    fn test_keyed_limiter_tracks_keys_separately() {
+        let limiter = KeyedRateLimiter::new(RateLimit {
+            requests_per_second: 1.0,
+            burst: None,
+        });
+        let alice = Address::repeat_byte(0x11);
+        let bob = Address::repeat_byte(0x22);
+
+        assert!(limiter.check(alice).is_ok());
+        assert!(limiter.check(alice).is_err());
+        assert!(limiter.check(bob).is_ok());
+
+        limiter.refund(&alice);
+        assert!(limiter.check(alice).is_ok());
+        assert!(limiter.check(alice).is_err());
+
+        // Bob's token comes back when alice is over her limit
+        let carol = Address::repeat_byte(0x33);
+        limiter.refund(&bob);
+        assert!(limiter.check_all(&[bob, alice]).is_err());
+        assert!(limiter.check_all(&[bob, carol]).is_ok());
+        assert!(limiter.check(bob).is_err());
+    }
+
+    #[test]
+    fn test_keyed_limiter_stays_bounded_under_churn() {
+        let mut limiter = KeyedRateLimiter::new(RateLimit {
+            requests_per_second: 1.0,
+            burst: None,
+        });
+        limiter.max_keys = 10;
+
+        // Every bucket is drained, so none can be pruned as idle
+        for key in 0..1000u32 {
+            assert!(limiter.check(key).is_ok());
+            assert!(limiter.buckets.lock().unwrap().len() <= 10);
+        }
+        // The most recent keys are still limited
+        assert!(limiter.check(999).is_err());
+    }
+
+    #[test]
+    fn test_client_ip_counts_trusted_proxies_from_the_right() {
+        let peer: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        let req = Request::builder()
+            .header("x-forwarded-for", "6.6.6.6, 1.1.1.1")
+            .header("x-forwarded-for", "2.2.2.2")
+            .body(Body::empty())
+            .unwrap();
+        let client_ip = |trusted_proxies| {
+            SubmissionRateLimiter::new(None, None, trusted_proxies).client_ip(&req, peer)
+        };
+
+        assert_eq!(client_ip(0), peer.ip());
+        assert_eq!(client_ip(1), "2.2.2.2".parse::<IpAddr>().unwrap());
+        assert_eq!(client_ip(2), "1.1.1.1".parse::<IpAddr>().unwrap());
+        // More proxies than entries: the leftmost was still written by one
+        assert_eq!(client_ip(5), "6.6.6.6".parse::<IpAddr>().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_payload_too_large() {
+        let e = to_bytes(Body::from(vec![0u8; 16]), 8).await.unwrap_err();
+        assert_eq!(
+            body_error(e).status(),
+            axum::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[test]
+    fn test_sender_probe_reads_every_transaction() {
+        let single = r#"{"raw_tx":{"from":"0x1111111111111111111111111111111111111111"}}"#;
+        let many = r#"{"transactions":[
+            {"from":"0x1111111111111111111111111111111111111111"},
+            {"from":"not an address"},
+            {"to":"0x3333333333333333333333333333333333333333"},
+            {"from":42},
+            "not a transaction",
+            {"from":"0x2222222222222222222222222222222222222222"}
+        ]}"#;
+
+        let senders = |body: &str| serde_json::from_str::<SenderProbe>(body).unwrap().senders();
+        assert_eq!(senders(single), vec![Address::repeat_byte(0x11)]);
+        assert_eq!(
+            senders(many),
+            vec![Address::repeat_byte(0x11), Address::repeat_byte(0x22)]
+        );
+    }
+}
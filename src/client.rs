@@ -1,13 +1,20 @@
-use crate::models::L2Transaction;
-use reqwest::Client;
-use serde_json::json;
+use crate::api::handler::{ItemStatus, TransactionResponse, TransactionsResponse};
+use crate::config::SubmissionMode;
+use crate::models::confirmation::ConfirmationError;
+use crate::models::{Confirmation, L2Transaction};
+use alloy::primitives::Address;
+use reqwest::{Client, Response, StatusCode};
+use serde_json::{json, Value};
 use std::time::Duration;
+use tracing::{warn, Span};
 
 #[derive(Debug, Clone)]
 pub struct L2Client {
     client: Client,
     endpoint: String,
     auth_token: String,
+    /// Address every confirmation must be signed by, when checked
+    sequencer: Option<Address>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -18,6 +25,8 @@ pub enum ClientError {
     ResponseError(String),
     #[error("Server error: {status}, body: {body}")]
     ServerError { status: u16, body: String },
+    #[error(transparent)]
+    Confirmation(#[from] ConfirmationError),
 }
 
 impl L2Client {
@@ -31,36 +40,26 @@ impl L2Client {
             client,
             endpoint,
             auth_token,
+            sequencer: None,
         }
     }
 
-    pub async fn send_transaction(&self, tx: L2Transaction) -> Result<(), ClientError> {
-        let tx_json = json!({
-            "raw_tx": {
-                "nonce": format!("0x{:x}", tx.nonce),
-                "from": format!("{:#x}", tx.from),
-                "to": tx.to.map(|addr| format!("{:#x}", addr)),
-                "value": format!("0x{:x}", tx.value),
-                "data": format!("0x{}", hex::encode(&tx.data)),
-                "gas_limit": format!("0x{:x}", tx.gas_limit),
-                "gas_price": format!("0x{:x}", tx.gas_price),
-                "chain_id": tx.chain_id.unwrap_or(42161),
-                "l1_block_number": tx.l1_block_number,
-                "submission_fee": format!("0x{:x}", tx.submission_fee)
-            }
-        });
+    /// Requires a confirmation signed by `sequencer` for every queued
+    /// transaction, failing the request otherwise.
+    pub fn with_sequencer(mut self, sequencer: Address) -> Self {
+        self.sequencer = Some(sequencer);
+        self
+    }
 
-        let response = self
-            .client
-            .post(&format!("{}/send_transaction", self.endpoint))
-            .bearer_auth(&self.auth_token)
-            .json(&tx_json)
-            .send()
-            .await
-            .map_err(|e| {
-                eprintln!("Failed to send request: {}", e);
-                ClientError::RequestError(e)
-            })?;
+    /// Returns the sequencer's confirmation, if it signs them; checked when
+    /// the client has `with_sequencer`.
+    #[tracing::instrument(skip_all, fields(tx_hash = %tx.hash(), request_id))]
+    pub async fn send_transaction(
+        &self,
+        tx: L2Transaction,
+    ) -> Result<Option<Confirmation>, ClientError> {
+        let body = json!({ "raw_tx": raw_tx_json(&tx) });
+        let response = self.post("send_transaction", &body).await?;
 
         if !response.status().is_success() {
             let status = response.status().as_u16();
@@ -68,16 +67,113 @@ impl L2Client {
                 .text()
                 .await
                 .unwrap_or_else(|_| "No response body".into());
-            eprintln!(
-                "Request body: {}",
-                serde_json::to_string_pretty(&tx_json).unwrap()
-            );
-            eprintln!("Response status: {}", status);
-            eprintln!("Response body: {}", body);
+            warn!(status, body = %body, "sequencer rejected transaction");
 
             return Err(ClientError::ServerError { status, body });
         }
 
-        Ok(())
+        let bytes = response.bytes().await?;
+        let response: TransactionResponse = serde_json::from_slice(&bytes).map_err(|_| {
+            ClientError::ResponseError(String::from_utf8_lossy(&bytes).into_owned())
+        })?;
+        self.check_confirmation(response.confirmation.as_ref(), &tx)?;
+        Ok(response.confirmation)
     }
+
+    /// Submits `txs` in one request. `mode` defaults to the sequencer's
+    /// `submission.mode`. Per-item rejections are returned in the response,
+    /// not as an error, even when they left nothing queued.
+    #[tracing::instrument(skip_all, fields(count = txs.len(), request_id))]
+    pub async fn send_transactions(
+        &self,
+        txs: &[L2Transaction],
+        mode: Option<SubmissionMode>,
+    ) -> Result<TransactionsResponse, ClientError> {
+        let body = json!({
+            "transactions": txs.iter().map(raw_tx_json).collect::<Vec<_>>(),
+            "mode": mode,
+        });
+        let response = self.post("send_transactions", &body).await?;
+
+        let status = response.status();
+        let bytes = response.bytes().await?;
+        // 422 carries per-item results unless the request as a whole was invalid
+        if status.is_success() || status == StatusCode::UNPROCESSABLE_ENTITY {
+            if let Ok(results) = serde_json::from_slice::<TransactionsResponse>(&bytes) {
+                for (result, tx) in results.results.iter().zip(txs) {
+                    if result.status == ItemStatus::Queued {
+                        self.check_confirmation(result.confirmation.as_ref(), tx)?;
+                    }
+                }
+                return Ok(results);
+            }
+        }
+
+        let body = String::from_utf8_lossy(&bytes).into_owned();
+        if status.is_success() {
+            return Err(ClientError::ResponseError(body));
+        }
+        warn!(status = status.as_u16(), body = %body, "sequencer rejected transactions");
+        Err(ClientError::ServerError {
+            status: status.as_u16(),
+            body,
+        })
+    }
+
+    /// Checks `confirmation` was signed for `tx` by the expected sequencer.
+    fn check_confirmation(
+        &self,
+        confirmation: Option<&Confirmation>,
+        tx: &L2Transaction,
+    ) -> Result<(), ClientError> {
+        let Some(sequencer) = self.sequencer else {
+            return Ok(());
+        };
+        let confirmation = confirmation.ok_or_else(|| {
+            ClientError::ResponseError(format!("no confirmation for {}", tx.hash()))
+        })?;
+        confirmation.verify(sequencer, tx).map_err(|e| {
+            warn!(error = %e, "sequencer confirmation didn't verify");
+            ClientError::from(e)
+        })
+    }
+
+    async fn post(&self, path: &str, body: &Value) -> Result<Response, ClientError> {
+        let response = self
+            .client
+            .post(format!("{}/{}", self.endpoint, path))
+            .bearer_auth(&self.auth_token)
+            .json(body)
+            .send()
+            .await
+            .map_err(|e| {
+                warn!(error = %e, "failed to send transaction");
+                ClientError::RequestError(e)
+            })?;
+
+        // The sequencer's id for this request, to correlate with its logs
+        if let Some(request_id) = response
+            .headers()
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+        {
+            Span::current().record("request_id", request_id);
+        }
+        Ok(response)
+    }
+}
+
+fn raw_tx_json(tx: &L2Transaction) -> Value {
+    json!({
+        "nonce": format!("0x{:x}", tx.nonce),
+        "from": format!("{:#x}", tx.from),
+        "to": tx.to.map(|addr| format!("{:#x}", addr)),
+        "value": format!("0x{:x}", tx.value),
+        "data": format!("0x{}", hex::encode(&tx.data)),
+        "gas_limit": format!("0x{:x}", tx.gas_limit),
+        "gas_price": format!("0x{:x}", tx.gas_price),
+        "chain_id": tx.chain_id.unwrap_or(42161),
+        "l1_block_number": tx.l1_block_number,
+        "submission_fee": format!("0x{:x}", tx.submission_fee)
+    })
 }
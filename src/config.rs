@@ -0,0 +1,1077 @@
+use crate::api::rate_limit::RateLimit;
+use crate::models::codec::{BatchCodec, Brotli, NoCompression, PayloadDecoder, Zstd};
+use crate::models::{BatchFormat, SequencingWindow, WindowPolicy};
+use alloy::primitives::Address;
+use alloy::signers::local::PrivateKeySigner;
+use clap::builder::BoolishValueParser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize, Serializer};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file {path}: {source}")]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+    #[error("Invalid config: {0}")]
+    Invalid(String),
+}
+
+/// Command line flags. Every override can also come from the environment
+/// variable named next to it; flags win over the environment, which wins over
+/// the config file.
+#[derive(Debug, Default, Parser)]
+#[command(name = "sequencer", about = "L2 sequencer")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to a TOML config file
+    #[arg(long, env = "SEQUENCER_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved config (secrets redacted) and exit, failing after
+    /// printing if it's invalid
+    #[arg(long)]
+    pub print_config: bool,
+
+    #[arg(long, env = "LISTEN_ADDR")]
+    pub listen_addr: Option<SocketAddr>,
+
+    #[arg(long, env = "RPC_URL")]
+    pub rpc_url: Option<String>,
+
+    /// Allowed CORS origins, comma separated
+    #[arg(long, env = "CORS_ORIGINS", value_delimiter = ',')]
+    pub cors_origins: Option<Vec<String>>,
+
+    #[arg(long, env = "AUTH_MODE")]
+    pub auth_mode: Option<AuthMode>,
+
+    #[arg(long, env = "API_KEYS_FILE")]
+    pub api_keys_file: Option<PathBuf>,
+
+    #[arg(long, env = "AUTH_TOKEN", hide_env_values = true)]
+    pub auth_token: Option<String>,
+
+    #[arg(long, env = "JWKS_FILE")]
+    pub jwks_file: Option<PathBuf>,
+
+    #[arg(long, env = "JWT_ISSUER")]
+    pub jwt_issuer: Option<String>,
+
+    #[arg(long, env = "JWT_AUDIENCE")]
+    pub jwt_audience: Option<String>,
+
+    #[arg(long, env = "PUBLIC_FEED", value_parser = BoolishValueParser::new())]
+    pub public_feed: Option<bool>,
+
+    #[arg(long, env = "RATE_LIMIT_IP_RPS")]
+    pub rate_limit_ip_rps: Option<f64>,
+
+    #[arg(long, env = "RATE_LIMIT_IP_BURST")]
+    pub rate_limit_ip_burst: Option<f64>,
+
+    #[arg(long, env = "RATE_LIMIT_SENDER_RPS")]
+    pub rate_limit_sender_rps: Option<f64>,
+
+    #[arg(long, env = "RATE_LIMIT_SENDER_BURST")]
+    pub rate_limit_sender_burst: Option<f64>,
+
+    /// Proxies in front of the server; the client IP is read that many
+    /// entries from the right of `X-Forwarded-For`
+    #[arg(long, env = "TRUSTED_PROXIES")]
+    pub trusted_proxies: Option<usize>,
+
+    /// Transactions per batch
+    #[arg(long, env = "BATCH_SIZE")]
+    pub batch_size: Option<usize>,
+
+    /// Close batches at this many compressed bytes
+    #[arg(long, env = "BATCH_MAX_COMPRESSED_BYTES")]
+    pub batch_max_compressed_bytes: Option<usize>,
+
+    /// Compression for batch payloads
+    #[arg(long, env = "BATCH_CODEC")]
+    pub batch_codec: Option<CodecKind>,
+
+    /// Transaction layout in batch payloads
+    #[arg(long, env = "BATCH_FORMAT")]
+    pub batch_format: Option<BatchFormat>,
+
+    /// Default for `POST /send_transactions` requests that don't pick a mode
+    #[arg(long, env = "SUBMISSION_MODE")]
+    pub submission_mode: Option<SubmissionMode>,
+
+    /// Hex private key admitted transactions' confirmations are signed with
+    #[arg(long, env = "SEQUENCER_SIGNING_KEY", hide_env_values = true)]
+    pub signing_key: Option<String>,
+
+    #[arg(long, env = "COMMAND_CHANNEL_CAPACITY")]
+    pub command_channel_capacity: Option<usize>,
+
+    #[arg(long, env = "FEED_CHANNEL_CAPACITY")]
+    pub feed_channel_capacity: Option<usize>,
+
+    #[arg(long, env = "DATA_DIR")]
+    pub data_dir: Option<PathBuf>,
+
+    /// L1 address batches are posted to; posting is off when unset
+    #[arg(long, env = "BATCH_INBOX")]
+    pub batch_inbox: Option<Address>,
+
+    /// Hex private key that pays for batch posting
+    #[arg(long, env = "L1_SIGNER_KEY", hide_env_values = true)]
+    pub l1_signer_key: Option<String>,
+
+    /// L1 bridge contract deposits are read from; deposits are off when unset
+    #[arg(long, env = "L1_BRIDGE")]
+    pub l1_bridge: Option<Address>,
+
+    /// L1 contract output roots are proposed to; proposing is off when unset
+    #[arg(long, env = "L1_OUTPUT_ORACLE")]
+    pub l1_output_oracle: Option<Address>,
+
+    /// Hex private key that pays for output proposals
+    #[arg(long, env = "L1_PROPOSER_KEY", hide_env_values = true)]
+    pub l1_proposer_key: Option<String>,
+
+    /// L1 blocks a transaction's `l1_block_number` may trail the L1 head by
+    #[arg(long, env = "SEQUENCING_WINDOW")]
+    pub sequencing_window: Option<u64>,
+
+    /// Whether out-of-window `l1_block_number`s are clamped or rejected
+    #[arg(long, env = "WINDOW_POLICY")]
+    pub window_policy: Option<WindowPolicy>,
+
+    /// Seconds the L1 submitter may spend on one batch before `/readyz` fails
+    #[arg(long, env = "L1_STALL_THRESHOLD_SECS")]
+    pub l1_stall_threshold_secs: Option<u64>,
+
+    /// Seconds allowed for draining and the final batch after SIGTERM
+    #[arg(long, env = "SHUTDOWN_DEADLINE_SECS")]
+    pub shutdown_deadline_secs: Option<u64>,
+
+    #[arg(long, env = "LOG_FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// Log filter directives, e.g. `info,sequencer=debug`
+    #[arg(long, env = "RUST_LOG")]
+    pub log_filter: Option<String>,
+
+    /// OTLP/HTTP traces endpoint, e.g. `http://localhost:4318/v1/traces`
+    #[arg(long, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    pub otlp_endpoint: Option<String>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Rebuild the chain from the batches on L1 and compare it with the
+    /// batch store, instead of sequencing
+    Verify(VerifyArgs),
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct VerifyArgs {
+    /// First L1 block to scan; defaults to where the earliest recorded batch
+    /// landed
+    #[arg(long)]
+    pub from_l1_block: Option<u64>,
+
+    /// Last L1 block to scan; defaults to the latest
+    #[arg(long)]
+    pub to_l1_block: Option<u64>,
+
+    /// Only read inbox transactions from this address; defaults to the
+    /// `l1.signer_key` address when that's set
+    #[arg(long, env = "BATCH_POSTER")]
+    pub poster: Option<Address>,
+
+    /// Beacon node REST endpoint that blob batches are read from; without
+    /// one, inbox transactions carrying blobs are reported unread
+    #[arg(long, env = "L1_BEACON_URL")]
+    pub beacon_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub rpc: RpcConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub batch: BatchConfig,
+    pub submission: SubmissionConfig,
+    pub channels: ChannelConfig,
+    pub storage: StorageConfig,
+    pub l1: L1Config,
+    pub logging: LoggingConfig,
+    pub health: HealthConfig,
+    pub shutdown: ShutdownConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_addr: SocketAddr,
+    pub cors_origins: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen_addr: SocketAddr::from(([0, 0, 0, 0], 3001)),
+            cors_origins: vec!["http://localhost:3000".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    pub url: String,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://eth.merkle.io".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum AuthMode {
+    #[default]
+    ApiKeys,
+    Jwt,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub mode: AuthMode,
+    pub api_keys_file: Option<PathBuf>,
+    /// Single all-scopes key used when no `api_keys_file` is set
+    #[serde(serialize_with = "redact")]
+    pub token: Option<String>,
+    pub jwks_file: Option<PathBuf>,
+    pub jwt_issuer: Option<String>,
+    pub jwt_audience: Option<String>,
+    /// Admit feed connections without credentials
+    pub public_feed: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub per_ip: Option<RateLimit>,
+    pub per_sender: Option<RateLimit>,
+    /// Proxies in front of the server, each appending to `X-Forwarded-For`;
+    /// with none the header is ignored
+    pub trusted_proxies: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BatchConfig {
+    pub max_transactions: usize,
+    /// Also close a batch before its compressed payload passes this many
+    /// bytes, measured by compressing pending transactions as they're queued
+    pub max_compressed_bytes: Option<usize>,
+    /// Recorded in each batch's header, so changing it doesn't affect
+    /// batches already built
+    pub codec: CodecKind,
+    /// Also recorded in each batch's header
+    pub format: BatchFormat,
+    pub brotli: BrotliConfig,
+    pub zstd: ZstdConfig,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_transactions: 50,
+            max_compressed_bytes: None,
+            codec: CodecKind::default(),
+            format: BatchFormat::default(),
+            brotli: BrotliConfig::default(),
+            zstd: ZstdConfig::default(),
+        }
+    }
+}
+
+impl BatchConfig {
+    /// The configured codec, with its zstd dictionary read in.
+    pub fn codec(&self) -> Result<Arc<dyn BatchCodec>, ConfigError> {
+        let codec: Arc<dyn BatchCodec> = match self.codec {
+            CodecKind::None => Arc::new(NoCompression),
+            CodecKind::Brotli => Arc::new(Brotli {
+                quality: self.brotli.quality,
+                window: self.brotli.window,
+            }),
+            CodecKind::Zstd => {
+                let zstd = Zstd {
+                    level: self.zstd.level,
+                    dictionary: self.zstd_dictionary()?,
+                };
+                // Catches a dictionary zstd can't load before the first batch does
+                zstd.encoder(Vec::new()).map_err(|e| {
+                    ConfigError::Invalid(format!("batch.zstd.dictionary is unusable: {}", e))
+                })?;
+                Arc::new(zstd)
+            }
+        };
+        Ok(codec)
+    }
+
+    /// Reads payloads in any codec, with the configured zstd dictionary for
+    /// zstd payloads.
+    pub fn decoder(&self) -> Result<PayloadDecoder, ConfigError> {
+        Ok(PayloadDecoder::new(self.zstd_dictionary()?))
+    }
+
+    fn zstd_dictionary(&self) -> Result<Option<Vec<u8>>, ConfigError> {
+        let Some(path) = &self.zstd.dictionary else {
+            return Ok(None);
+        };
+        std::fs::read(path)
+            .map(Some)
+            .map_err(|source| ConfigError::Io {
+                path: path.clone(),
+                source,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum CodecKind {
+    None,
+    #[default]
+    Brotli,
+    Zstd,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BrotliConfig {
+    /// 0 to 11
+    pub quality: u32,
+    /// Log2 of the window size, 10 to 24
+    pub window: u32,
+}
+
+impl Default for BrotliConfig {
+    fn default() -> Self {
+        let defaults = Brotli::default();
+        Self {
+            quality: defaults.quality,
+            window: defaults.window,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ZstdConfig {
+    /// 1 to 22
+    pub level: i32,
+    /// Dictionary trained on typical batches. Anything decoding the batches
+    /// needs the same file.
+    pub dictionary: Option<PathBuf>,
+}
+
+impl Default for ZstdConfig {
+    fn default() -> Self {
+        Self {
+            level: 3,
+            dictionary: None,
+        }
+    }
+}
+
+/// How `POST /send_transactions` treats a request with some invalid items.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum SubmissionMode {
+    /// Queue the valid items and report the rest
+    #[default]
+    Individual,
+    /// Queue nothing unless every item is valid
+    Atomic,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubmissionConfig {
+    pub mode: SubmissionMode,
+    /// Most transactions accepted in one `POST /send_transactions`
+    pub max_transactions_per_request: usize,
+    /// Items of one `POST /send_transactions` validated at a time
+    pub validation_concurrency: usize,
+    /// Signs a confirmation for every admitted transaction when set
+    #[serde(serialize_with = "redact")]
+    pub signing_key: Option<String>,
+}
+
+impl Default for SubmissionConfig {
+    fn default() -> Self {
+        Self {
+            mode: SubmissionMode::Individual,
+            max_transactions_per_request: 100,
+            validation_concurrency: 16,
+            signing_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub command_capacity: usize,
+    pub feed_capacity: usize,
+    /// Sealed batches waiting to be compressed, and built batches waiting to
+    /// be posted to L1
+    pub batch_capacity: usize,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self {
+            command_capacity: 100,
+            feed_capacity: 100,
+            batch_capacity: 16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    pub data_dir: PathBuf,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("data"),
+        }
+    }
+}
+
+/// Batch posting to L1. `batch_inbox` and `signer_key` must both be set to
+/// enable it; the L1 node is `rpc.url`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct L1Config {
+    pub batch_inbox: Option<Address>,
+    #[serde(serialize_with = "redact")]
+    pub signer_key: Option<String>,
+    /// L1 blocks a transaction's `l1_block_number` may trail its block's L1
+    /// origin by, and a batch may land after its earliest block's origin
+    pub sequencing_window: u64,
+    pub window_policy: WindowPolicy,
+    /// Held batches are posted despite a pause once their window has this
+    /// many L1 blocks left
+    pub window_margin: u64,
+    pub head_poll_secs: u64,
+    /// Recent L1 block hashes kept to detect reorgs; a reorg deeper than
+    /// this is handled as if it were this deep
+    pub reorg_depth: usize,
+    /// Contract whose `DepositInitiated` events become L2 deposits
+    pub bridge: Option<Address>,
+    /// L1 blocks behind the head a deposit must be before it's read
+    pub deposit_confirmations: u64,
+    /// A deposit must be in a block whose L1 origin is at most this many L1
+    /// blocks after its own
+    pub deposit_deadline: u64,
+    /// Contract output roots are proposed to
+    pub output_oracle: Option<Address>,
+    /// Pays for output proposals; kept apart from `signer_key` so the two
+    /// don't race for nonces
+    #[serde(serialize_with = "redact")]
+    pub proposer_key: Option<String>,
+    /// Seconds between output proposals
+    pub output_interval_secs: u64,
+}
+
+impl Default for L1Config {
+    fn default() -> Self {
+        Self {
+            batch_inbox: None,
+            signer_key: None,
+            sequencing_window: 3600,
+            window_policy: WindowPolicy::Clamp,
+            window_margin: 600,
+            head_poll_secs: 12,
+            reorg_depth: 64,
+            bridge: None,
+            deposit_confirmations: 6,
+            deposit_deadline: 300,
+            output_oracle: None,
+            proposer_key: None,
+            output_interval_secs: 300,
+        }
+    }
+}
+
+impl L1Config {
+    pub fn posting_enabled(&self) -> bool {
+        self.batch_inbox.is_some() && self.signer_key.is_some()
+    }
+
+    pub fn sequencing_window(&self) -> SequencingWindow {
+        SequencingWindow {
+            size: self.sequencing_window,
+            policy: self.window_policy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    /// `/readyz` fails once the L1 submitter has been stuck on one batch for
+    /// longer than this
+    pub l1_stall_threshold_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            l1_stall_threshold_secs: 600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// Time from SIGTERM until the process exits regardless: in-flight
+    /// requests, the final batch and posting it must fit in here
+    pub deadline_secs: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self { deadline_secs: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// `EnvFilter` directives
+    pub filter: String,
+    /// Traces are exported here when set (needs the `otlp` feature)
+    pub otlp_endpoint: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            filter: "info".to_string(),
+            otlp_endpoint: None,
+        }
+    }
+}
+
+fn redact<S: Serializer>(value: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match value {
+        Some(_) => serializer.serialize_str("<redacted>"),
+        None => serializer.serialize_none(),
+    }
+}
+
+impl Config {
+    /// Defaults, then the config file, then env/CLI overrides, then validation
+    /// for the mode the command line picks.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let config = Self::resolve(cli)?;
+        config.validate_for(cli)?;
+        Ok(config)
+    }
+
+    /// Defaults, file and overrides merged, without validation.
+    pub fn resolve(cli: &Cli) -> Result<Self, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        config.apply_overrides(cli);
+        Ok(config)
+    }
+
+    /// Checks what `cli`'s command needs.
+    pub fn validate_for(&self, cli: &Cli) -> Result<(), ConfigError> {
+        match cli.command {
+            Some(Command::Verify(_)) => self.validate_verifier(),
+            None => self.validate(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Ok(toml::from_str(&contents)?)
+    }
+
+    fn apply_overrides(&mut self, cli: &Cli) {
+        if let Some(addr) = cli.listen_addr {
+            self.server.listen_addr = addr;
+        }
+        if let Some(origins) = &cli.cors_origins {
+            self.server.cors_origins = origins.clone();
+        }
+        if let Some(url) = &cli.rpc_url {
+            self.rpc.url = url.clone();
+        }
+
+        if let Some(mode) = cli.auth_mode {
+            self.auth.mode = mode;
+        }
+        if let Some(path) = &cli.api_keys_file {
+            self.auth.api_keys_file = Some(path.clone());
+        }
+        if let Some(token) = &cli.auth_token {
+            self.auth.token = Some(token.clone());
+        }
+        if let Some(path) = &cli.jwks_file {
+            self.auth.jwks_file = Some(path.clone());
+        }
+        if let Some(issuer) = &cli.jwt_issuer {
+            self.auth.jwt_issuer = Some(issuer.clone());
+        }
+        if let Some(audience) = &cli.jwt_audience {
+            self.auth.jwt_audience = Some(audience.clone());
+        }
+        if let Some(public_feed) = cli.public_feed {
+            self.auth.public_feed = public_feed;
+        }
+
+        override_rate_limit(
+            &mut self.rate_limit.per_ip,
+            cli.rate_limit_ip_rps,
+            cli.rate_limit_ip_burst,
+        );
+        override_rate_limit(
+            &mut self.rate_limit.per_sender,
+            cli.rate_limit_sender_rps,
+            cli.rate_limit_sender_burst,
+        );
+        if let Some(proxies) = cli.trusted_proxies {
+            self.rate_limit.trusted_proxies = proxies;
+        }
+
+        if let Some(size) = cli.batch_size {
+            self.batch.max_transactions = size;
+        }
+        if let Some(bytes) = cli.batch_max_compressed_bytes {
+            self.batch.max_compressed_bytes = Some(bytes);
+        }
+        if let Some(codec) = cli.batch_codec {
+            self.batch.codec = codec;
+        }
+        if let Some(format) = cli.batch_format {
+            self.batch.format = format;
+        }
+        if let Some(mode) = cli.submission_mode {
+            self.submission.mode = mode;
+        }
+        if let Some(key) = &cli.signing_key {
+            self.submission.signing_key = Some(key.clone());
+        }
+        if let Some(capacity) = cli.command_channel_capacity {
+            self.channels.command_capacity = capacity;
+        }
+        if let Some(capacity) = cli.feed_channel_capacity {
+            self.channels.feed_capacity = capacity;
+        }
+        if let Some(dir) = &cli.data_dir {
+            self.storage.data_dir = dir.clone();
+        }
+        if let Some(inbox) = cli.batch_inbox {
+            self.l1.batch_inbox = Some(inbox);
+        }
+        if let Some(key) = &cli.l1_signer_key {
+            self.l1.signer_key = Some(key.clone());
+        }
+        if let Some(bridge) = cli.l1_bridge {
+            self.l1.bridge = Some(bridge);
+        }
+        if let Some(oracle) = cli.l1_output_oracle {
+            self.l1.output_oracle = Some(oracle);
+        }
+        if let Some(key) = &cli.l1_proposer_key {
+            self.l1.proposer_key = Some(key.clone());
+        }
+        if let Some(window) = cli.sequencing_window {
+            self.l1.sequencing_window = window;
+        }
+        if let Some(policy) = cli.window_policy {
+            self.l1.window_policy = policy;
+        }
+        if let Some(secs) = cli.l1_stall_threshold_secs {
+            self.health.l1_stall_threshold_secs = secs;
+        }
+        if let Some(secs) = cli.shutdown_deadline_secs {
+            self.shutdown.deadline_secs = secs;
+        }
+        if let Some(format) = cli.log_format {
+            self.logging.format = format;
+        }
+        if let Some(filter) = &cli.log_filter {
+            self.logging.filter = filter.clone();
+        }
+        if let Some(endpoint) = &cli.otlp_endpoint {
+            self.logging.otlp_endpoint = Some(endpoint.clone());
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| Err(ConfigError::Invalid(msg.to_string()));
+
+        if self.rpc.url.parse::<reqwest::Url>().is_err() {
+            return invalid("rpc.url is not a valid URL");
+        }
+        for origin in &self.server.cors_origins {
+            if origin != "*" && http::HeaderValue::from_str(origin).is_err() {
+                return Err(ConfigError::Invalid(format!(
+                    "server.cors_origins contains an invalid origin: {}",
+                    origin
+                )));
+            }
+        }
+
+        match self.auth.mode {
+            AuthMode::ApiKeys => {
+                if self.auth.api_keys_file.is_none() && self.auth.token.is_none() {
+                    return invalid("auth.api_keys_file or auth.token must be set");
+                }
+            }
+            AuthMode::Jwt => {
+                if self.auth.jwks_file.is_none()
+                    || self.auth.jwt_issuer.is_none()
+                    || self.auth.jwt_audience.is_none()
+                {
+                    return invalid(
+                        "auth.jwks_file, auth.jwt_issuer and auth.jwt_audience are required in jwt mode",
+                    );
+                }
+            }
+        }
+
+        for limit in [&self.rate_limit.per_ip, &self.rate_limit.per_sender]
+            .into_iter()
+            .flatten()
+        {
+            if !limit.is_valid() {
+                return invalid("rate limits need requests_per_second > 0 and burst >= 1");
+            }
+        }
+
+        self.validate_batch()?;
+        if self.submission.max_transactions_per_request == 0
+            || self.submission.validation_concurrency == 0
+        {
+            return invalid(
+                "submission.max_transactions_per_request and validation_concurrency must be at least 1",
+            );
+        }
+        if self.channels.command_capacity == 0
+            || self.channels.feed_capacity == 0
+            || self.channels.batch_capacity == 0
+        {
+            return invalid("channel capacities must be at least 1");
+        }
+
+        if tracing_subscriber::EnvFilter::try_new(&self.logging.filter).is_err() {
+            return invalid("logging.filter is not a valid filter");
+        }
+
+        if self.l1.sequencing_window == 0 || self.l1.window_margin >= self.l1.sequencing_window {
+            return invalid("l1.sequencing_window must be at least 1 and above l1.window_margin");
+        }
+        if self.l1.head_poll_secs == 0 {
+            return invalid("l1.head_poll_secs must be at least 1");
+        }
+        if self.l1.reorg_depth == 0 {
+            return invalid("l1.reorg_depth must be at least 1");
+        }
+        if self.l1.deposit_deadline <= self.l1.deposit_confirmations {
+            return invalid("l1.deposit_deadline must be above l1.deposit_confirmations");
+        }
+        if self.l1.batch_inbox.is_some() != self.l1.signer_key.is_some() {
+            return invalid("l1.batch_inbox and l1.signer_key must be set together");
+        }
+        if let Some(key) = &self.l1.signer_key {
+            if key.parse::<PrivateKeySigner>().is_err() {
+                return invalid("l1.signer_key is not a valid private key");
+            }
+        }
+        if self.l1.output_oracle.is_some() != self.l1.proposer_key.is_some() {
+            return invalid("l1.output_oracle and l1.proposer_key must be set together");
+        }
+        if let Some(key) = &self.l1.proposer_key {
+            if key.parse::<PrivateKeySigner>().is_err() {
+                return invalid("l1.proposer_key is not a valid private key");
+            }
+        }
+        if self.l1.output_oracle.is_some() && !self.l1.posting_enabled() {
+            return invalid(
+                "l1.output_oracle needs batch posting, since outputs follow posted batches",
+            );
+        }
+        if self.l1.output_interval_secs == 0 {
+            return invalid("l1.output_interval_secs must be at least 1");
+        }
+        if let Some(key) = &self.submission.signing_key {
+            if key.parse::<PrivateKeySigner>().is_err() {
+                return invalid("submission.signing_key is not a valid private key");
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks what `sequencer verify` uses: the L1 node, the inbox and the
+    /// batch codec. The server and posting settings don't matter to it.
+    pub fn validate_verifier(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| Err(ConfigError::Invalid(msg.to_string()));
+
+        if self.rpc.url.parse::<reqwest::Url>().is_err() {
+            return invalid("rpc.url is not a valid URL");
+        }
+        if self.l1.batch_inbox.is_none() {
+            return invalid("l1.batch_inbox must be set to verify batches");
+        }
+        if let Some(key) = &self.l1.signer_key {
+            if key.parse::<PrivateKeySigner>().is_err() {
+                return invalid("l1.signer_key is not a valid private key");
+            }
+        }
+        self.validate_batch()
+    }
+
+    fn validate_batch(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: &str| Err(ConfigError::Invalid(msg.to_string()));
+
+        if self.batch.max_transactions == 0 {
+            return invalid("batch.max_transactions must be at least 1");
+        }
+        if self.batch.max_compressed_bytes == Some(0) {
+            return invalid("batch.max_compressed_bytes must be at least 1");
+        }
+        if self.batch.format == BatchFormat::Span && self.batch.max_compressed_bytes.is_some() {
+            return invalid("batch.max_compressed_bytes needs batch.format = \"flat\"");
+        }
+        if self.batch.brotli.quality > 11 || !(10..=24).contains(&self.batch.brotli.window) {
+            return invalid("batch.brotli needs quality 0 to 11 and window 10 to 24");
+        }
+        if !(1..=22).contains(&self.batch.zstd.level) {
+            return invalid("batch.zstd.level must be 1 to 22");
+        }
+        Ok(())
+    }
+
+    /// TOML rendering with secrets redacted, for `--print-config`.
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("Config is always serializable")
+    }
+}
+
+fn override_rate_limit(limit: &mut Option<RateLimit>, rps: Option<f64>, burst: Option<f64>) {
+    if let Some(requests_per_second) = rps {
+        *limit = Some(RateLimit {
+            requests_per_second,
+            burst: burst.or(limit.and_then(|l| l.burst)),
+        });
+    } else if let (Some(limit), Some(burst)) = (limit.as_mut(), burst) {
+        limit.burst = Some(burst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::parse_from(std::iter::once("sequencer").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_file_then_flags() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [server]
+            listen_addr = "127.0.0.1:4000"
+
+            [auth]
+            token = "secret"
+
+            [batch]
+            max_transactions = 10
+            "#,
+        )
+        .unwrap();
+        config.apply_overrides(&cli(&[
+            "--batch-size",
+            "25",
+            "--rate-limit-ip-rps",
+            "5",
+            "--submission-mode",
+            "atomic",
+            "--batch-codec",
+            "zstd",
+        ]));
+        config.validate().unwrap();
+
+        assert_eq!(config.server.listen_addr.port(), 4000);
+        assert_eq!(config.batch.max_transactions, 25);
+        assert_eq!(config.rate_limit.per_ip.unwrap().requests_per_second, 5.0);
+        assert_eq!(config.channels.command_capacity, 100);
+        assert_eq!(config.submission.mode, SubmissionMode::Atomic);
+        assert_eq!(config.batch.codec, CodecKind::Zstd);
+        assert_eq!(config.batch.codec().unwrap().id() as u8, 2);
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut config = Config::default();
+        assert!(config.validate().is_err(), "no credentials configured");
+
+        config.auth.token = Some("secret".to_string());
+        config.validate().unwrap();
+
+        config.auth.mode = AuthMode::Jwt;
+        assert!(config.validate().is_err());
+
+        config.auth.mode = AuthMode::ApiKeys;
+        for requests_per_second in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            config.rate_limit.per_ip = Some(RateLimit {
+                requests_per_second,
+                burst: None,
+            });
+            assert!(config.validate().is_err());
+        }
+        config.rate_limit.per_ip = Some(RateLimit {
+            requests_per_second: 1.0,
+            burst: Some(f64::NAN),
+        });
+        assert!(config.validate().is_err());
+
+        config.rate_limit.per_ip = None;
+        config.batch.max_transactions = 0;
+        assert!(config.validate().is_err());
+
+        config.batch.max_transactions = 1;
+        config.batch.brotli.window = 25;
+        assert!(config.validate().is_err());
+
+        config.batch.brotli.window = 22;
+        config.batch.format = BatchFormat::Span;
+        config.validate().unwrap();
+        config.batch.max_compressed_bytes = Some(100_000);
+        assert!(config.validate().is_err());
+
+        config.batch.max_compressed_bytes = None;
+        config.l1.sequencing_window = config.l1.window_margin;
+        assert!(config.validate().is_err());
+
+        config.l1.sequencing_window = 3600;
+        config.l1.reorg_depth = 0;
+        assert!(config.validate().is_err());
+
+        config.l1.reorg_depth = 64;
+        config.l1.deposit_deadline = config.l1.deposit_confirmations;
+        assert!(config.validate().is_err());
+
+        config.l1.deposit_deadline = 300;
+        config.l1.output_oracle = Some(Address::repeat_byte(0x0a));
+        assert!(config.validate().is_err());
+        config.l1.proposer_key =
+            Some("0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d".to_string());
+        assert!(config.validate().is_err());
+        config.l1.batch_inbox = Some(Address::repeat_byte(0xff));
+        config.l1.signer_key =
+            Some("0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".to_string());
+        config.validate().unwrap();
+        config.l1.output_interval_secs = 0;
+        assert!(config.validate().is_err());
+
+        config.l1.output_interval_secs = 300;
+        config.submission.signing_key = Some("0x1234".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_verify_mode() {
+        let cli = cli(&[
+            "--batch-inbox",
+            "0x00000000000000000000000000000000000000aa",
+            "verify",
+            "--from-l1-block",
+            "100",
+        ]);
+        let Some(Command::Verify(args)) = &cli.command else {
+            panic!("expected the verify command");
+        };
+        assert_eq!(args.from_l1_block, Some(100));
+        assert_eq!(args.to_l1_block, None);
+
+        // No credentials or signer key needed, but the inbox is
+        let config = Config::load(&cli).unwrap();
+        assert!(config.validate().is_err());
+        let mut config = Config::default();
+        assert!(config.validate_verifier().is_err());
+        config.l1.batch_inbox = Some(Address::repeat_byte(0xaa));
+        config.validate_verifier().unwrap();
+    }
+
+    #[test]
+    fn test_print_config_redacts_token() {
+        let mut config = Config::default();
+        config.auth.token = Some("secret".to_string());
+        let rendered = config.to_toml();
+        assert!(!rendered.contains("secret"));
+        assert!(rendered.contains("<redacted>"));
+
+        let parsed: Config = toml::from_str(&rendered.replace("<redacted>", "x")).unwrap();
+        assert_eq!(parsed.server.listen_addr, config.server.listen_addr);
+
+        // An invalid config still resolves, so it can be printed
+        let cli = cli(&["--print-config", "--batch-size", "0"]);
+        assert!(Config::load(&cli).is_err());
+        let config = Config::resolve(&cli).unwrap();
+        assert!(config.to_toml().contains("max_transactions = 0"));
+        assert!(config.validate_for(&cli).is_err());
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields() {
+        assert!(toml::from_str::<Config>("[batch]\nsize = 5").is_err());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod api;
 pub mod client;
+pub mod config;
 pub mod models;
 pub mod services;
+pub mod telemetry;
 pub mod types;
@@ -1,47 +1,420 @@
+use alloy::network::EthereumWallet;
 use alloy::providers::ProviderBuilder;
+use alloy::signers::local::PrivateKeySigner;
+use axum::extract::Request;
 use axum::middleware;
 use axum::{
-    routing::{get, post},
+    routing::{delete, get, post},
     Router,
 };
-use sequencer::api::auth::auth_middleware;
+use clap::Parser;
+use sequencer::api::admin;
+use sequencer::api::api_keys::Scope;
+use sequencer::api::auth::{auth_middleware, feed_auth_middleware, Authenticator, ScopedAuth};
 use sequencer::api::cors::create_cors_middleware;
-use sequencer::api::handler::{send_transaction, transaction_feed};
+use sequencer::api::handler::{send_transaction, send_transactions, transaction_feed};
+use sequencer::api::health::{healthz, readyz, status};
+use sequencer::api::metrics::{prometheus_metrics, submission_metrics_middleware};
+use sequencer::api::outbox::withdrawal_proof;
+use sequencer::api::outputs::get_output;
+use sequencer::api::rate_limit::{
+    ip_rate_limit_middleware, sender_rate_limit_middleware, SubmissionRateLimiter,
+};
+use sequencer::config::{Cli, Command, Config, VerifyArgs};
+use sequencer::services::batch_store::BatchStore;
+use sequencer::services::batch_submitter::BatchSubmitter;
+use sequencer::services::beacon::BeaconClient;
+use sequencer::services::deposits::DepositWatcher;
+use sequencer::services::derivation::{verify_posted_batches, Derivation, InboxScanner};
+use sequencer::services::l1_head::{L1Head, L1HeadTracker};
+use sequencer::services::metrics::Metrics;
+use sequencer::services::outbox::WithdrawalIndex;
+use sequencer::services::outputs::{OutputProposer, OutputStore};
 use sequencer::services::queue_service::setup_queue;
+use sequencer::services::status::SequencerStatus;
+use sequencer::telemetry;
 use sequencer::types::AppState;
-use std::env;
 use std::error::Error;
+use std::future::IntoFuture;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     dotenv::dotenv().ok();
-    let rpc_url = env::var("RPC_URL")
-        .unwrap_or_else(|_| "https://eth.merkle.io".to_string())
-        .parse()?;
-    let provider = ProviderBuilder::new().on_http(rpc_url);
+    let cli = Cli::parse();
+    let config = Config::resolve(&cli)?;
+
+    if cli.print_config {
+        print!("{}", config.to_toml());
+        config.validate_for(&cli)?;
+        return Ok(());
+    }
+    config.validate_for(&cli)?;
+
+    let _telemetry = telemetry::init(&config.logging)?;
+
+    if let Some(Command::Verify(args)) = &cli.command {
+        return verify(&config, args).await;
+    }
+
+    std::fs::create_dir_all(&config.storage.data_dir)?;
+
+    let rpc_url: reqwest::Url = config.rpc.url.parse()?;
+    let provider = ProviderBuilder::new().on_http(rpc_url.clone());
+    let metrics = Arc::new(Metrics::new());
+    let sequencer_status = Arc::new(SequencerStatus::new(
+        config.storage.data_dir.clone(),
+        Duration::from_secs(config.health.l1_stall_threshold_secs),
+    ));
+
+    let store = Arc::new(BatchStore::open(&config.storage.data_dir)?);
+    let outputs = Arc::new(OutputStore::open(&config.storage.data_dir)?);
+
+    let l1_head = Arc::new(L1Head::new());
+    let mut tracker = L1HeadTracker::new(
+        provider.clone(),
+        l1_head.clone(),
+        Duration::from_secs(config.l1.head_poll_secs),
+        metrics.clone(),
+    );
 
     let queue_provider = provider.clone();
-    let (queue_handle, mut processor) = setup_queue(queue_provider);
+    let (queue_handle, processor, mut builder) = setup_queue(
+        queue_provider,
+        &config,
+        store.clone(),
+        metrics.clone(),
+        sequencer_status.clone(),
+        config.batch.codec()?,
+    )?;
+    let mut processor = processor.with_l1_head(l1_head.clone());
+    let withdrawals = Arc::new(WithdrawalIndex::load(store.clone())?);
+    builder = builder.with_withdrawal_index(withdrawals.clone());
+
+    let mut l1_submitter = None;
+    let mut submitter_task = None;
+    if let (Some(inbox), Some(key)) = (config.l1.batch_inbox, &config.l1.signer_key) {
+        let signer: PrivateKeySigner = key.parse()?;
+        info!(%inbox, poster = %signer.address(), "posting batches to L1");
+        let l1_provider = ProviderBuilder::new()
+            .wallet(EthereumWallet::from(signer))
+            .on_http(rpc_url.clone());
+
+        let (batch_tx, batch_rx) = tokio::sync::mpsc::channel(config.channels.batch_capacity);
+        builder = builder.with_batch_submitter(batch_tx);
+        let (reorg_tx, reorg_rx) = tokio::sync::mpsc::channel(16);
+        tracker = tracker.with_reorg_detection(config.l1.reorg_depth, reorg_tx);
+        let mut submitter = BatchSubmitter::new(
+            l1_provider,
+            inbox,
+            batch_rx,
+            store.clone(),
+            metrics.clone(),
+            sequencer_status.clone(),
+        )
+        .with_sequencing_window(
+            l1_head.clone(),
+            config.l1.sequencing_window(),
+            config.l1.window_margin,
+        )
+        .with_reorg_recovery(reorg_rx, queue_handle.clone())
+        .with_backlog(store.unposted()?);
+        l1_submitter = Some(submitter.handle());
+        submitter_task = Some(tokio::spawn(async move {
+            submitter.run().await;
+        }));
+    } else {
+        info!("L1 batch posting disabled (no l1.batch_inbox/l1.signer_key)");
+    }
+
+    if let (Some(oracle), Some(key)) = (config.l1.output_oracle, &config.l1.proposer_key) {
+        let signer: PrivateKeySigner = key.parse()?;
+        info!(%oracle, proposer = %signer.address(), "proposing outputs to L1");
+        let proposer = OutputProposer::new(
+            ProviderBuilder::new()
+                .wallet(EthereumWallet::from(signer))
+                .on_http(rpc_url),
+            oracle,
+            store.clone(),
+            outputs.clone(),
+            metrics.clone(),
+            Duration::from_secs(config.l1.output_interval_secs),
+        );
+        tokio::spawn(async move {
+            proposer.run().await;
+        });
+    }
 
+    if let Some(bridge) = config.l1.bridge {
+        info!(%bridge, "reading deposits from the L1 bridge");
+        let watcher = DepositWatcher::new(
+            provider.clone(),
+            bridge,
+            queue_handle.clone(),
+            l1_head.clone(),
+            config.l1.deposit_confirmations,
+            Duration::from_secs(config.l1.head_poll_secs),
+        );
+        tokio::spawn(async move {
+            watcher.run().await;
+        });
+    }
+
+    tokio::spawn(async move {
+        tracker.run().await;
+    });
     tokio::spawn(async move {
         processor.run().await;
     });
+    tokio::spawn(async move {
+        builder.run().await;
+    });
+
+    let auth = Arc::new(Authenticator::from_config(&config.auth)?);
 
+    let rate_limiter = Arc::new(SubmissionRateLimiter::new(
+        config.rate_limit.per_ip,
+        config.rate_limit.per_sender,
+        config.rate_limit.trusted_proxies,
+    ));
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+    let signer = match &config.submission.signing_key {
+        Some(key) => {
+            let signer: PrivateKeySigner = key.parse()?;
+            info!(sequencer = %signer.address(), "signing transaction confirmations");
+            Some(signer)
+        }
+        None => None,
+    };
     let state = AppState {
-        queue: queue_handle,
+        queue: queue_handle.clone(),
         provider,
+        auth: auth.clone(),
+        public_feed: config.auth.public_feed,
+        submission: config.submission.clone(),
+        signer,
+        l1_head,
+        sequencing_window: config.l1.sequencing_window(),
+        metrics: metrics.clone(),
+        status: sequencer_status,
+        withdrawals,
+        outputs,
+        l1_submitter,
+        shutdown: shutdown_rx.clone(),
     };
 
+    let feed = Router::new()
+        .route("/transaction_feed", get(transaction_feed))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            feed_auth_middleware,
+        ));
+
+    let admin = Router::new()
+        .route("/admin/batch", post(admin::force_batch))
+        .route("/admin/admission/pause", post(admin::pause_admission))
+        .route("/admin/admission/resume", post(admin::resume_admission))
+        .route("/admin/l1_posting/pause", post(admin::pause_l1_posting))
+        .route("/admin/l1_posting/resume", post(admin::resume_l1_posting))
+        .route("/admin/pending", get(admin::list_pending))
+        .route(
+            "/admin/pending/{from}/{nonce}",
+            delete(admin::evict_transaction),
+        )
+        .route(
+            "/admin/batch_params",
+            get(admin::get_batch_params).patch(admin::update_batch_params),
+        )
+        .layer(middleware::from_fn_with_state(
+            ScopedAuth::new(auth.clone(), Scope::Admin),
+            auth_middleware,
+        ));
+
+    // Unauthenticated, for load balancers and orchestrators
+    let health = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/status", get(status));
+
+    let read = Router::new()
+        .route("/metrics", get(prometheus_metrics))
+        .route("/withdrawal/{hash}/proof", get(withdrawal_proof))
+        .route("/output/{block}", get(get_output))
+        .layer(middleware::from_fn_with_state(
+            ScopedAuth::new(auth.clone(), Scope::Read),
+            auth_middleware,
+        ));
+
     let app = Router::new()
         .route("/send_transaction", post(send_transaction))
-        .route("/transaction_feed", get(transaction_feed))
-        .layer(middleware::from_fn(auth_middleware))
-        .layer(create_cors_middleware())
+        .route("/send_transactions", post(send_transactions))
+        .layer(middleware::from_fn_with_state(
+            rate_limiter.clone(),
+            sender_rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            ScopedAuth::new(auth, Scope::Submit),
+            auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            rate_limiter,
+            ip_rate_limit_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            metrics,
+            submission_metrics_middleware,
+        ))
+        .merge(feed)
+        .merge(read)
+        .merge(health)
+        .merge(admin)
+        .layer(create_cors_middleware(&config.server.cors_origins))
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(TraceLayer::new_for_http().make_span_with(http_span))
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        )
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await?;
-    println!("Server running on http://0.0.0.0:3001");
-    axum::serve(listener, app).await?;
+    let listener = tokio::net::TcpListener::bind(config.server.listen_addr).await?;
+    info!("Server running on http://{}", config.server.listen_addr);
+    let mut server = tokio::spawn(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_requested(shutdown_rx))
+        .into_future(),
+    );
+
+    tokio::select! {
+        _ = shutdown_signal() => {}
+        result = &mut server => {
+            result??;
+            return Ok(());
+        }
+    }
+
+    // New submissions get 503 and feeds are closed while in-flight requests
+    // finish; then the queue is flushed and the submitter drains
+    let deadline = Duration::from_secs(config.shutdown.deadline_secs);
+    info!(deadline_secs = deadline.as_secs(), "shutting down");
+    queue_handle.begin_shutdown();
+    let _ = shutdown_tx.send(true);
 
+    let drained = tokio::time::timeout(deadline, async {
+        if let Ok(Err(e)) = server.await {
+            error!(error = %e, "server error while draining");
+        }
+        match queue_handle.shutdown().await {
+            Ok(batches) => info!(?batches, "final batches built"),
+            Err(e) => error!(error = %e, "failed to flush queue"),
+        }
+        if let Some(task) = submitter_task {
+            let _ = task.await;
+        }
+    })
+    .await;
+
+    match drained {
+        Ok(()) => info!("shutdown complete"),
+        Err(_) => warn!("shutdown deadline reached; unposted batches stay in the store"),
+    }
     Ok(())
 }
+
+/// `sequencer verify`: derives the chain from L1 and exits non-zero if it
+/// doesn't match the batch store.
+async fn verify(config: &Config, args: &VerifyArgs) -> Result<(), Box<dyn Error>> {
+    let inbox = config
+        .l1
+        .batch_inbox
+        .ok_or("l1.batch_inbox must be set to verify batches")?;
+    let poster = match (args.poster, &config.l1.signer_key) {
+        (Some(poster), _) => Some(poster),
+        (None, Some(key)) => Some(key.parse::<PrivateKeySigner>()?.address()),
+        (None, None) => None,
+    };
+    let provider = ProviderBuilder::new().on_http(config.rpc.url.parse()?);
+    let mut scanner = InboxScanner::new(provider, inbox, poster);
+    if let Some(url) = &args.beacon_url {
+        scanner = scanner.with_beacon(BeaconClient::new(url));
+    }
+    let store = BatchStore::open(&config.storage.data_dir)?;
+
+    let derivation = Derivation::new(config.batch.decoder()?)
+        .with_sequencing_window(config.l1.sequencing_window());
+    let divergences = verify_posted_batches(
+        &scanner,
+        derivation,
+        &store,
+        args.from_l1_block,
+        args.to_l1_block,
+    )
+    .await?;
+    if divergences.is_empty() {
+        info!("L1 matches the batch store");
+        return Ok(());
+    }
+    for divergence in &divergences {
+        error!(%divergence, "divergence");
+    }
+    error!(
+        count = divergences.len(),
+        "L1 doesn't match the batch store"
+    );
+    std::process::exit(1);
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
+
+async fn shutdown_requested(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|shutting_down| *shutting_down).await;
+}
+
+/// Root span for every HTTP request, tagged with its `x-request-id`.
+fn http_span(req: &Request) -> tracing::Span {
+    let request_id = req
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "http",
+        method = %req.method(),
+        path = req.uri().path(),
+        request_id,
+    )
+}
@@ -0,0 +1,304 @@
+use super::block::Block;
+use super::codec::{BatchCodec, PayloadFormat};
+use super::compressor::{self, ChannelCompressor};
+use super::outbox::OutboxTree;
+use super::transaction::L2Transaction;
+use alloy::primitives::B256;
+use alloy::rlp::{BufMut, Decodable, Encodable, Header};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError {
+    #[error("Failed to encode batch: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Failed to compress batch: {0}")]
+    Compression(#[from] std::io::Error),
+    #[error("Failed to decompress batch: {0}")]
+    Decompression(std::io::Error),
+    #[error("Invalid batch header: {0}")]
+    Header(String),
+    #[error("Failed to decode transaction: {0}")]
+    Decoding(#[from] alloy::rlp::Error),
+    #[error("Failed to decode span batch: {0}")]
+    SpanBatch(#[from] super::span_batch::SpanBatchError),
+}
+
+/// How the batcher lays out transactions in the payloads it writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum BatchFormat {
+    /// RLP transactions back to back
+    #[default]
+    Flat,
+    /// Shared fields factored out across the whole batch
+    Span,
+}
+
+impl From<BatchFormat> for PayloadFormat {
+    fn from(format: BatchFormat) -> Self {
+        match format {
+            BatchFormat::Flat => PayloadFormat::Flat,
+            BatchFormat::Span => PayloadFormat::Span,
+        }
+    }
+}
+
+/// One block's share of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockFrame {
+    pub timestamp: u64,
+    pub l1_origin: u64,
+    pub transactions: u64,
+}
+
+/// The block boundaries of a batch, written uncompressed after the payload
+/// header so a verifier can rebuild the blocks from L1: the first block's
+/// number, then each block's timestamp, L1 origin and transaction count,
+/// then the root of the batch's outbox tree. Batches framed before
+/// withdrawals end without the root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockFrames {
+    pub first_number: u64,
+    pub frames: Vec<BlockFrame>,
+    /// Commits L1 to the batch's withdrawals; missing from batches framed
+    /// before withdrawals, which aren't checked
+    pub outbox_root: Option<B256>,
+}
+
+impl BlockFrames {
+    pub fn of(blocks: &[Block]) -> Self {
+        Self {
+            first_number: blocks.first().map_or(0, |b| b.number),
+            frames: blocks
+                .iter()
+                .map(|b| BlockFrame {
+                    timestamp: b.timestamp,
+                    l1_origin: b.l1_origin,
+                    transactions: b.transactions.len() as u64,
+                })
+                .collect(),
+            outbox_root: Some(OutboxTree::of(blocks).root()),
+        }
+    }
+
+    /// Splits `transactions` back into blocks chained onto `parent_hash`,
+    /// the way the queue sealed them. Fails unless the frames account for
+    /// every transaction and the blocks' withdrawals match any outbox root.
+    pub fn rebuild(
+        &self,
+        transactions: Vec<L2Transaction>,
+        parent_hash: B256,
+    ) -> Result<Vec<Block>, BatchError> {
+        // Frames come off L1, where anyone can post, so nothing here may
+        // overflow
+        let framed = self
+            .frames
+            .iter()
+            .try_fold(0u64, |sum, f| sum.checked_add(f.transactions));
+        if framed != Some(transactions.len() as u64) {
+            return Err(BatchError::Header(format!(
+                "block frames don't cover the payload's {} transactions",
+                transactions.len()
+            )));
+        }
+
+        let mut transactions = transactions.into_iter();
+        let mut parent_hash = parent_hash;
+        let mut blocks = Vec::with_capacity(self.frames.len());
+        for (i, frame) in self.frames.iter().enumerate() {
+            let number = self.first_number.checked_add(i as u64).ok_or_else(|| {
+                BatchError::Header(format!(
+                    "{} blocks from block {} run past the last block number",
+                    self.frames.len(),
+                    self.first_number
+                ))
+            })?;
+            let block = Block::new(
+                number,
+                transactions
+                    .by_ref()
+                    .take(frame.transactions as usize)
+                    .collect(),
+                parent_hash,
+                B256::ZERO,
+                frame.timestamp,
+            )
+            .with_l1_origin(frame.l1_origin);
+            parent_hash = block.hash();
+            blocks.push(block);
+        }
+
+        if let Some(expected) = self.outbox_root {
+            let outbox_root = OutboxTree::of(&blocks).root();
+            if outbox_root != expected {
+                return Err(BatchError::Header(format!(
+                    "outbox root is {}, withdrawals hash to {}",
+                    expected, outbox_root
+                )));
+            }
+        }
+        Ok(blocks)
+    }
+}
+
+impl Encodable for BlockFrame {
+    fn encode(&self, out: &mut dyn BufMut) {
+        Header {
+            list: true,
+            payload_length: self.rlp_payload_length(),
+        }
+        .encode(out);
+        self.timestamp.encode(out);
+        self.l1_origin.encode(out);
+        self.transactions.encode(out);
+    }
+
+    fn length(&self) -> usize {
+        let payload_length = self.rlp_payload_length();
+        payload_length + alloy::rlp::length_of_length(payload_length)
+    }
+}
+
+impl BlockFrame {
+    fn rlp_payload_length(&self) -> usize {
+        self.timestamp.length() + self.l1_origin.length() + self.transactions.length()
+    }
+}
+
+impl Decodable for BlockFrame {
+    fn decode(buf: &mut &[u8]) -> alloy::rlp::Result<Self> {
+        let mut fields = Header::decode_bytes(buf, true)?;
+        let buf = &mut fields;
+        let frame = Self {
+            timestamp: Decodable::decode(buf)?,
+            l1_origin: Decodable::decode(buf)?,
+            transactions: Decodable::decode(buf)?,
+        };
+        if !buf.is_empty() {
+            return Err(alloy::rlp::Error::Custom("trailing bytes in block frame"));
+        }
+        Ok(frame)
+    }
+}
+
+impl BlockFrames {
+    fn rlp_payload_length(&self) -> usize {
+        self.first_number.length()
+            + self.frames.length()
+            + self.outbox_root.as_ref().map_or(0, Encodable::length)
+    }
+}
+
+impl Encodable for BlockFrames {
+    fn encode(&self, out: &mut dyn BufMut) {
+        Header {
+            list: true,
+            payload_length: self.rlp_payload_length(),
+        }
+        .encode(out);
+        self.first_number.encode(out);
+        self.frames.encode(out);
+        if let Some(outbox_root) = &self.outbox_root {
+            outbox_root.encode(out);
+        }
+    }
+
+    fn length(&self) -> usize {
+        let payload_length = self.rlp_payload_length();
+        payload_length + alloy::rlp::length_of_length(payload_length)
+    }
+}
+
+impl Decodable for BlockFrames {
+    fn decode(buf: &mut &[u8]) -> alloy::rlp::Result<Self> {
+        let mut fields = Header::decode_bytes(buf, true)?;
+        let buf = &mut fields;
+        let frames = Self {
+            first_number: Decodable::decode(buf)?,
+            frames: Decodable::decode(buf)?,
+            outbox_root: match buf.is_empty() {
+                true => None,
+                false => Some(Decodable::decode(buf)?),
+            },
+        };
+        if !buf.is_empty() {
+            return Err(alloy::rlp::Error::Custom("trailing bytes in block frames"));
+        }
+        Ok(frames)
+    }
+}
+
+/// A group of sealed blocks together with their compressed payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Batch {
+    pub index: u64,
+    pub blocks: Vec<Block>,
+    pub data: Vec<u8>,
+    /// Size of the encoded transactions before compression
+    pub uncompressed_size: usize,
+}
+
+impl Batch {
+    pub fn new(index: u64, blocks: Vec<Block>, data: Vec<u8>, uncompressed_size: usize) -> Self {
+        Self {
+            index,
+            blocks,
+            data,
+            uncompressed_size,
+        }
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.blocks.iter().map(|b| b.transactions.len()).sum()
+    }
+}
+
+/// Blocks that have a batch index, and their transactions' compressed
+/// stream if the batcher compressed as it went. Finishing the payload is
+/// left to `build` off the queue processor.
+#[derive(Debug)]
+pub struct SealedBatch {
+    pub index: u64,
+    pub blocks: Vec<Block>,
+    /// Holds exactly the blocks' transactions, in order
+    pub compressor: Option<ChannelCompressor>,
+}
+
+impl SealedBatch {
+    /// Compresses the blocks' transactions, or finishes the batcher's stream.
+    /// Blocks the thread; run it on the blocking pool.
+    #[tracing::instrument(
+        name = "build_batch",
+        skip_all,
+        fields(batch_index = self.index, input_bytes, output_bytes)
+    )]
+    pub fn build(self, codec: &dyn BatchCodec, format: BatchFormat) -> Result<Batch, BatchError> {
+        let compressed = match format {
+            BatchFormat::Flat => {
+                let compressor = match self.compressor {
+                    Some(compressor) => compressor,
+                    None => {
+                        let mut compressor = ChannelCompressor::new(codec)?;
+                        for tx in self.blocks.iter().flat_map(|b| &b.transactions) {
+                            compressor.add(tx)?;
+                        }
+                        compressor
+                    }
+                };
+                compressor.finish(&BlockFrames::of(&self.blocks))?
+            }
+            BatchFormat::Span => compressor::compress_span_batch(&self.blocks, codec)?,
+        };
+
+        let span = tracing::Span::current();
+        span.record("input_bytes", compressed.uncompressed_size);
+        span.record("output_bytes", compressed.data.len());
+        Ok(Batch::new(
+            self.index,
+            self.blocks,
+            compressed.data,
+            compressed.uncompressed_size,
+        ))
+    }
+}
@@ -1,26 +1,60 @@
 use super::transaction::L2Transaction;
-use alloy::primitives::B256;
+use alloy::primitives::{keccak256, B256};
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
+    pub number: u64,
     pub transactions: Vec<L2Transaction>,
     pub parent_hash: B256,
     pub state_root: B256,
     pub timestamp: u64,
+    /// L1 block the sequencer had seen when sealing this one. Its
+    /// transactions' `l1_block_number`s are at most this, and no older than
+    /// the sequencing window allows.
+    pub l1_origin: u64,
 }
 
 impl Block {
     pub fn new(
+        number: u64,
         transactions: Vec<L2Transaction>,
         parent_hash: B256,
         state_root: B256,
         timestamp: u64,
     ) -> Self {
         Self {
+            number,
             transactions,
             parent_hash,
             state_root,
             timestamp,
+            l1_origin: 0,
         }
     }
+
+    pub fn with_l1_origin(mut self, l1_origin: u64) -> Self {
+        self.l1_origin = l1_origin;
+        self
+    }
+
+    /// Hash over the header fields and the ordered transaction hashes.
+    pub fn hash(&self) -> B256 {
+        let mut preimage = Vec::with_capacity(8 + 32 + 32 + 8 + 8 + 32);
+        preimage.extend_from_slice(&self.number.to_be_bytes());
+        preimage.extend_from_slice(self.parent_hash.as_slice());
+        preimage.extend_from_slice(self.state_root.as_slice());
+        preimage.extend_from_slice(&self.timestamp.to_be_bytes());
+        preimage.extend_from_slice(&self.l1_origin.to_be_bytes());
+        preimage.extend_from_slice(self.transactions_root().as_slice());
+        keccak256(preimage)
+    }
+
+    pub fn transactions_root(&self) -> B256 {
+        let mut preimage = Vec::with_capacity(self.transactions.len() * 32);
+        for tx in &self.transactions {
+            preimage.extend_from_slice(tx.hash().as_slice());
+        }
+        keccak256(preimage)
+    }
 }
@@ -0,0 +1,406 @@
+use super::batch::{BatchError, BlockFrames};
+use alloy::rlp::Decodable;
+use brotli::{CompressorWriter, Decompressor};
+use std::io::{self, Read, Write};
+
+const BROTLI_BUFFER: usize = 4096;
+
+/// Most bytes a payload's codec stream may expand to. Payloads come from
+/// L1 calldata anyone can post, so a small stream must not be able to
+/// exhaust memory.
+pub const MAX_DECOMPRESSED: usize = 32 * 1024 * 1024;
+
+/// The first header byte: how transactions are laid out in the codec's
+/// stream. The values are part of the L1 format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PayloadFormat {
+    /// bincode transactions back to back; read but no longer written
+    Bincode = 0,
+    /// `Flat` without block frames; read but no longer written
+    UnframedFlat = 1,
+    /// `Span` without block frames; read but no longer written
+    UnframedSpan = 2,
+    /// RLP transactions back to back
+    Flat = 3,
+    /// See `span_batch`
+    Span = 4,
+}
+
+impl PayloadFormat {
+    /// Whether block frames follow the header.
+    pub fn is_framed(self) -> bool {
+        matches!(self, PayloadFormat::Flat | PayloadFormat::Span)
+    }
+
+    pub fn is_span(self) -> bool {
+        matches!(self, PayloadFormat::Span | PayloadFormat::UnframedSpan)
+    }
+}
+
+impl TryFrom<u8> for PayloadFormat {
+    type Error = BatchError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(PayloadFormat::Bincode),
+            1 => Ok(PayloadFormat::UnframedFlat),
+            2 => Ok(PayloadFormat::UnframedSpan),
+            3 => Ok(PayloadFormat::Flat),
+            4 => Ok(PayloadFormat::Span),
+            other => Err(BatchError::Header(format!(
+                "unsupported payload format {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// Every batch payload starts with the payload format, then the codec id.
+/// Block frames follow in framed formats, and then the codec's stream.
+pub fn header(format: PayloadFormat, codec: CodecId) -> Vec<u8> {
+    vec![format as u8, codec as u8]
+}
+
+/// The codec byte of a batch header. The values are part of the L1 format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CodecId {
+    None = 0,
+    Brotli = 1,
+    Zstd = 2,
+}
+
+impl TryFrom<u8> for CodecId {
+    type Error = BatchError;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(CodecId::None),
+            1 => Ok(CodecId::Brotli),
+            2 => Ok(CodecId::Zstd),
+            other => Err(BatchError::Header(format!("unknown codec {}", other))),
+        }
+    }
+}
+
+/// Splits a batch payload into its format, its codec and the compressed
+/// bytes after the header.
+pub fn split_header(data: &[u8]) -> Result<(PayloadFormat, CodecId, &[u8]), BatchError> {
+    match data {
+        [format, codec, rest @ ..] => Ok((
+            PayloadFormat::try_from(*format)?,
+            CodecId::try_from(*codec)?,
+            rest,
+        )),
+        _ => Err(BatchError::Header("payload too short".to_string())),
+    }
+}
+
+/// A batch payload with its codec stream decompressed.
+#[derive(Debug)]
+pub struct DecodedPayload {
+    pub format: PayloadFormat,
+    /// Missing from formats written before block frames
+    pub frames: Option<BlockFrames>,
+    /// Transactions in the layout `format` names
+    pub encoded: Vec<u8>,
+}
+
+/// Decompresses payloads with whichever codec their header names, so
+/// batches written before a codec change stay readable.
+#[derive(Debug, Clone, Default)]
+pub struct PayloadDecoder {
+    brotli: Brotli,
+    zstd: Zstd,
+}
+
+impl PayloadDecoder {
+    /// `zstd_dictionary` is the one zstd payloads were written with, if any.
+    pub fn new(zstd_dictionary: Option<Vec<u8>>) -> Self {
+        Self {
+            brotli: Brotli::default(),
+            zstd: Zstd {
+                dictionary: zstd_dictionary,
+                ..Zstd::default()
+            },
+        }
+    }
+
+    fn codec(&self, id: CodecId) -> &dyn BatchCodec {
+        match id {
+            CodecId::None => &NoCompression,
+            CodecId::Brotli => &self.brotli,
+            CodecId::Zstd => &self.zstd,
+        }
+    }
+}
+
+/// Decompresses a batch payload, header included.
+pub fn decode_payload(data: &[u8], decoder: &PayloadDecoder) -> Result<DecodedPayload, BatchError> {
+    let (format, id, mut rest) = split_header(data)?;
+    let codec = decoder.codec(id);
+    let frames = match format.is_framed() {
+        true => Some(BlockFrames::decode(&mut rest)?),
+        false => None,
+    };
+    Ok(DecodedPayload {
+        format,
+        frames,
+        encoded: codec.decode(rest).map_err(BatchError::Decompression)?,
+    })
+}
+
+/// Compresses a batch as it's written, one codec stream per batch.
+pub trait Encoder: Write + Send + Sync {
+    /// Output so far. `flush` brings it up to date with everything written.
+    fn written(&self) -> usize;
+
+    /// Ends the stream and returns the output.
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>>;
+}
+
+/// A compression scheme for batch payloads.
+pub trait BatchCodec: Send + Sync {
+    fn id(&self) -> CodecId;
+
+    /// Starts a stream appended to `output`.
+    fn encoder(&self, output: Vec<u8>) -> io::Result<Box<dyn Encoder>>;
+
+    /// Decompresses a stream produced by this codec's encoder, failing if
+    /// it expands past `MAX_DECOMPRESSED`.
+    fn decode(&self, compressed: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Stores payloads as they are.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoCompression;
+
+impl BatchCodec for NoCompression {
+    fn id(&self) -> CodecId {
+        CodecId::None
+    }
+
+    fn encoder(&self, output: Vec<u8>) -> io::Result<Box<dyn Encoder>> {
+        Ok(Box::new(output))
+    }
+
+    fn decode(&self, compressed: &[u8]) -> io::Result<Vec<u8>> {
+        read_limited(compressed)
+    }
+}
+
+impl Encoder for Vec<u8> {
+    fn written(&self) -> usize {
+        self.len()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        Ok(*self)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Brotli {
+    /// 0 to 11
+    pub quality: u32,
+    /// Log2 of the window size, 10 to 24
+    pub window: u32,
+}
+
+impl Default for Brotli {
+    fn default() -> Self {
+        Self {
+            quality: 3,
+            window: 22,
+        }
+    }
+}
+
+impl BatchCodec for Brotli {
+    fn id(&self) -> CodecId {
+        CodecId::Brotli
+    }
+
+    fn encoder(&self, output: Vec<u8>) -> io::Result<Box<dyn Encoder>> {
+        Ok(Box::new(CompressorWriter::new(
+            output,
+            BROTLI_BUFFER,
+            self.quality,
+            self.window,
+        )))
+    }
+
+    fn decode(&self, compressed: &[u8]) -> io::Result<Vec<u8>> {
+        read_limited(Decompressor::new(compressed, BROTLI_BUFFER))
+    }
+}
+
+impl Encoder for CompressorWriter<Vec<u8>> {
+    fn written(&self) -> usize {
+        self.get_ref().len()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        Ok(self.into_inner())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Zstd {
+    /// 1 to 22
+    pub level: i32,
+    /// Trained on typical batches; decoders need the same one
+    pub dictionary: Option<Vec<u8>>,
+}
+
+impl BatchCodec for Zstd {
+    fn id(&self) -> CodecId {
+        CodecId::Zstd
+    }
+
+    fn encoder(&self, output: Vec<u8>) -> io::Result<Box<dyn Encoder>> {
+        let encoder = match &self.dictionary {
+            Some(dictionary) => {
+                zstd::stream::write::Encoder::with_dictionary(output, self.level, dictionary)?
+            }
+            None => zstd::stream::write::Encoder::new(output, self.level)?,
+        };
+        Ok(Box::new(encoder))
+    }
+
+    fn decode(&self, compressed: &[u8]) -> io::Result<Vec<u8>> {
+        match &self.dictionary {
+            Some(dictionary) => read_limited(zstd::stream::read::Decoder::with_dictionary(
+                compressed, dictionary,
+            )?),
+            None => read_limited(zstd::stream::read::Decoder::new(compressed)?),
+        }
+    }
+}
+
+/// Reads a decompressed stream to the end, up to `MAX_DECOMPRESSED` bytes.
+fn read_limited(reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    reader
+        .take(MAX_DECOMPRESSED as u64 + 1)
+        .read_to_end(&mut output)?;
+    if output.len() > MAX_DECOMPRESSED {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "payload decompresses to more than {} bytes",
+                MAX_DECOMPRESSED
+            ),
+        ));
+    }
+    Ok(output)
+}
+
+impl Encoder for zstd::stream::write::Encoder<'static, Vec<u8>> {
+    fn written(&self) -> usize {
+        self.get_ref().len()
+    }
+
+    fn finish(self: Box<Self>) -> io::Result<Vec<u8>> {
+        zstd::stream::write::Encoder::finish(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let input: Vec<u8> = (0..20_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let dictionary = zstd::dict::from_continuous(&input, &[4_000; 20], 2_048).unwrap();
+        let with_dictionary = PayloadDecoder::new(Some(dictionary.clone()));
+        let codecs: Vec<(Box<dyn BatchCodec>, PayloadDecoder)> = vec![
+            (Box::new(NoCompression), PayloadDecoder::default()),
+            (Box::new(Brotli::default()), PayloadDecoder::default()),
+            (
+                Box::new(Zstd {
+                    level: 3,
+                    dictionary: None,
+                }),
+                PayloadDecoder::default(),
+            ),
+            (
+                Box::new(Zstd {
+                    level: 3,
+                    dictionary: Some(dictionary),
+                }),
+                with_dictionary,
+            ),
+        ];
+
+        for (codec, decoder) in codecs {
+            let mut encoder = codec
+                .encoder(header(PayloadFormat::Bincode, codec.id()))
+                .unwrap();
+            for chunk in input.chunks(1_000) {
+                encoder.write_all(chunk).unwrap();
+            }
+            let payload = encoder.finish().unwrap();
+            assert_eq!(payload[1], codec.id() as u8);
+            assert_eq!(
+                decode_payload(&payload, &decoder).unwrap().encoded,
+                input,
+                "{:?}",
+                codec.id()
+            );
+        }
+    }
+
+    #[test]
+    fn test_split_header() {
+        let (format, codec, rest) = split_header(&[3, 2, 0xaa]).unwrap();
+        assert_eq!(format, PayloadFormat::Flat);
+        assert_eq!(codec, CodecId::Zstd);
+        assert_eq!(rest, [0xaa]);
+        assert_eq!(split_header(&[0, 1]).unwrap().0, PayloadFormat::Bincode);
+        assert_eq!(
+            split_header(&[2, 1]).unwrap().0,
+            PayloadFormat::UnframedSpan
+        );
+
+        assert!(split_header(&[1, 9]).is_err());
+        assert!(split_header(&[7, 1]).is_err());
+        assert!(split_header(&[1]).is_err());
+
+        let decoder = PayloadDecoder::default();
+        let payload = header(PayloadFormat::Bincode, CodecId::None);
+        let decoded = decode_payload(&payload, &decoder).unwrap();
+        assert!(decoded.frames.is_none());
+        assert!(decoded.encoded.is_empty());
+
+        // Flat and span payloads need block frames after the header
+        let payload = header(PayloadFormat::Flat, CodecId::None);
+        assert!(decode_payload(&payload, &decoder).is_err());
+        let payload = header(PayloadFormat::UnframedFlat, CodecId::None);
+        assert!(decode_payload(&payload, &decoder).unwrap().frames.is_none());
+    }
+
+    #[test]
+    fn test_decompression_is_capped() {
+        let input = vec![0u8; MAX_DECOMPRESSED + 1];
+        let codecs: Vec<Box<dyn BatchCodec>> =
+            vec![Box::new(Brotli::default()), Box::new(Zstd::default())];
+        for codec in codecs {
+            let mut encoder = codec
+                .encoder(header(PayloadFormat::Bincode, codec.id()))
+                .unwrap();
+            encoder.write_all(&input).unwrap();
+            let payload = encoder.finish().unwrap();
+            assert!(payload.len() < 64 * 1024, "{:?}", codec.id());
+
+            let err = decode_payload(&payload, &PayloadDecoder::default()).unwrap_err();
+            assert!(err.to_string().contains("more than"), "{}", err);
+            let exact = codec.decode(&payload[2..]);
+            assert!(exact.is_err());
+        }
+    }
+}
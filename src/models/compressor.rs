@@ -0,0 +1,534 @@
+use super::batch::{BatchError, BlockFrame, BlockFrames};
+use super::block::Block;
+use super::codec::{self, BatchCodec, CodecId, Encoder, PayloadDecoder, PayloadFormat};
+use super::span_batch;
+use super::transaction::{BincodeTransaction, L2Transaction};
+use alloy::primitives::B256;
+use alloy::rlp::{Decodable, Encodable};
+use std::io::Write;
+
+/// Most bytes a codec stream adds to its input between flushes, its header
+/// and closing bytes included. Incompressible input also grows by up to
+/// 1/1024 of its size, in block headers.
+const STREAM_OVERHEAD: usize = 32;
+
+/// A finished batch payload.
+#[derive(Debug, Clone)]
+pub struct Compressed {
+    /// Batch header, then the codec's stream
+    pub data: Vec<u8>,
+    /// Size of the encoded transactions before compression
+    pub uncompressed_size: usize,
+}
+
+/// Compresses a batch one transaction at a time, as one stream, so the
+/// batcher can close it at a compressed byte target rather than a
+/// transaction count.
+///
+/// The payload is the batch header and block frames, followed by the
+/// transactions' RLP encodings back to back as one stream of the header's
+/// codec.
+pub struct ChannelCompressor {
+    codec: CodecId,
+    writer: Box<dyn Encoder>,
+    /// Encoded bytes written since the stream last flushed; how small
+    /// they'll compress isn't known yet
+    unflushed: usize,
+    uncompressed_size: usize,
+    transactions: usize,
+}
+
+impl ChannelCompressor {
+    pub fn new(codec: &dyn BatchCodec) -> Result<Self, BatchError> {
+        Ok(Self {
+            codec: codec.id(),
+            writer: codec.encoder(Vec::new())?,
+            unflushed: 0,
+            uncompressed_size: 0,
+            transactions: 0,
+        })
+    }
+
+    pub fn add(&mut self, tx: &L2Transaction) -> Result<(), BatchError> {
+        self.write(&alloy::rlp::encode(tx))
+    }
+
+    /// Adds `tx` only if the finished payload, framed as one block, is
+    /// certain to stay within `target` bytes, returning whether it was
+    /// added. The first transaction is always added, so a lone oversized one
+    /// still makes a batch.
+    pub fn try_add(&mut self, tx: &L2Transaction, target: usize) -> Result<bool, BatchError> {
+        let encoded = alloy::rlp::encode(tx);
+        if self.transactions > 0 && self.bound_with(encoded.len()) > target {
+            // The bound counts unflushed input at full size; flushing makes
+            // it exact and may leave room after all
+            self.compressed_size()?;
+            if self.bound_with(encoded.len()) > target {
+                return Ok(false);
+            }
+        }
+        self.write(&encoded)?;
+        Ok(true)
+    }
+
+    /// Whether the finished payload, framed as one block, is certain to stay
+    /// within `target` bytes. A single transaction always fits.
+    pub fn fits(&mut self, target: usize) -> Result<bool, BatchError> {
+        if self.transactions <= 1 || self.size_bound() <= target {
+            return Ok(true);
+        }
+        self.compressed_size()?;
+        Ok(self.size_bound() <= target)
+    }
+
+    fn write(&mut self, encoded: &[u8]) -> Result<(), BatchError> {
+        self.writer.write_all(encoded)?;
+        self.unflushed += encoded.len();
+        self.uncompressed_size += encoded.len();
+        self.transactions += 1;
+        Ok(())
+    }
+
+    /// Exact size of the stream for everything added so far. Flushes the
+    /// stream, which costs a little ratio; `size_bound` is free.
+    pub fn compressed_size(&mut self) -> Result<usize, BatchError> {
+        if self.unflushed > 0 {
+            self.writer.flush()?;
+            self.unflushed = 0;
+        }
+        Ok(self.writer.written())
+    }
+
+    /// An upper bound on the finished payload's size when framed as one
+    /// block, without flushing.
+    pub fn size_bound(&self) -> usize {
+        self.bound_with(0)
+    }
+
+    fn bound_with(&self, extra: usize) -> usize {
+        let unflushed = self.unflushed + extra;
+        one_block_framing() + self.writer.written() + unflushed + unflushed / 1024 + STREAM_OVERHEAD
+    }
+
+    pub fn uncompressed_size(&self) -> usize {
+        self.uncompressed_size
+    }
+
+    /// Transactions added so far.
+    pub fn len(&self) -> usize {
+        self.transactions
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions == 0
+    }
+
+    /// Closes the stream and returns the payload, with `frames` splitting
+    /// the transactions into blocks.
+    pub fn finish(self, frames: &BlockFrames) -> Result<Compressed, BatchError> {
+        let stream = self.writer.finish()?;
+        Ok(Compressed {
+            data: assemble(PayloadFormat::Flat, self.codec, frames, &stream),
+            uncompressed_size: self.uncompressed_size,
+        })
+    }
+}
+
+impl std::fmt::Debug for ChannelCompressor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChannelCompressor")
+            .field("codec", &self.codec)
+            .field("uncompressed_size", &self.uncompressed_size)
+            .field("transactions", &self.transactions)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Most bytes the payload header and one block's frames can take.
+fn one_block_framing() -> usize {
+    let frames = BlockFrames {
+        first_number: u64::MAX,
+        frames: vec![BlockFrame {
+            timestamp: u64::MAX,
+            l1_origin: u64::MAX,
+            transactions: u64::MAX,
+        }],
+        outbox_root: Some(B256::repeat_byte(0xff)),
+    };
+    codec::header(PayloadFormat::Flat, CodecId::None).len() + frames.length()
+}
+
+/// Compresses a whole batch in the span format. Unlike `ChannelCompressor`
+/// this needs every transaction up front, since shared fields are factored
+/// out across all of them.
+pub fn compress_span_batch(
+    blocks: &[Block],
+    codec: &dyn BatchCodec,
+) -> Result<Compressed, BatchError> {
+    let transactions: Vec<_> = blocks
+        .iter()
+        .flat_map(|b| b.transactions.iter().cloned())
+        .collect();
+    let encoded = span_batch::encode(&transactions);
+    let mut writer = codec.encoder(Vec::new())?;
+    writer.write_all(&encoded)?;
+    let stream = writer.finish()?;
+    Ok(Compressed {
+        data: assemble(
+            PayloadFormat::Span,
+            codec.id(),
+            &BlockFrames::of(blocks),
+            &stream,
+        ),
+        uncompressed_size: encoded.len(),
+    })
+}
+
+fn assemble(format: PayloadFormat, codec: CodecId, frames: &BlockFrames, stream: &[u8]) -> Vec<u8> {
+    let mut data = codec::header(format, codec);
+    data.reserve(frames.length() + stream.len());
+    frames.encode(&mut data);
+    data.extend_from_slice(stream);
+    data
+}
+
+/// Reads the transactions back out of a batch payload, in any payload
+/// format.
+pub fn decode_transactions(
+    data: &[u8],
+    decoder: &PayloadDecoder,
+) -> Result<Vec<L2Transaction>, BatchError> {
+    let payload = codec::decode_payload(data, decoder)?;
+    transactions(payload.format, &payload.encoded)
+}
+
+/// Rebuilds the blocks of a batch payload, chaining the first onto
+/// `parent_hash`. Payloads in formats from before block frames can't be
+/// rebuilt.
+pub fn decode_blocks(
+    data: &[u8],
+    decoder: &PayloadDecoder,
+    parent_hash: B256,
+) -> Result<Vec<Block>, BatchError> {
+    let payload = codec::decode_payload(data, decoder)?;
+    let frames = payload.frames.ok_or_else(|| {
+        BatchError::Header(format!(
+            "{:?} payloads have no block frames",
+            payload.format
+        ))
+    })?;
+    frames.rebuild(transactions(payload.format, &payload.encoded)?, parent_hash)
+}
+
+fn transactions(format: PayloadFormat, encoded: &[u8]) -> Result<Vec<L2Transaction>, BatchError> {
+    if format.is_span() {
+        return Ok(span_batch::decode(encoded)?);
+    }
+
+    let mut reader = encoded;
+    let mut transactions = Vec::new();
+    while !reader.is_empty() {
+        let tx = match format {
+            PayloadFormat::Bincode => {
+                bincode::deserialize_from::<_, BincodeTransaction>(&mut reader)?.into()
+            }
+            _ => L2Transaction::decode(&mut reader)?,
+        };
+        transactions.push(tx);
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::batch::BlockFrame;
+    use crate::models::codec::{Brotli, NoCompression, Zstd};
+    use alloy::primitives::{keccak256, Address, Bytes, U256};
+
+    fn transaction(nonce: u64) -> L2Transaction {
+        L2Transaction::new(
+            nonce,
+            Address::repeat_byte((nonce % 7) as u8 + 1),
+            Some(Address::repeat_byte(0xcc)),
+            U256::from(nonce * 1_000_003),
+            Bytes::from(nonce.to_be_bytes().repeat(4)),
+            21000,
+            U256::from(30_000_000_000u64),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    /// Frames for one block, as large as they encode
+    fn frames(transactions: usize) -> BlockFrames {
+        BlockFrames {
+            first_number: u64::MAX,
+            frames: vec![BlockFrame {
+                timestamp: u64::MAX,
+                l1_origin: u64::MAX,
+                transactions: transactions as u64,
+            }],
+            outbox_root: Some(B256::repeat_byte(0xff)),
+        }
+    }
+
+    fn codecs() -> Vec<Box<dyn BatchCodec>> {
+        vec![
+            Box::new(NoCompression),
+            Box::new(Brotli::default()),
+            Box::new(Zstd {
+                level: 3,
+                dictionary: None,
+            }),
+        ]
+    }
+
+    /// Compressible at first, then with calldata that doesn't compress
+    fn mixed_transaction(nonce: u64) -> L2Transaction {
+        let mut tx = transaction(nonce);
+        if nonce >= 100 {
+            tx.data = (0..nonce % 7 + 1)
+                .flat_map(|i| keccak256((nonce * 8 + i).to_be_bytes()))
+                .collect();
+        }
+        tx
+    }
+
+    #[test]
+    fn test_closes_within_target() {
+        for codec in codecs() {
+            for target in [1_000, 4_096, 20_000] {
+                let mut compressor = ChannelCompressor::new(codec.as_ref()).unwrap();
+                let mut batches = 0;
+                for nonce in 0..400 {
+                    let tx = mixed_transaction(nonce);
+                    if compressor.try_add(&tx, target).unwrap() {
+                        continue;
+                    }
+                    assert!(
+                        compressor.len() > 1,
+                        "target {} fit one transaction",
+                        target
+                    );
+                    let full = std::mem::replace(
+                        &mut compressor,
+                        ChannelCompressor::new(codec.as_ref()).unwrap(),
+                    );
+                    let transactions = full.len();
+                    let payload = full.finish(&frames(transactions)).unwrap();
+                    assert!(
+                        payload.data.len() <= target,
+                        "{:?}: {} bytes for a {} byte target",
+                        codec.id(),
+                        payload.data.len(),
+                        target
+                    );
+                    batches += 1;
+                    compressor.add(&tx).unwrap();
+                }
+                assert!(batches > 0, "target {} never filled", target);
+                assert!(compressor.fits(target).unwrap());
+                let transactions = compressor.len();
+                assert!(compressor.finish(&frames(transactions)).unwrap().data.len() <= target);
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let txs: Vec<_> = (0..20).map(transaction).collect();
+        for codec in codecs() {
+            let mut compressor = ChannelCompressor::new(codec.as_ref()).unwrap();
+            for tx in &txs {
+                compressor.add(tx).unwrap();
+            }
+            let bound = compressor.size_bound();
+            assert!(compressor.compressed_size().unwrap() < bound);
+            assert!(compressor.size_bound() <= bound);
+            let uncompressed_size = compressor.uncompressed_size();
+            let compressed = compressor.finish(&frames(txs.len())).unwrap();
+            assert!(compressed.data.len() <= bound);
+            assert_eq!(compressed.uncompressed_size, uncompressed_size);
+
+            let decoded =
+                decode_transactions(&compressed.data, &PayloadDecoder::default()).unwrap();
+            let hashes: Vec<_> = decoded.iter().map(L2Transaction::hash).collect();
+            assert_eq!(
+                hashes,
+                txs.iter().map(L2Transaction::hash).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_blocks() {
+        let parent = Block::new(7, vec![transaction(99)], B256::ZERO, B256::ZERO, 1_000);
+        let first = Block::new(
+            8,
+            (0..3).map(transaction).collect(),
+            parent.hash(),
+            B256::ZERO,
+            1_002,
+        )
+        .with_l1_origin(50);
+        let second = Block::new(
+            9,
+            (3..5).map(transaction).collect(),
+            first.hash(),
+            B256::ZERO,
+            1_004,
+        )
+        .with_l1_origin(51);
+        let empty = Block::new(10, Vec::new(), second.hash(), B256::ZERO, 1_006).with_l1_origin(51);
+        let blocks = vec![first, second, empty];
+        let codec = Brotli::default();
+        let decoder = PayloadDecoder::default();
+
+        let mut compressor = ChannelCompressor::new(&codec).unwrap();
+        for tx in blocks.iter().flat_map(|b| &b.transactions) {
+            compressor.add(tx).unwrap();
+        }
+        let flat = compressor.finish(&BlockFrames::of(&blocks)).unwrap();
+        let span = compress_span_batch(&blocks, &codec).unwrap();
+
+        for payload in [flat.data, span.data] {
+            let rebuilt = decode_blocks(&payload, &decoder, parent.hash()).unwrap();
+            let hashes: Vec<_> = rebuilt.iter().map(Block::hash).collect();
+            assert_eq!(hashes, blocks.iter().map(Block::hash).collect::<Vec<_>>());
+
+            // Chained onto the wrong parent, every hash changes
+            let rebuilt = decode_blocks(&payload, &decoder, B256::ZERO).unwrap();
+            assert_ne!(rebuilt[2].hash(), blocks[2].hash());
+        }
+
+        // Frames that don't cover the payload's transactions
+        let mut compressor = ChannelCompressor::new(&codec).unwrap();
+        compressor.add(&transaction(0)).unwrap();
+        let payload = compressor.finish(&frames(2)).unwrap();
+        assert!(decode_blocks(&payload.data, &decoder, B256::ZERO).is_err());
+
+        // Counts and block numbers off L1 that would overflow are errors
+        let frame = |transactions| BlockFrame {
+            timestamp: 0,
+            l1_origin: 0,
+            transactions,
+        };
+        let wrapping = BlockFrames {
+            first_number: 1,
+            frames: vec![frame(u64::MAX), frame(2)],
+            outbox_root: Some(B256::ZERO),
+        };
+        assert!(wrapping.rebuild(vec![transaction(0)], B256::ZERO).is_err());
+        let last_block = BlockFrames {
+            first_number: u64::MAX,
+            frames: vec![frame(0), frame(0)],
+            outbox_root: Some(B256::ZERO),
+        };
+        assert!(last_block.rebuild(Vec::new(), B256::ZERO).is_err());
+
+        // An outbox root the withdrawals don't hash to
+        let mut compressor = ChannelCompressor::new(&codec).unwrap();
+        compressor.add(&transaction(0)).unwrap();
+        let mut frames = frames(1);
+        frames.first_number = 1;
+        let payload = compressor.finish(&frames).unwrap();
+        assert!(decode_blocks(&payload.data, &decoder, B256::ZERO).is_err());
+        frames.outbox_root = Some(B256::ZERO);
+        let mut compressor = ChannelCompressor::new(&codec).unwrap();
+        compressor.add(&transaction(0)).unwrap();
+        let payload = compressor.finish(&frames).unwrap();
+        assert!(decode_blocks(&payload.data, &decoder, B256::ZERO).is_ok());
+
+        // Framed before withdrawals: `[first_number, frames]`, no root
+        let mut compressor = ChannelCompressor::new(&codec).unwrap();
+        for tx in blocks.iter().flat_map(|b| &b.transactions) {
+            compressor.add(tx).unwrap();
+        }
+        let mut frames = BlockFrames::of(&blocks);
+        frames.outbox_root = None;
+        let payload = compressor.finish(&frames).unwrap();
+        let decoded = codec::decode_payload(&payload.data, &decoder).unwrap();
+        assert_eq!(decoded.frames.unwrap().outbox_root, None);
+        let rebuilt = decode_blocks(&payload.data, &decoder, parent.hash()).unwrap();
+        assert_eq!(rebuilt[2].hash(), blocks[2].hash());
+    }
+
+    #[test]
+    fn test_reads_bincode_payloads() {
+        let txs: Vec<_> = (0..5).map(transaction).collect();
+        let codec = Brotli::default();
+        let mut encoder = codec.encoder(vec![0, codec.id() as u8]).unwrap();
+        for tx in &txs {
+            encoder
+                .write_all(&bincode::serialize(&BincodeTransaction::from(tx)).unwrap())
+                .unwrap();
+        }
+        let payload = encoder.finish().unwrap();
+
+        let decoded = decode_transactions(&payload, &PayloadDecoder::default()).unwrap();
+        assert_eq!(decoded.len(), txs.len());
+        assert_eq!(decoded[4].hash(), txs[4].hash());
+    }
+
+    /// Flat and span payloads as they were written before block frames
+    #[test]
+    fn test_reads_unframed_payloads() {
+        let txs: Vec<_> = (0..5).map(transaction).collect();
+        let codec = Brotli::default();
+        let decoder = PayloadDecoder::default();
+
+        let mut flat = codec.encoder(vec![1, codec.id() as u8]).unwrap();
+        for tx in &txs {
+            flat.write_all(&alloy::rlp::encode(tx)).unwrap();
+        }
+        let mut span = codec.encoder(vec![2, codec.id() as u8]).unwrap();
+        span.write_all(&span_batch::encode(&txs)).unwrap();
+
+        for payload in [flat.finish().unwrap(), span.finish().unwrap()] {
+            let decoded = decode_transactions(&payload, &decoder).unwrap();
+            assert_eq!(
+                decoded.iter().map(L2Transaction::hash).collect::<Vec<_>>(),
+                txs.iter().map(L2Transaction::hash).collect::<Vec<_>>()
+            );
+            assert!(decode_blocks(&payload, &decoder, B256::ZERO).is_err());
+        }
+    }
+
+    /// RLP against the bincode payloads it replaced, after compression.
+    /// Varying values and calldata lengths are where bincode's fixed-width
+    /// integers cost the most.
+    #[test]
+    fn test_smaller_than_bincode() {
+        let txs: Vec<_> = (0..500u64)
+            .map(|i| {
+                let mut tx = transaction(i);
+                tx.value = U256::from(i * i * 7_919);
+                tx.gas_price = U256::from(100_000_000 + i * 13);
+                tx.data = Bytes::from(vec![0xab; (i % 5 * 36) as usize]);
+                tx
+            })
+            .collect();
+
+        for codec in codecs() {
+            let mut bincode_encoder = codec.encoder(Vec::new()).unwrap();
+            let mut compressor = ChannelCompressor::new(codec.as_ref()).unwrap();
+            for tx in &txs {
+                bincode_encoder
+                    .write_all(&bincode::serialize(&BincodeTransaction::from(tx)).unwrap())
+                    .unwrap();
+                compressor.add(tx).unwrap();
+            }
+            let bincode_size = bincode_encoder.finish().unwrap().len();
+            let rlp_size = compressor.finish(&frames(txs.len())).unwrap().data.len();
+            assert!(
+                rlp_size < bincode_size,
+                "{:?}: RLP {} bytes, bincode {} bytes",
+                codec.id(),
+                rlp_size,
+                bincode_size
+            );
+        }
+    }
+}
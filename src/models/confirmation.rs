@@ -0,0 +1,162 @@
+use super::transaction::L2Transaction;
+use alloy::primitives::{keccak256, Address, Bytes, PrimitiveSignature, SignatureError, B256};
+use alloy::signers::local::PrivateKeySigner;
+use alloy::signers::SignerSync;
+use alloy::sol_types::SolValue;
+use serde::{Deserialize, Serialize};
+
+alloy::sol! {
+    /// What the sequencer signs when it admits a transaction. `l1BlockNumber`
+    /// is the admitted one, which may have been clamped into the sequencing
+    /// window and so changed the transaction's hash.
+    struct SoftConfirmation {
+        bytes32 txHash;
+        uint64 l1BlockNumber;
+        uint64 sequenceNumber;
+        uint64 targetBlock;
+        uint64 timestamp;
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfirmationError {
+    #[error("Invalid confirmation signature: {0}")]
+    Signature(#[from] SignatureError),
+    #[error("Confirmation signed by {signer}, expected {expected}")]
+    WrongSigner { signer: Address, expected: Address },
+    #[error("Confirmation is for {confirmed}, not {submitted}")]
+    WrongTransaction { confirmed: B256, submitted: B256 },
+}
+
+/// The sequencer's signed promise, made at admission, to include a
+/// transaction in `sequence_number` order. Two confirmations with one
+/// sequence number, or a chain that orders transactions against their
+/// sequence numbers, prove it broke its word. `target_block` is only its
+/// estimate of the block; deposits, evictions and batch size changes can
+/// move the transaction either way.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confirmation {
+    pub tx_hash: B256,
+    pub l1_block_number: u64,
+    pub sequence_number: u64,
+    pub target_block: u64,
+    /// Unix seconds at admission
+    pub timestamp: u64,
+    /// 65-byte EIP-191 signature over `digest`
+    pub signature: Bytes,
+}
+
+impl Confirmation {
+    /// An unsigned confirmation for the admitted `tx`.
+    pub fn new(
+        tx: &L2Transaction,
+        sequence_number: u64,
+        target_block: u64,
+        timestamp: u64,
+    ) -> Self {
+        Self {
+            tx_hash: tx.hash(),
+            l1_block_number: tx.l1_block_number,
+            sequence_number,
+            target_block,
+            timestamp,
+            signature: Bytes::new(),
+        }
+    }
+
+    pub fn sign(mut self, signer: &PrivateKeySigner) -> Result<Self, alloy::signers::Error> {
+        let signature = signer.sign_message_sync(self.digest().as_slice())?;
+        self.signature = Bytes::from(signature.as_bytes());
+        Ok(self)
+    }
+
+    /// `keccak256(abi.encode(SoftConfirmation))`, the message that's signed.
+    pub fn digest(&self) -> B256 {
+        let message = SoftConfirmation {
+            txHash: self.tx_hash,
+            l1BlockNumber: self.l1_block_number,
+            sequenceNumber: self.sequence_number,
+            targetBlock: self.target_block,
+            timestamp: self.timestamp,
+        };
+        keccak256(message.abi_encode())
+    }
+
+    pub fn signer(&self) -> Result<Address, ConfirmationError> {
+        let signature = PrimitiveSignature::try_from(self.signature.as_ref())?;
+        Ok(signature.recover_address_from_msg(self.digest())?)
+    }
+
+    /// Checks that `sequencer` signed this for `tx`, as admitted with the
+    /// confirmed `l1_block_number`.
+    pub fn verify(&self, sequencer: Address, tx: &L2Transaction) -> Result<(), ConfirmationError> {
+        let signer = self.signer()?;
+        if signer != sequencer {
+            return Err(ConfirmationError::WrongSigner {
+                signer,
+                expected: sequencer,
+            });
+        }
+        let mut admitted = tx.clone();
+        admitted.l1_block_number = self.l1_block_number;
+        if admitted.hash() != self.tx_hash {
+            return Err(ConfirmationError::WrongTransaction {
+                confirmed: self.tx_hash,
+                submitted: admitted.hash(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+
+    #[test]
+    fn test_verifies_signer_and_transaction() {
+        let signer = PrivateKeySigner::random();
+        let mut tx = L2Transaction::new(
+            0,
+            Address::repeat_byte(0xaa),
+            Some(Address::repeat_byte(0xbb)),
+            U256::ZERO,
+            Bytes::new(),
+            21000,
+            U256::from(1),
+            Some(42161),
+            7,
+            U256::ZERO,
+        );
+        let confirmation = Confirmation::new(&tx, 12, 40, 1_700_000_000)
+            .sign(&signer)
+            .unwrap();
+        confirmation.verify(signer.address(), &tx).unwrap();
+
+        // Clamped at admission: the client's copy still checks out
+        tx.l1_block_number = 3;
+        confirmation.verify(signer.address(), &tx).unwrap();
+
+        assert!(matches!(
+            confirmation.verify(Address::repeat_byte(0x01), &tx),
+            Err(ConfirmationError::WrongSigner { .. })
+        ));
+        tx.nonce = 1;
+        assert!(matches!(
+            confirmation.verify(signer.address(), &tx),
+            Err(ConfirmationError::WrongTransaction { .. })
+        ));
+
+        // Any change to what was promised breaks the signature
+        tx.nonce = 0;
+        let mut altered = confirmation.clone();
+        altered.target_block = 41;
+        assert!(altered.verify(signer.address(), &tx).is_err());
+        altered.signature = Bytes::from(vec![0; 10]);
+        assert!(matches!(
+            altered.verify(signer.address(), &tx),
+            Err(ConfirmationError::Signature(_))
+        ));
+    }
+}
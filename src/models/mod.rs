@@ -1,6 +1,22 @@
+pub mod batch;
 pub mod block;
+pub mod codec;
+pub mod compressor;
+pub mod confirmation;
+pub mod outbox;
 pub mod queue;
+pub mod sequencing_window;
+pub mod span_batch;
 pub mod transaction;
 
+pub use batch::{Batch, BatchFormat, BlockFrames, SealedBatch};
+pub use block::Block;
+pub use codec::{BatchCodec, PayloadDecoder};
+pub use compressor::{
+    compress_span_batch, decode_blocks, decode_transactions, ChannelCompressor, Compressed,
+};
+pub use confirmation::Confirmation;
+pub use outbox::{OutboxTree, Withdrawal};
 pub use queue::Queue;
-pub use transaction::L2Transaction;
+pub use sequencing_window::{SequencingWindow, WindowPolicy};
+pub use transaction::{BincodeTransaction, Deposit, L2Transaction};
@@ -0,0 +1,246 @@
+use super::block::Block;
+use super::transaction::L2Transaction;
+use alloy::primitives::{address, keccak256, Address, Bytes, B256, U256};
+use alloy::sol_types::{SolCall, SolValue};
+use serde::{Deserialize, Serialize};
+
+/// L2 predeploy that withdrawals are sent to.
+pub const OUTBOX_ADDRESS: Address = address!("4200000000000000000000000000000000000016");
+
+alloy::sol! {
+    /// Called on the outbox to send the transaction's value, and a call to
+    /// `target`, to L1.
+    function initiateWithdrawal(address target, uint256 gasLimit, bytes data);
+
+    /// What a withdrawal's leaf in the outbox tree is the hash of, so L1 can
+    /// recompute it.
+    struct WithdrawalMessage {
+        uint64 l2Block;
+        uint64 txIndex;
+        address sender;
+        address target;
+        uint256 value;
+        uint256 gasLimit;
+        bytes data;
+    }
+}
+
+/// A message from L2 to L1, made by calling `initiateWithdrawal` on the
+/// outbox.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Withdrawal {
+    pub l2_block: u64,
+    /// Position of the transaction in its block
+    pub tx_index: u64,
+    pub l2_tx_hash: B256,
+    pub sender: Address,
+    pub target: Address,
+    pub value: U256,
+    pub gas_limit: U256,
+    pub data: Bytes,
+}
+
+impl Withdrawal {
+    /// The withdrawal `tx` makes, if it's a well-formed call to the outbox.
+    pub fn from_transaction(tx: &L2Transaction, l2_block: u64, tx_index: u64) -> Option<Self> {
+        if tx.to != Some(OUTBOX_ADDRESS) {
+            return None;
+        }
+        let call = initiateWithdrawalCall::abi_decode(&tx.data, true).ok()?;
+        Some(Self {
+            l2_block,
+            tx_index,
+            l2_tx_hash: tx.hash(),
+            sender: tx.from,
+            target: call.target,
+            value: tx.value,
+            gas_limit: call.gasLimit,
+            data: call.data,
+        })
+    }
+
+    /// The withdrawal's leaf in the outbox tree.
+    pub fn hash(&self) -> B256 {
+        let message = WithdrawalMessage {
+            l2Block: self.l2_block,
+            txIndex: self.tx_index,
+            sender: self.sender,
+            target: self.target,
+            value: self.value,
+            gasLimit: self.gas_limit,
+            data: self.data.clone(),
+        };
+        keccak256(message.abi_encode())
+    }
+}
+
+/// Every withdrawal in `blocks`, in order.
+pub fn withdrawals(blocks: &[Block]) -> Vec<Withdrawal> {
+    blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .transactions
+                .iter()
+                .enumerate()
+                .filter_map(|(index, tx)| {
+                    Withdrawal::from_transaction(tx, block.number, index as u64)
+                })
+        })
+        .collect()
+}
+
+/// Binary Merkle tree over a batch's withdrawal hashes. A level with an odd
+/// number of nodes is padded with a zero hash, and a parent is the keccak256
+/// of its children; a batch without withdrawals has a zero root.
+#[derive(Debug, Clone)]
+pub struct OutboxTree {
+    /// Leaves first, root last
+    levels: Vec<Vec<B256>>,
+}
+
+impl OutboxTree {
+    pub fn new(leaves: Vec<B256>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().is_some_and(|level| level.len() > 1) {
+            let parents = levels
+                .last()
+                .unwrap()
+                .chunks(2)
+                .map(|pair| hash_pair(pair[0], pair.get(1).copied().unwrap_or_default()))
+                .collect();
+            levels.push(parents);
+        }
+        Self { levels }
+    }
+
+    /// The tree over the withdrawals in `blocks`.
+    pub fn of(blocks: &[Block]) -> Self {
+        Self::new(withdrawals(blocks).iter().map(Withdrawal::hash).collect())
+    }
+
+    pub fn root(&self) -> B256 {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Siblings from leaf `index` up to the root, or `None` past the last
+    /// leaf.
+    pub fn proof(&self, index: usize) -> Option<Vec<B256>> {
+        if index >= self.levels[0].len() {
+            return None;
+        }
+        let mut index = index;
+        let mut proof = Vec::with_capacity(self.levels.len() - 1);
+        for level in &self.levels[..self.levels.len() - 1] {
+            proof.push(level.get(index ^ 1).copied().unwrap_or_default());
+            index /= 2;
+        }
+        Some(proof)
+    }
+}
+
+/// Checks that `leaf` is at `index` in the tree with `root`, the way the L1
+/// outbox contract does.
+pub fn verify_proof(leaf: B256, index: u64, proof: &[B256], root: B256) -> bool {
+    let mut hash = leaf;
+    let mut index = index;
+    for &sibling in proof {
+        hash = if index.is_multiple_of(2) {
+            hash_pair(hash, sibling)
+        } else {
+            hash_pair(sibling, hash)
+        };
+        index /= 2;
+    }
+    index == 0 && hash == root
+}
+
+fn hash_pair(left: B256, right: B256) -> B256 {
+    keccak256([left.as_slice(), right.as_slice()].concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn withdrawal(from: u8, value: u64) -> L2Transaction {
+        let call = initiateWithdrawalCall {
+            target: Address::repeat_byte(0xbb),
+            gasLimit: U256::from(100_000),
+            data: Bytes::from(vec![0xde, 0xad]),
+        };
+        L2Transaction::new(
+            0,
+            Address::repeat_byte(from),
+            Some(OUTBOX_ADDRESS),
+            U256::from(value),
+            call.abi_encode().into(),
+            100_000,
+            U256::from(1),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    #[test]
+    fn test_recognizes_outbox_calls() {
+        let mut transfer = withdrawal(0xaa, 1);
+        transfer.to = Some(Address::repeat_byte(0xcc));
+        let mut garbled = withdrawal(0xaa, 1);
+        garbled.data = Bytes::from(vec![0x01, 0x02, 0x03, 0x04]);
+        let blocks = vec![
+            Block::new(
+                7,
+                vec![transfer, withdrawal(0xaa, 5)],
+                B256::ZERO,
+                B256::ZERO,
+                0,
+            ),
+            Block::new(
+                8,
+                vec![garbled, withdrawal(0xab, 6)],
+                B256::ZERO,
+                B256::ZERO,
+                0,
+            ),
+        ];
+
+        let found = withdrawals(&blocks);
+        assert_eq!(found.len(), 2);
+        assert_eq!((found[0].l2_block, found[0].tx_index), (7, 1));
+        assert_eq!((found[1].l2_block, found[1].tx_index), (8, 1));
+        assert_eq!(found[0].sender, Address::repeat_byte(0xaa));
+        assert_eq!(found[0].target, Address::repeat_byte(0xbb));
+        assert_eq!(found[0].value, U256::from(5));
+        assert_eq!(found[0].l2_tx_hash, blocks[0].transactions[1].hash());
+        assert_ne!(found[0].hash(), found[1].hash());
+    }
+
+    #[test]
+    fn test_proves_every_leaf() {
+        assert_eq!(OutboxTree::new(Vec::new()).root(), B256::ZERO);
+        assert_eq!(OutboxTree::new(Vec::new()).proof(0), None);
+
+        for count in 1..=9u8 {
+            let leaves: Vec<B256> = (0..count).map(|i| keccak256([i])).collect();
+            let tree = OutboxTree::new(leaves.clone());
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).unwrap();
+                assert!(verify_proof(*leaf, index as u64, &proof, tree.root()));
+                assert!(!verify_proof(*leaf, index as u64 ^ 1, &proof, tree.root()));
+                assert!(!verify_proof(
+                    *leaf,
+                    index as u64 + (1 << proof.len()),
+                    &proof,
+                    tree.root()
+                ));
+            }
+            assert_eq!(tree.proof(count as usize), None);
+        }
+    }
+}
@@ -1,72 +1,317 @@
-use crate::models::L2Transaction;
+use crate::models::{
+    Batch, BatchCodec, Block, ChannelCompressor, L2Transaction, SealedBatch, SequencingWindow,
+};
+use alloy::primitives::{Address, B256};
 use alloy::providers::Provider;
-use bincode;
-use brotli::CompressorWriter;
-use std::io::Write;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct Queue<T: Provider> {
+    #[allow(dead_code)]
     provider: T,
     transactions: Vec<L2Transaction>,
     batch_size: usize,
+    head_number: u64,
+    head_hash: B256,
+    next_batch_index: u64,
+    /// Batches close at this many compressed bytes as well as at
+    /// `batch_size`, compressed with this codec
+    max_compressed_bytes: Option<(usize, Arc<dyn BatchCodec>)>,
+    /// The pending transactions, compressed as they arrive; only kept with a
+    /// byte target
+    compressor: Option<ChannelCompressor>,
+    window: SequencingWindow,
+    l1_head: Option<u64>,
+    /// L1 origin of the head block
+    l1_origin: u64,
 }
 
 impl<T: Provider> Queue<T> {
-    pub fn new(provider: T) -> Self {
+    pub fn new(provider: T, batch_size: usize) -> Self {
         Self {
             provider,
             transactions: Vec::new(),
-            batch_size: 50,
+            batch_size,
+            head_number: 0,
+            head_hash: B256::ZERO,
+            next_batch_index: 0,
+            max_compressed_bytes: None,
+            compressor: None,
+            window: SequencingWindow::default(),
+            l1_head: None,
+            l1_origin: 0,
         }
     }
 
-    pub fn queue_transaction(&mut self, tx: &L2Transaction) {
-        self.transactions.push(tx.clone());
+    pub fn with_sequencing_window(mut self, window: SequencingWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Latest L1 block number, which new blocks take as their L1 origin.
+    /// `None` until it's known, and then blocks keep the previous origin.
+    pub fn set_l1_head(&mut self, head: Option<u64>) {
+        self.l1_head = head;
+    }
+
+    /// Closes batches once their payload, compressed with `codec`, would
+    /// pass `target` bytes. This moves compression onto whoever queues
+    /// transactions.
+    pub fn with_max_compressed_bytes(
+        mut self,
+        target: Option<usize>,
+        codec: Arc<dyn BatchCodec>,
+    ) -> Self {
+        self.max_compressed_bytes = target.map(|target| (target, codec));
+        self.reset_compressor();
+        self
+    }
+
+    /// Continues the chain after `last`, the most recently built batch.
+    pub fn resume_from(&mut self, last: &Batch) {
+        if let Some(block) = last.blocks.last() {
+            self.head_number = block.number;
+            self.head_hash = block.hash();
+            self.l1_origin = block.l1_origin;
+        }
+        self.next_batch_index = last.index + 1;
+    }
+
+    /// Drops every block after the last one in `last` (or after genesis)
+    /// and queues `transactions`, taken from the dropped blocks, ahead of
+    /// the pending ones. They're admitted again against the current L1
+    /// head; those the sequencing window no longer allows are returned.
+    /// Deposits are requeued as they are.
+    pub fn rewind(
+        &mut self,
+        last: Option<&Batch>,
+        transactions: Vec<L2Transaction>,
+    ) -> Vec<L2Transaction> {
+        match last {
+            Some(last) => self.resume_from(last),
+            None => {
+                self.head_number = 0;
+                self.head_hash = B256::ZERO;
+                self.l1_origin = 0;
+                self.next_batch_index = 0;
+            }
+        }
+
+        let mut requeued = Vec::with_capacity(transactions.len() + self.transactions.len());
+        let mut rejected = Vec::new();
+        for mut tx in transactions {
+            if tx.is_deposit() {
+                requeued.push(tx);
+                continue;
+            }
+            match self
+                .l1_head
+                .map(|head| self.window.admit(tx.l1_block_number, head))
+            {
+                Some(Ok(l1_block_number)) => {
+                    tx.l1_block_number = l1_block_number;
+                    requeued.push(tx);
+                }
+                Some(Err(_)) => rejected.push(tx),
+                None => requeued.push(tx),
+            }
+        }
+        // Deposits from every dropped block go first, as they did in each
+        requeued.sort_by_key(|tx| !tx.is_deposit());
+        requeued.append(&mut self.transactions);
+        self.transactions = requeued;
+        self.reset_compressor();
+        rejected
+    }
+
+    /// Queues deposits ahead of every pending transaction but earlier
+    /// deposits. They aren't counted towards closing a batch; seal them with
+    /// `batch_transactions` while `pending_deposits` is nonzero.
+    pub fn queue_deposits(&mut self, deposits: Vec<L2Transaction>) {
+        let position = self.pending_deposits();
+        self.transactions.splice(position..position, deposits);
+        self.reset_compressor();
+    }
+
+    /// Deposits at the front of the queue, waiting to be sealed.
+    pub fn pending_deposits(&self) -> usize {
+        self.transactions
+            .iter()
+            .take_while(|tx| tx.is_deposit())
+            .count()
+    }
+
+    /// Returns a batch once enough transactions are queued to fill one.
+    pub fn queue_transaction(&mut self, tx: L2Transaction) -> Option<SealedBatch> {
+        if let (Some((target, _)), Some(compressor)) =
+            (&self.max_compressed_bytes, &mut self.compressor)
+        {
+            if !compressor
+                .try_add(&tx, *target)
+                .expect("compressing into memory can't fail")
+            {
+                // Full: close what's pending and start the next batch with `tx`
+                let batch = self.batch_transactions();
+                if let Some(compressor) = &mut self.compressor {
+                    compressor
+                        .add(&tx)
+                        .expect("compressing into memory can't fail");
+                }
+                self.transactions.push(tx);
+                return batch;
+            }
+        }
+
+        self.transactions.push(tx);
         if self.transactions.len() >= self.batch_size {
-            let _ = self.batch_transactions();
+            return self.batch_transactions();
         }
+        None
     }
 
-    pub fn batch_transactions(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Seals up to a batch's worth of queued transactions into a block.
+    pub fn batch_transactions(&mut self) -> Option<SealedBatch> {
         if self.transactions.is_empty() {
-            return Ok(());
+            return None;
         }
 
-        let batch_size = std::cmp::min(self.batch_size, self.transactions.len());
+        let compressor = self.batch_compressor();
+        let batch_size = match &compressor {
+            Some(compressor) => compressor.len(),
+            None => std::cmp::min(self.batch_size, self.transactions.len()),
+        };
         let batch: Vec<L2Transaction> = self.transactions.drain(0..batch_size).collect();
+        self.reset_compressor();
+        let block = self.seal_block(batch);
 
-        println!("Batched transactions:");
-        for (i, tx) in batch.iter().enumerate() {
-            println!("  {}: from {:?}", i + 1, tx.from);
-        }
+        let batch = SealedBatch {
+            index: self.next_batch_index,
+            blocks: vec![block],
+            compressor,
+        };
+        self.next_batch_index += 1;
+        Some(batch)
+    }
 
-        self.compress_batch(&batch)?;
-        Ok(())
+    /// Seals a batch if a full one is pending, as after the batch size is
+    /// lowered.
+    pub fn seal_full_batch(&mut self) -> Option<SealedBatch> {
+        if self.transactions.len() < self.batch_size {
+            return None;
+        }
+        self.batch_transactions()
     }
 
-    pub fn compress_batch(
-        &mut self,
-        batch: &Vec<L2Transaction>,
-    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-        let mut output = Vec::new();
-        {
-            let mut compressor = CompressorWriter::new(&mut output, 4096, 3, 22);
-            let bytes = bincode::serialize(batch)?;
-            // let bytes = postcard::to_allocvec(batch)?;
-            println!("pre-compression: {} bytes", bytes.len());
-            println!("Bytes: {:?}", bytes);
-            compressor.write_all(&bytes)?;
-            compressor.flush()?;
+    /// With a byte target, the next batch's transactions compressed: the
+    /// longest run from the front, up to the batch size, within the target.
+    fn batch_compressor(&mut self) -> Option<ChannelCompressor> {
+        let (target, codec) = self.max_compressed_bytes.as_ref()?;
+        if let Some(mut pending) = self.compressor.take() {
+            // Usually every pending transaction goes, and they're compressed
+            // already
+            if pending.len() <= self.batch_size
+                && pending
+                    .fits(*target)
+                    .expect("compressing into memory can't fail")
+            {
+                return Some(pending);
+            }
         }
 
-        println!("post-compression: {} bytes", output.len());
+        let mut compressor =
+            ChannelCompressor::new(codec.as_ref()).expect("compressing into memory can't fail");
+        for tx in self.transactions.iter().take(self.batch_size) {
+            if !compressor
+                .try_add(tx, *target)
+                .expect("compressing into memory can't fail")
+            {
+                break;
+            }
+        }
+        Some(compressor)
+    }
 
-        Ok(output)
+    /// Recompresses the pending transactions, when there's a byte target.
+    fn reset_compressor(&mut self) {
+        self.compressor = self.max_compressed_bytes.as_ref().map(|(_, codec)| {
+            let mut compressor =
+                ChannelCompressor::new(codec.as_ref()).expect("compressing into memory can't fail");
+            for tx in &self.transactions {
+                compressor
+                    .add(tx)
+                    .expect("compressing into memory can't fail");
+            }
+            compressor
+        });
     }
 
-    pub fn print_queue_state(&self) {
-        println!("Queue state: {} transactions", self.transactions.len());
-        for (i, tx) in self.transactions.iter().enumerate() {
-            println!("  {}: from {:?}", i + 1, tx.from);
-        }
+    fn seal_block(&mut self, transactions: Vec<L2Transaction>) -> Block {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        let l1_origin = self
+            .window
+            .origin(self.l1_origin, self.l1_head, &transactions);
+
+        // No execution yet, so there is no state root to commit to
+        let block = Block::new(
+            self.head_number + 1,
+            transactions,
+            self.head_hash,
+            B256::ZERO,
+            timestamp,
+        )
+        .with_l1_origin(l1_origin);
+        self.head_number = block.number;
+        self.head_hash = block.hash();
+        self.l1_origin = l1_origin;
+        block
+    }
+
+    /// Transactions waiting for the next batch, oldest first.
+    pub fn pending(&self) -> &[L2Transaction] {
+        &self.transactions
+    }
+
+    /// Drops every pending transaction from `from` with `nonce`, resubmitted
+    /// copies included, and returns them. Deposits are forced in from L1 and
+    /// are never evicted.
+    pub fn evict(&mut self, from: Address, nonce: u64) -> Vec<L2Transaction> {
+        let (evicted, kept) = std::mem::take(&mut self.transactions)
+            .into_iter()
+            .partition(|tx| !tx.is_deposit() && tx.from == from && tx.nonce == nonce);
+        self.transactions = kept;
+        self.reset_compressor();
+        evicted
+    }
+
+    pub fn batch_size(&self) -> usize {
+        self.batch_size
+    }
+
+    /// Full batches already pending are left for `seal_full_batch`.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size;
+    }
+
+    /// Number of the most recently sealed block.
+    pub fn head_number(&self) -> u64 {
+        self.head_number
+    }
+
+    /// Index the next sealed batch gets.
+    pub fn next_batch_index(&self) -> u64 {
+        self.next_batch_index
+    }
+
+    /// Transactions waiting for the next batch.
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
     }
 }
@@ -0,0 +1,177 @@
+use super::transaction::L2Transaction;
+use serde::{Deserialize, Serialize};
+
+/// What admission does with a transaction whose `l1_block_number` is outside
+/// the sequencing window.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum WindowPolicy {
+    /// Move it to the nearest L1 block inside the window
+    #[default]
+    Clamp,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum WindowError {
+    #[error("l1_block_number {block} is ahead of the L1 head {head}")]
+    Future { block: u64, head: u64 },
+    #[error("l1_block_number {block} is older than the sequencing window allows ({oldest})")]
+    Expired { block: u64, oldest: u64 },
+}
+
+/// How far apart, in L1 blocks, a block's L1 origin may be from the
+/// `l1_block_number`s of its transactions and from the L1 block its batch
+/// lands in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequencingWindow {
+    pub size: u64,
+    pub policy: WindowPolicy,
+}
+
+impl Default for SequencingWindow {
+    fn default() -> Self {
+        Self {
+            size: 3600,
+            policy: WindowPolicy::Clamp,
+        }
+    }
+}
+
+impl SequencingWindow {
+    /// The `l1_block_number` a transaction is admitted with while the L1 head
+    /// is `head`.
+    pub fn admit(&self, l1_block_number: u64, head: u64) -> Result<u64, WindowError> {
+        let oldest = head.saturating_sub(self.size);
+        let error = if l1_block_number > head {
+            WindowError::Future {
+                block: l1_block_number,
+                head,
+            }
+        } else if l1_block_number < oldest {
+            WindowError::Expired {
+                block: l1_block_number,
+                oldest,
+            }
+        } else {
+            return Ok(l1_block_number);
+        };
+        match self.policy {
+            WindowPolicy::Clamp => Ok(l1_block_number.clamp(oldest, head)),
+            WindowPolicy::Reject => Err(error),
+        }
+    }
+
+    /// L1 origin for a block of `transactions` sealed after one with origin
+    /// `previous`. Follows `head`, but holds back far enough that the oldest
+    /// transaction stays inside the window, and never goes backwards.
+    /// Deposits have their own deadline and don't hold it back.
+    pub fn origin(&self, previous: u64, head: Option<u64>, transactions: &[L2Transaction]) -> u64 {
+        let Some(head) = head else {
+            return previous;
+        };
+        let latest_allowed = transactions
+            .iter()
+            .filter(|tx| !tx.is_deposit())
+            .map(|tx| tx.l1_block_number.saturating_add(self.size))
+            .min()
+            .unwrap_or(u64::MAX);
+        previous.max(head.min(latest_allowed))
+    }
+
+    /// Last L1 block a batch can land in when its earliest block has L1
+    /// origin `origin`.
+    pub fn deadline(&self, origin: u64) -> u64 {
+        origin.saturating_add(self.size)
+    }
+
+    /// Why a transaction with `l1_block_number` can't be in a block with
+    /// L1 origin `origin`, if it can't.
+    pub fn check(&self, l1_block_number: u64, origin: u64) -> Result<(), WindowError> {
+        let strict = Self {
+            policy: WindowPolicy::Reject,
+            ..*self
+        };
+        strict.admit(l1_block_number, origin).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{Address, Bytes, U256};
+
+    fn transaction(l1_block_number: u64) -> L2Transaction {
+        L2Transaction::new(
+            0,
+            Address::repeat_byte(1),
+            None,
+            U256::ZERO,
+            Bytes::new(),
+            21000,
+            U256::ZERO,
+            None,
+            l1_block_number,
+            U256::ZERO,
+        )
+    }
+
+    #[test]
+    fn test_admit() {
+        let clamp = SequencingWindow {
+            size: 100,
+            policy: WindowPolicy::Clamp,
+        };
+        let reject = SequencingWindow {
+            policy: WindowPolicy::Reject,
+            ..clamp
+        };
+
+        for window in [clamp, reject] {
+            assert_eq!(window.admit(1_000, 1_000), Ok(1_000));
+            assert_eq!(window.admit(900, 1_000), Ok(900));
+            // Early L1 blocks have a shorter window behind them
+            assert_eq!(window.admit(0, 50), Ok(0));
+        }
+
+        assert_eq!(clamp.admit(1_001, 1_000), Ok(1_000));
+        assert_eq!(clamp.admit(0, 1_000), Ok(900));
+        assert_eq!(
+            reject.admit(1_001, 1_000),
+            Err(WindowError::Future {
+                block: 1_001,
+                head: 1_000
+            })
+        );
+        assert_eq!(
+            reject.admit(899, 1_000),
+            Err(WindowError::Expired {
+                block: 899,
+                oldest: 900
+            })
+        );
+
+        assert!(clamp.check(899, 1_000).is_err());
+        assert!(clamp.check(1_001, 1_000).is_err());
+        clamp.check(950, 1_000).unwrap();
+    }
+
+    #[test]
+    fn test_origin() {
+        let window = SequencingWindow {
+            size: 100,
+            policy: WindowPolicy::Clamp,
+        };
+        let txs = [transaction(950), transaction(990)];
+
+        assert_eq!(window.origin(900, Some(1_000), &txs), 1_000);
+        assert_eq!(window.origin(900, None, &txs), 900);
+        // Held back so the transaction from L1 block 950 stays inside
+        assert_eq!(window.origin(900, Some(1_200), &txs), 1_050);
+        // Never behind the previous block, even after an L1 reorg
+        assert_eq!(window.origin(1_010, Some(1_000), &txs), 1_010);
+        assert_eq!(window.origin(0, Some(1_200), &[]), 1_200);
+        assert_eq!(window.deadline(1_000), 1_100);
+    }
+}
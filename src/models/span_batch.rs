@@ -0,0 +1,511 @@
+//! Span batches: a columnar batch encoding that stores what transactions
+//! share once, modelled on the OP Stack's span batches.
+//!
+//! Layout, integers as LEB128 varints unless noted:
+//!
+//! - transaction count
+//! - address table: count, then 20 bytes per address, in first-use order
+//! - `from`: per transaction, an index into the table
+//! - `to`: per transaction, 0 for a contract creation or table index + 1
+//! - nonces: per transaction, the zigzag difference from the sender's
+//!   previous nonce in the batch plus one (from 0 for its first)
+//! - `gas_limit`: per transaction
+//! - `value`: per transaction, a length byte then big-endian bytes
+//! - `data`: per transaction, a length then the bytes
+//! - `gas_price`, `submission_fee` as amounts, `chain_id` as a presence byte
+//!   and a varint, and `l1_block_number`: each a column, which is either a
+//!   `0` byte and one value every transaction shares, or a `1` byte and a
+//!   value per transaction
+//! - deposits: count, then per deposit the index of its transaction (in
+//!   ascending order), the 32-byte L1 transaction hash, the log index and
+//!   the minted amount
+//!
+//! Sequential nonces from one sender cost a byte each, and a batch that all
+//! shares one chain id, L1 block, gas price and fee stores each once.
+
+use super::transaction::{Deposit, L2Transaction};
+use alloy::primitives::{Address, Bytes, B256, U256};
+use std::collections::HashMap;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SpanBatchError {
+    #[error("unexpected end of input")]
+    UnexpectedEnd,
+    #[error("{0}")]
+    Invalid(&'static str),
+}
+
+const UNIFORM: u8 = 0;
+const PER_TRANSACTION: u8 = 1;
+
+pub fn encode(transactions: &[L2Transaction]) -> Vec<u8> {
+    let mut out = Vec::new();
+    put_varint(&mut out, transactions.len() as u64);
+
+    let mut table = AddressTable::default();
+    let from: Vec<u64> = transactions.iter().map(|tx| table.index(tx.from)).collect();
+    let to: Vec<u64> = transactions
+        .iter()
+        .map(|tx| tx.to.map_or(0, |to| table.index(to) + 1))
+        .collect();
+    put_varint(&mut out, table.addresses.len() as u64);
+    for address in &table.addresses {
+        out.extend_from_slice(address.as_slice());
+    }
+    for index in from.iter().chain(&to) {
+        put_varint(&mut out, *index);
+    }
+
+    let mut next_nonce = HashMap::new();
+    for tx in transactions {
+        let expected = next_nonce.insert(tx.from, tx.nonce.wrapping_add(1));
+        let delta = tx.nonce.wrapping_sub(expected.unwrap_or(0)) as i64;
+        put_varint(&mut out, zigzag(delta));
+    }
+    for tx in transactions {
+        put_varint(&mut out, tx.gas_limit);
+    }
+    for tx in transactions {
+        put_amount(&mut out, &tx.value);
+    }
+    for tx in transactions {
+        put_varint(&mut out, tx.data.len() as u64);
+        out.extend_from_slice(&tx.data);
+    }
+
+    put_column(&mut out, transactions, |tx| tx.gas_price, put_amount);
+    put_column(&mut out, transactions, |tx| tx.submission_fee, put_amount);
+    put_column(&mut out, transactions, |tx| tx.chain_id, put_chain_id);
+    put_column(
+        &mut out,
+        transactions,
+        |tx| tx.l1_block_number,
+        |out, block| put_varint(out, *block),
+    );
+
+    let deposits: Vec<(usize, &Deposit)> = transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tx)| tx.deposit.as_ref().map(|deposit| (i, deposit)))
+        .collect();
+    put_varint(&mut out, deposits.len() as u64);
+    for (index, deposit) in deposits {
+        put_varint(&mut out, index as u64);
+        out.extend_from_slice(deposit.l1_tx_hash.as_slice());
+        put_varint(&mut out, deposit.log_index);
+        put_amount(&mut out, &deposit.mint);
+    }
+    out
+}
+
+/// Decodes a span batch. Fails on malformed input, including trailing
+/// bytes.
+pub fn decode(data: &[u8]) -> Result<Vec<L2Transaction>, SpanBatchError> {
+    let mut reader = Reader { data };
+    let count = reader.varint()? as usize;
+    // Every transaction takes at least one byte in each per-transaction
+    // column, so a larger count can't be genuine
+    if count > reader.data.len() {
+        return Err(SpanBatchError::Invalid("transaction count exceeds input"));
+    }
+
+    let table_len = reader.varint()? as usize;
+    if table_len > reader.data.len() / Address::len_bytes() {
+        return Err(SpanBatchError::UnexpectedEnd);
+    }
+    let addresses: Vec<Address> = (0..table_len)
+        .map(|_| reader.take(Address::len_bytes()).map(Address::from_slice))
+        .collect::<Result<_, _>>()?;
+    let lookup = |index: u64| {
+        addresses
+            .get(index as usize)
+            .copied()
+            .ok_or(SpanBatchError::Invalid("address index out of range"))
+    };
+
+    let from = (0..count)
+        .map(|_| lookup(reader.varint()?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let to = (0..count)
+        .map(|_| match reader.varint()? {
+            0 => Ok(None),
+            index => lookup(index - 1).map(Some),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut next_nonce = HashMap::new();
+    let mut nonces = Vec::with_capacity(count);
+    for sender in &from {
+        let delta = unzigzag(reader.varint()?);
+        let expected = next_nonce.get(sender).copied().unwrap_or(0u64);
+        let nonce = expected.wrapping_add(delta as u64);
+        next_nonce.insert(*sender, nonce.wrapping_add(1));
+        nonces.push(nonce);
+    }
+    let gas_limits = (0..count)
+        .map(|_| reader.varint())
+        .collect::<Result<Vec<_>, _>>()?;
+    let values = (0..count)
+        .map(|_| reader.amount())
+        .collect::<Result<Vec<_>, _>>()?;
+    let data = (0..count)
+        .map(|_| {
+            let len = reader.varint()?;
+            let len = usize::try_from(len).map_err(|_| SpanBatchError::UnexpectedEnd)?;
+            reader.take(len).map(Bytes::copy_from_slice)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let gas_prices = reader.column(count, Reader::amount)?;
+    let submission_fees = reader.column(count, Reader::amount)?;
+    let chain_ids = reader.column(count, Reader::chain_id)?;
+    let l1_blocks = reader.column(count, Reader::varint)?;
+
+    let mut deposits = vec![None; count];
+    let deposit_count = reader.varint()?;
+    let mut next_index = 0;
+    for _ in 0..deposit_count {
+        let index = reader.varint()?;
+        let index = usize::try_from(index)
+            .ok()
+            .filter(|index| (next_index..count).contains(index))
+            .ok_or(SpanBatchError::Invalid("deposit index out of order"))?;
+        next_index = index + 1;
+        deposits[index] = Some(Deposit {
+            l1_tx_hash: B256::from_slice(reader.take(B256::len_bytes())?),
+            log_index: reader.varint()?,
+            mint: reader.amount()?,
+        });
+    }
+    if !reader.data.is_empty() {
+        return Err(SpanBatchError::Invalid("trailing bytes"));
+    }
+
+    Ok((0..count)
+        .zip(deposits)
+        .map(|(i, deposit)| {
+            let tx = L2Transaction::new(
+                nonces[i],
+                from[i],
+                to[i],
+                values[i],
+                data[i].clone(),
+                gas_limits[i],
+                gas_prices[i],
+                chain_ids[i],
+                l1_blocks[i],
+                submission_fees[i],
+            );
+            match deposit {
+                Some(deposit) => tx.with_deposit(deposit),
+                None => tx,
+            }
+        })
+        .collect())
+}
+
+#[derive(Default)]
+struct AddressTable {
+    addresses: Vec<Address>,
+    indices: HashMap<Address, u64>,
+}
+
+impl AddressTable {
+    fn index(&mut self, address: Address) -> u64 {
+        *self.indices.entry(address).or_insert_with(|| {
+            self.addresses.push(address);
+            self.addresses.len() as u64 - 1
+        })
+    }
+}
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    (value >> 1) as i64 ^ -((value & 1) as i64)
+}
+
+fn put_amount(out: &mut Vec<u8>, amount: &U256) {
+    let bytes = amount.to_be_bytes_trimmed_vec();
+    out.push(bytes.len() as u8);
+    out.extend_from_slice(&bytes);
+}
+
+fn put_chain_id(out: &mut Vec<u8>, chain_id: &Option<u64>) {
+    match chain_id {
+        Some(id) => {
+            out.push(1);
+            put_varint(out, *id);
+        }
+        None => out.push(0),
+    }
+}
+
+/// Writes one value if every transaction has the same, else one each.
+fn put_column<T: PartialEq>(
+    out: &mut Vec<u8>,
+    transactions: &[L2Transaction],
+    field: impl Fn(&L2Transaction) -> T,
+    put: impl Fn(&mut Vec<u8>, &T),
+) {
+    let values: Vec<T> = transactions.iter().map(field).collect();
+    match values.split_first() {
+        Some((first, rest)) if rest.iter().all(|v| v == first) => {
+            out.push(UNIFORM);
+            put(out, first);
+        }
+        _ => {
+            out.push(PER_TRANSACTION);
+            for value in &values {
+                put(out, value);
+            }
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SpanBatchError> {
+        if len > self.data.len() {
+            return Err(SpanBatchError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, SpanBatchError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, SpanBatchError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            let bits = (byte & 0x7f) as u64;
+            if shift == 63 && bits > 1 {
+                return Err(SpanBatchError::Invalid("varint overflows u64"));
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                if byte == 0 && shift > 0 {
+                    return Err(SpanBatchError::Invalid("varint isn't minimal"));
+                }
+                return Ok(value);
+            }
+        }
+        Err(SpanBatchError::Invalid("varint overflows u64"))
+    }
+
+    fn amount(&mut self) -> Result<U256, SpanBatchError> {
+        let len = self.byte()? as usize;
+        if len > 32 {
+            return Err(SpanBatchError::Invalid("amount longer than 32 bytes"));
+        }
+        let bytes = self.take(len)?;
+        if bytes.first() == Some(&0) {
+            return Err(SpanBatchError::Invalid("amount has leading zeros"));
+        }
+        Ok(U256::from_be_slice(bytes))
+    }
+
+    fn chain_id(&mut self) -> Result<Option<u64>, SpanBatchError> {
+        match self.byte()? {
+            0 => Ok(None),
+            1 => self.varint().map(Some),
+            _ => Err(SpanBatchError::Invalid("invalid chain id flag")),
+        }
+    }
+
+    fn column<T: Clone>(
+        &mut self,
+        count: usize,
+        read: impl Fn(&mut Self) -> Result<T, SpanBatchError>,
+    ) -> Result<Vec<T>, SpanBatchError> {
+        match self.byte()? {
+            UNIFORM => {
+                let value = read(self)?;
+                Ok(vec![value; count])
+            }
+            PER_TRANSACTION => (0..count).map(|_| read(self)).collect(),
+            _ => Err(SpanBatchError::Invalid("invalid column tag")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic, so a failing case can be replayed
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.0;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+
+        fn amount(&mut self) -> U256 {
+            match self.below(4) {
+                0 => U256::ZERO,
+                1 => U256::MAX,
+                _ => U256::from(self.next()) << (self.below(192) as usize),
+            }
+        }
+    }
+
+    /// Transactions mixing the shapes the encoding special-cases: repeat and
+    /// one-off senders, sequential and arbitrary nonces, creations, deposits,
+    /// and shared or varying fields.
+    fn random_batch(rng: &mut Rng) -> Vec<L2Transaction> {
+        let pool: Vec<Address> = (0..rng.below(6) + 1)
+            .map(|i| Address::repeat_byte(i as u8 + 1))
+            .collect();
+        let mut nonces: HashMap<Address, u64> = HashMap::new();
+        let shared_chain = rng.below(2) == 0;
+        let shared_block = rng.next();
+
+        (0..rng.below(40))
+            .map(|_| {
+                let from = if rng.below(4) == 0 {
+                    Address::left_padding_from(&rng.next().to_be_bytes())
+                } else {
+                    pool[rng.below(pool.len() as u64) as usize]
+                };
+                let nonce = match rng.below(5) {
+                    0 => rng.next(),
+                    1 => u64::MAX,
+                    _ => {
+                        let nonce = nonces.entry(from).or_insert(rng.below(1_000));
+                        *nonce += 1;
+                        *nonce
+                    }
+                };
+                let to = match rng.below(5) {
+                    0 => None,
+                    1 => Some(from),
+                    _ => Some(pool[rng.below(pool.len() as u64) as usize]),
+                };
+                let data: Vec<u8> = (0..rng.below(200)).map(|_| rng.next() as u8).collect();
+                let tx = L2Transaction::new(
+                    nonce,
+                    from,
+                    to,
+                    rng.amount(),
+                    Bytes::from(data),
+                    rng.next(),
+                    if rng.below(3) == 0 {
+                        rng.amount()
+                    } else {
+                        U256::from(1_000)
+                    },
+                    match (shared_chain, rng.below(3)) {
+                        (true, _) => Some(42161),
+                        (false, 0) => None,
+                        (false, _) => Some(rng.next()),
+                    },
+                    if rng.below(2) == 0 {
+                        shared_block
+                    } else {
+                        rng.next()
+                    },
+                    rng.amount(),
+                );
+                match rng.below(8) {
+                    0 => tx.with_deposit(Deposit {
+                        l1_tx_hash: B256::from(U256::from(rng.next())),
+                        log_index: rng.below(300),
+                        mint: rng.amount(),
+                    }),
+                    _ => tx,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_round_trip_random_batches() {
+        let mut rng = Rng(7);
+        for case in 0..500 {
+            let txs = random_batch(&mut rng);
+            let encoded = encode(&txs);
+            let decoded = decode(&encoded).unwrap_or_else(|e| panic!("case {}: {}", case, e));
+            assert_eq!(decoded.len(), txs.len(), "case {}", case);
+            for (decoded, tx) in decoded.iter().zip(&txs) {
+                assert_eq!(decoded.hash(), tx.hash(), "case {}", case);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rejects_corrupt_input() {
+        let mut rng = Rng(11);
+        for _ in 0..200 {
+            let encoded = encode(&random_batch(&mut rng));
+
+            // Truncations always fail; mutations and noise may decode to
+            // something else, but mustn't panic
+            for _ in 0..20 {
+                let len = rng.below(encoded.len() as u64) as usize;
+                assert!(decode(&encoded[..len]).is_err());
+            }
+            let mut mutated = encoded.clone();
+            let position = rng.below(mutated.len() as u64) as usize;
+            mutated[position] ^= 1 << rng.below(8);
+            let _ = decode(&mutated);
+            let noise: Vec<u8> = (0..rng.below(64)).map(|_| rng.next() as u8).collect();
+            let _ = decode(&noise);
+        }
+        assert!(decode(&[0xff; 11]).is_err());
+    }
+
+    #[test]
+    fn test_shared_fields_stored_once() {
+        let sender = Address::repeat_byte(0x11);
+        let recipient = Address::repeat_byte(0x22);
+        let txs: Vec<_> = (100..200)
+            .map(|nonce| {
+                L2Transaction::new(
+                    nonce,
+                    sender,
+                    Some(recipient),
+                    U256::ZERO,
+                    Bytes::new(),
+                    21000,
+                    U256::from(100_000_000u64),
+                    Some(42161),
+                    19_000_000,
+                    U256::ZERO,
+                )
+            })
+            .collect();
+
+        let span = encode(&txs).len();
+        let flat: usize = txs.iter().map(|tx| alloy::rlp::encode(tx).len()).sum();
+        // Per transaction: sender and recipient indices, nonce delta, three
+        // bytes of gas limit, an empty value and empty data
+        assert!(span <= 100 * 8 + 64, "{} bytes", span);
+        assert!(span * 5 < flat, "span {} bytes, flat {}", span, flat);
+    }
+}
@@ -1,6 +1,7 @@
 use alloy::consensus::Transaction;
 use alloy::eips::{eip2930::AccessList, eip7702::SignedAuthorization, Typed2718};
-use alloy::primitives::{Address, Bytes, ChainId, TxKind, B256, U256};
+use alloy::primitives::{keccak256, Address, Bytes, ChainId, TxKind, B256, U256};
+use alloy::rlp::{BufMut, Decodable, Encodable, Header, EMPTY_LIST_CODE};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,9 +19,119 @@ pub struct L2Transaction {
     pub chain_id: Option<ChainId>,
     pub l1_block_number: u64,
     pub submission_fee: U256,
+
+    /// Set on deposits made through the L1 bridge, which carry no signature
+    /// and are included without going through admission
+    #[serde(default)]
+    pub deposit: Option<Deposit>,
+}
+
+/// `L2Transaction` as bincode laid it out before deposits, in format 0
+/// payloads and in batch stores before version 2. Transaction hashes are
+/// still taken over it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BincodeTransaction {
+    pub nonce: u64,
+    pub from: Address,
+    pub to: Option<Address>,
+    pub value: U256,
+    pub data: Bytes,
+    pub gas_limit: u64,
+    pub gas_price: U256,
+    pub chain_id: Option<ChainId>,
+    pub l1_block_number: u64,
+    pub submission_fee: U256,
+}
+
+impl From<&L2Transaction> for BincodeTransaction {
+    fn from(tx: &L2Transaction) -> Self {
+        Self {
+            nonce: tx.nonce,
+            from: tx.from,
+            to: tx.to,
+            value: tx.value,
+            data: tx.data.clone(),
+            gas_limit: tx.gas_limit,
+            gas_price: tx.gas_price,
+            chain_id: tx.chain_id,
+            l1_block_number: tx.l1_block_number,
+            submission_fee: tx.submission_fee,
+        }
+    }
+}
+
+impl From<BincodeTransaction> for L2Transaction {
+    fn from(tx: BincodeTransaction) -> Self {
+        L2Transaction::new(
+            tx.nonce,
+            tx.from,
+            tx.to,
+            tx.value,
+            tx.data,
+            tx.gas_limit,
+            tx.gas_price,
+            tx.chain_id,
+            tx.l1_block_number,
+            tx.submission_fee,
+        )
+    }
+}
+
+/// Where a deposit came from on L1, and the value it mints on L2.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deposit {
+    pub l1_tx_hash: B256,
+    pub log_index: u64,
+    pub mint: U256,
+}
+
+impl Deposit {
+    /// Identifies the bridge event, so a deposit is only included once.
+    pub fn id(&self) -> (B256, u64) {
+        (self.l1_tx_hash, self.log_index)
+    }
+
+    fn rlp_payload_length(&self) -> usize {
+        self.l1_tx_hash.length() + self.log_index.length() + self.mint.length()
+    }
+}
+
+impl Encodable for Deposit {
+    fn encode(&self, out: &mut dyn BufMut) {
+        Header {
+            list: true,
+            payload_length: self.rlp_payload_length(),
+        }
+        .encode(out);
+        self.l1_tx_hash.encode(out);
+        self.log_index.encode(out);
+        self.mint.encode(out);
+    }
+
+    fn length(&self) -> usize {
+        let payload_length = self.rlp_payload_length();
+        payload_length + alloy::rlp::length_of_length(payload_length)
+    }
+}
+
+impl Decodable for Deposit {
+    fn decode(buf: &mut &[u8]) -> alloy::rlp::Result<Self> {
+        let mut fields = Header::decode_bytes(buf, true)?;
+        let buf = &mut fields;
+        let deposit = Self {
+            l1_tx_hash: Decodable::decode(buf)?,
+            log_index: Decodable::decode(buf)?,
+            mint: Decodable::decode(buf)?,
+        };
+        if !buf.is_empty() {
+            return Err(alloy::rlp::Error::Custom("trailing bytes in deposit"));
+        }
+        Ok(deposit)
+    }
 }
 
 impl L2Transaction {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         nonce: u64,
         from: Address,
@@ -44,8 +155,116 @@ impl L2Transaction {
             chain_id,
             l1_block_number,
             submission_fee,
+            deposit: None,
         }
     }
+
+    /// Marks this as a deposit from L1.
+    pub fn with_deposit(mut self, deposit: Deposit) -> Self {
+        self.deposit = Some(deposit);
+        self
+    }
+
+    pub fn is_deposit(&self) -> bool {
+        self.deposit.is_some()
+    }
+
+    /// Hash over the `BincodeTransaction` layout, so transactions other
+    /// than deposits hash as they did before deposits existed. A deposit's
+    /// origin is appended.
+    pub fn hash(&self) -> B256 {
+        let mut preimage = bincode::serialize(&BincodeTransaction::from(self))
+            .expect("L2Transaction is always serializable");
+        if self.deposit.is_some() {
+            bincode::serialize_into(&mut preimage, &self.deposit)
+                .expect("Deposit is always serializable");
+        }
+        keccak256(preimage)
+    }
+
+    fn rlp_payload_length(&self) -> usize {
+        self.nonce.length()
+            + self.from.length()
+            + TxKind::from(self.to).length()
+            + self.value.length()
+            + self.data.length()
+            + self.gas_limit.length()
+            + self.gas_price.length()
+            + self.chain_id.map_or(1, |id| id.length())
+            + self.l1_block_number.length()
+            + self.submission_fee.length()
+            + self.deposit.as_ref().map_or(0, Encodable::length)
+    }
+}
+
+/// The batch payload encoding: an RLP list of the fields in declaration
+/// order. Integers drop their leading zeros, a contract creation's `to` is
+/// the empty string and a missing `chain_id` is the empty list, since the
+/// empty string already means chain 0. A deposit has one more field, the
+/// list of its `Deposit` fields.
+impl Encodable for L2Transaction {
+    fn encode(&self, out: &mut dyn BufMut) {
+        Header {
+            list: true,
+            payload_length: self.rlp_payload_length(),
+        }
+        .encode(out);
+        self.nonce.encode(out);
+        self.from.encode(out);
+        TxKind::from(self.to).encode(out);
+        self.value.encode(out);
+        self.data.encode(out);
+        self.gas_limit.encode(out);
+        self.gas_price.encode(out);
+        match self.chain_id {
+            Some(id) => id.encode(out),
+            None => out.put_u8(EMPTY_LIST_CODE),
+        }
+        self.l1_block_number.encode(out);
+        self.submission_fee.encode(out);
+        if let Some(deposit) = &self.deposit {
+            deposit.encode(out);
+        }
+    }
+
+    fn length(&self) -> usize {
+        let payload_length = self.rlp_payload_length();
+        payload_length + alloy::rlp::length_of_length(payload_length)
+    }
+}
+
+impl Decodable for L2Transaction {
+    fn decode(buf: &mut &[u8]) -> alloy::rlp::Result<Self> {
+        let mut fields = Header::decode_bytes(buf, true)?;
+        let buf = &mut fields;
+        let tx = Self {
+            nonce: Decodable::decode(buf)?,
+            from: Decodable::decode(buf)?,
+            to: TxKind::decode(buf)?.to().copied(),
+            value: Decodable::decode(buf)?,
+            data: Decodable::decode(buf)?,
+            gas_limit: Decodable::decode(buf)?,
+            gas_price: Decodable::decode(buf)?,
+            chain_id: match buf.first() {
+                Some(&EMPTY_LIST_CODE) => {
+                    *buf = &buf[1..];
+                    None
+                }
+                _ => Some(Decodable::decode(buf)?),
+            },
+            l1_block_number: Decodable::decode(buf)?,
+            submission_fee: Decodable::decode(buf)?,
+            deposit: if buf.is_empty() {
+                None
+            } else {
+                Some(Decodable::decode(buf)?)
+            },
+        };
+        if !buf.is_empty() {
+            return Err(alloy::rlp::Error::Custom("trailing bytes in transaction"));
+        }
+        Ok(tx)
+    }
 }
 
 impl Typed2718 for L2Transaction {
@@ -53,6 +272,10 @@ impl Typed2718 for L2Transaction {
         // Custom type for Arbitrum transactions
         // Using a number higher than standard Ethereum types (0-4)
         // but still within EIP-2718 limit of 0x7f
+        if self.is_deposit() {
+            // Deposits use the OP Stack's deposit type
+            return 0x7e;
+        }
         0x64
     }
 }
@@ -129,3 +352,119 @@ impl Transaction for L2Transaction {
         None // Unless implementing EIP-7702
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{b256, hex};
+
+    fn transaction() -> L2Transaction {
+        L2Transaction::new(
+            7,
+            Address::repeat_byte(0x11),
+            Some(Address::repeat_byte(0x22)),
+            U256::from(10u64.pow(18)),
+            Bytes::from(vec![0xa9, 0x05, 0x9c, 0xbb]),
+            21000,
+            U256::from(30_000_000_000u64),
+            Some(42161),
+            19_000_000,
+            U256::ZERO,
+        )
+    }
+
+    #[test]
+    fn test_rlp_round_trip() {
+        let mut create = transaction();
+        create.to = None;
+        create.data = Bytes::new();
+        let mut no_chain = transaction();
+        no_chain.chain_id = None;
+        let mut chain_zero = transaction();
+        chain_zero.chain_id = Some(0);
+        let mut large = transaction();
+        large.value = U256::MAX;
+        large.nonce = u64::MAX;
+        large.data = Bytes::from(vec![0x5a; 1_000]);
+        let deposit = transaction().with_deposit(Deposit {
+            l1_tx_hash: B256::repeat_byte(0x33),
+            log_index: 2,
+            mint: U256::from(10u64.pow(18)),
+        });
+
+        for tx in [transaction(), create, no_chain, chain_zero, large, deposit] {
+            let encoded = alloy::rlp::encode(&tx);
+            assert_eq!(encoded.len(), tx.length());
+            let decoded: L2Transaction = alloy::rlp::decode_exact(&encoded).unwrap();
+            assert_eq!(decoded.hash(), tx.hash());
+            assert_eq!(decoded.chain_id, tx.chain_id);
+            assert_eq!(decoded.deposit, tx.deposit);
+        }
+    }
+
+    #[test]
+    fn test_rlp_is_compact() {
+        let tx = transaction();
+        let rlp = alloy::rlp::encode(&tx).len();
+        let bincode = bincode::serialize(&BincodeTransaction::from(&tx))
+            .unwrap()
+            .len();
+        assert!(rlp * 2 < bincode, "RLP {} bytes, bincode {}", rlp, bincode);
+    }
+
+    /// A transaction as bincode encoded it before deposits, and its hash
+    /// from then
+    #[test]
+    fn test_reads_pre_deposit_bincode() {
+        let fixture = hex!(
+            "0700000000000000140000000000000011111111111111111111111111111111"
+            "1111111101140000000000000022222222222222222222222222222222222222"
+            "2220000000000000000000000000000000000000000000000000000000000000"
+            "0000000000000f42400400000000000000deadbeef0852000000000000200000"
+            "0000000000000000000000000000000000000000000000000000000000000000"
+            "007735940001b1a4000000000000090000000000000020000000000000000000"
+            "000000000000000000000000000000000000000000000000000000000005"
+        );
+        let tx: L2Transaction = bincode::deserialize::<BincodeTransaction>(&fixture)
+            .unwrap()
+            .into();
+        assert_eq!(tx.nonce, 7);
+        assert_eq!(tx.to, Some(Address::repeat_byte(0x22)));
+        assert_eq!(tx.data, Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]));
+        assert_eq!(tx.chain_id, Some(42161));
+        assert_eq!(tx.submission_fee, U256::from(5u64));
+        assert_eq!(
+            tx.hash(),
+            b256!("2b884a7f31baee962aaa5b2f91c2001ba669b863c5cddba8595f11d4f446fb85")
+        );
+        assert_eq!(
+            bincode::serialize(&BincodeTransaction::from(&tx)).unwrap(),
+            fixture
+        );
+
+        let deposit = tx.clone().with_deposit(Deposit {
+            l1_tx_hash: B256::repeat_byte(0x33),
+            log_index: 2,
+            mint: U256::ZERO,
+        });
+        assert_ne!(deposit.hash(), tx.hash());
+    }
+
+    #[test]
+    fn test_rlp_rejects_malformed() {
+        let encoded = alloy::rlp::encode(transaction());
+        assert!(L2Transaction::decode(&mut &encoded[..encoded.len() - 1]).is_err());
+
+        // A list with an extra field
+        let mut fields = encoded[2..].to_vec();
+        fields.push(0x01);
+        let mut padded = Vec::new();
+        Header {
+            list: true,
+            payload_length: fields.len(),
+        }
+        .encode(&mut padded);
+        padded.extend(fields);
+        assert!(L2Transaction::decode(&mut padded.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,150 @@
+use crate::models::{Batch, BatchCodec, BatchFormat, SealedBatch};
+use crate::services::batch_store::BatchStore;
+use crate::services::feed::{BatchSummary, BlockSummary, FeedEvent};
+use crate::services::metrics::Metrics;
+use crate::services::outbox::WithdrawalIndex;
+use crate::services::status::SequencerStatus;
+use std::sync::Arc;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{error, info, Instrument, Span};
+
+/// Work for the `BatchBuilder`, handled in the order it was sent.
+#[derive(Debug)]
+pub enum BuildCommand {
+    Build {
+        batch: SealedBatch,
+        /// Where the batch was sealed, so building shows up in that trace
+        span: Span,
+    },
+    /// Replies once every batch sent before it has been handled
+    Flush { response: oneshot::Sender<()> },
+}
+
+/// Takes sealed batches off the queue processor's hands: compresses each on
+/// the blocking pool, then persists, publishes and hands it to the L1
+/// submitter, keeping batches in order.
+pub struct BatchBuilder {
+    build_rx: mpsc::Receiver<BuildCommand>,
+    feed_tx: broadcast::Sender<FeedEvent>,
+    store: Arc<BatchStore>,
+    metrics: Arc<Metrics>,
+    status: Arc<SequencerStatus>,
+    codec: Arc<dyn BatchCodec>,
+    format: BatchFormat,
+    /// Built batches go here for L1 posting, when it's enabled
+    batch_tx: Option<mpsc::Sender<Batch>>,
+    /// Told about each stored batch, so its withdrawals can be proven
+    withdrawals: Option<Arc<WithdrawalIndex>>,
+}
+
+impl BatchBuilder {
+    pub fn new(
+        build_rx: mpsc::Receiver<BuildCommand>,
+        feed_tx: broadcast::Sender<FeedEvent>,
+        store: Arc<BatchStore>,
+        metrics: Arc<Metrics>,
+        status: Arc<SequencerStatus>,
+        codec: Arc<dyn BatchCodec>,
+        format: BatchFormat,
+    ) -> Self {
+        Self {
+            build_rx,
+            feed_tx,
+            store,
+            metrics,
+            status,
+            codec,
+            format,
+            batch_tx: None,
+            withdrawals: None,
+        }
+    }
+
+    /// Hands every built batch to a `BatchSubmitter`.
+    pub fn with_batch_submitter(mut self, batch_tx: mpsc::Sender<Batch>) -> Self {
+        self.batch_tx = Some(batch_tx);
+        self
+    }
+
+    /// Indexes the withdrawals in every stored batch.
+    pub fn with_withdrawal_index(mut self, withdrawals: Arc<WithdrawalIndex>) -> Self {
+        self.withdrawals = Some(withdrawals);
+        self
+    }
+
+    /// Builds batches until the queue processor stops.
+    pub async fn run(&mut self) {
+        while let Some(command) = self.build_rx.recv().await {
+            match command {
+                BuildCommand::Build { batch, span } => {
+                    if let Some(batch) = self.build(batch).instrument(span.clone()).await {
+                        self.post_batch(batch).instrument(span).await;
+                    }
+                }
+                BuildCommand::Flush { response } => {
+                    let _ = response.send(());
+                }
+            }
+        }
+    }
+
+    /// Compresses, records, persists and publishes a sealed batch.
+    async fn build(&self, sealed: SealedBatch) -> Option<Batch> {
+        let index = sealed.index;
+        let span = Span::current();
+        let codec = self.codec.clone();
+        let format = self.format;
+        let build = move || span.in_scope(|| sealed.build(codec.as_ref(), format));
+        let batch = match tokio::task::spawn_blocking(build).await {
+            Ok(Ok(batch)) => batch,
+            Ok(Err(e)) => {
+                error!(batch_index = index, error = %e, "failed to build batch");
+                return None;
+            }
+            Err(e) => {
+                error!(batch_index = index, error = %e, "batch build task failed");
+                return None;
+            }
+        };
+
+        info!(
+            batch_index = batch.index,
+            transactions = batch.transaction_count(),
+            uncompressed_bytes = batch.uncompressed_size,
+            compressed_bytes = batch.data.len(),
+            codec = ?self.codec.id(),
+            "built batch"
+        );
+        self.metrics
+            .record_batch(batch.uncompressed_size, batch.data.len());
+        self.status.set_last_batch(batch.index);
+        if let Err(e) = self.store.save(&batch) {
+            error!(batch_index = batch.index, error = %e, "failed to persist batch");
+        } else if let Some(withdrawals) = &self.withdrawals {
+            let count = withdrawals.record(&batch);
+            self.metrics.withdrawals.inc_by(count as u64);
+        }
+        self.publish_batch(&batch);
+        Some(batch)
+    }
+
+    async fn post_batch(&self, batch: Batch) {
+        if let Some(batch_tx) = &self.batch_tx {
+            // Waits for the submitter if it's behind, pushing back on admission
+            if batch_tx.send(batch).await.is_err() {
+                error!("batch submitter has stopped; batch not posted");
+            }
+        }
+    }
+
+    fn publish_batch(&self, batch: &Batch) {
+        for block in &batch.blocks {
+            let _ = self
+                .feed_tx
+                .send(FeedEvent::Block(BlockSummary::from(block)));
+        }
+        let _ = self
+            .feed_tx
+            .send(FeedEvent::Batch(BatchSummary::from(batch)));
+    }
+}
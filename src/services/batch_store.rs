@@ -0,0 +1,430 @@
+use crate::models::{Batch, BincodeTransaction, Block};
+use alloy::primitives::B256;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error("Batch store I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to encode or decode batch: {0}")]
+    Encoding(#[from] bincode::Error),
+    #[error("Failed to encode or decode posting record: {0}")]
+    Record(#[from] serde_json::Error),
+    #[error("Batch store is version {found}, newer than the supported {supported}")]
+    Version { found: u64, supported: u64 },
+}
+
+/// Layout of the stored `.bin` files. Anything that changes how `Batch`
+/// encodes bumps it, with a step in `BatchStore::migrate` from the last one.
+///
+/// - 0: blocks without an L1 origin
+/// - 1: blocks with an L1 origin
+/// - 2: transactions that may be deposits
+const STORE_VERSION: u64 = 2;
+
+/// Where a batch landed on L1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PostedBatch {
+    pub l1_tx_hash: B256,
+    pub l1_block: Option<u64>,
+    /// Checked against L1 after a reorg; missing from older records
+    #[serde(default)]
+    pub l1_block_hash: Option<B256>,
+}
+
+/// Every batch the sequencer builds, kept under `<data_dir>/batches` so that
+/// nothing is lost across restarts and unposted batches can be posted later.
+///
+/// Each batch is `<index>.bin` (bincode); once it's on L1 a `<index>.posted`
+/// JSON record sits next to it. `sequence.json` holds the sequence numbers
+/// reserved so far, and `version.json` the layout of the `.bin` files.
+pub struct BatchStore {
+    dir: PathBuf,
+}
+
+impl BatchStore {
+    pub fn open(data_dir: &Path) -> Result<Self, StoreError> {
+        let dir = data_dir.join("batches");
+        std::fs::create_dir_all(&dir)?;
+        let store = Self { dir };
+        store.migrate()?;
+        Ok(store)
+    }
+
+    /// Rewrites batches stored by earlier versions in the current layout.
+    /// Stores that predate `version.json` are version 0.
+    fn migrate(&self) -> Result<(), StoreError> {
+        let found = match std::fs::read(self.version_path()) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        if found > STORE_VERSION {
+            return Err(StoreError::Version {
+                found,
+                supported: STORE_VERSION,
+            });
+        }
+        if found < STORE_VERSION {
+            for index in self.indices()? {
+                let bytes = std::fs::read(self.batch_path(index))?;
+                let batch: Batch = match found {
+                    0 => bincode::deserialize::<BatchV0>(&bytes)?.into(),
+                    _ => bincode::deserialize::<BatchV1>(&bytes)?.into(),
+                };
+                self.save(&batch)?;
+            }
+            let json = serde_json::to_vec(&STORE_VERSION)?;
+            self.write_atomic(&self.version_path(), &json)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, batch: &Batch) -> Result<(), StoreError> {
+        let bytes = bincode::serialize(batch)?;
+        self.write_atomic(&self.batch_path(batch.index), &bytes)
+    }
+
+    pub fn load(&self, index: u64) -> Result<Option<Batch>, StoreError> {
+        match std::fs::read(self.batch_path(index)) {
+            Ok(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stored batch indices in ascending order.
+    pub fn indices(&self) -> Result<Vec<u64>, StoreError> {
+        let mut indices = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "bin") {
+                if let Some(index) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                {
+                    indices.push(index);
+                }
+            }
+        }
+        indices.sort_unstable();
+        Ok(indices)
+    }
+
+    /// The most recently built batch, which the queue resumes from.
+    pub fn latest(&self) -> Result<Option<Batch>, StoreError> {
+        match self.indices()?.last() {
+            Some(&index) => self.load(index),
+            None => Ok(None),
+        }
+    }
+
+    pub fn mark_posted(&self, index: u64, posted: &PostedBatch) -> Result<(), StoreError> {
+        let json = serde_json::to_vec(posted)?;
+        self.write_atomic(&self.posted_path(index), &json)
+    }
+
+    /// Drops the posting record, for a batch an L1 reorg took off L1.
+    pub fn unmark_posted(&self, index: u64) -> Result<(), StoreError> {
+        remove_if_exists(&self.posted_path(index))
+    }
+
+    pub fn posted(&self, index: u64) -> Result<Option<PostedBatch>, StoreError> {
+        match std::fs::read(self.posted_path(index)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes a batch that's being rebuilt, with any posting record.
+    pub fn remove(&self, index: u64) -> Result<(), StoreError> {
+        remove_if_exists(&self.posted_path(index))?;
+        remove_if_exists(&self.batch_path(index))
+    }
+
+    /// Stored batches with no posting record, oldest first.
+    pub fn unposted(&self) -> Result<Vec<Batch>, StoreError> {
+        let mut batches = Vec::new();
+        for index in self.indices()? {
+            if self.posted(index)?.is_none() {
+                batches.extend(self.load(index)?);
+            }
+        }
+        Ok(batches)
+    }
+
+    /// Sequence numbers below this may have been handed out, by this run or
+    /// an earlier one; `None` for stores that predate the record.
+    pub fn sequence_reserved(&self) -> Result<Option<u64>, StoreError> {
+        match std::fs::read(self.sequence_path()) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn reserve_sequence(&self, reserved: u64) -> Result<(), StoreError> {
+        let json = serde_json::to_vec(&reserved)?;
+        self.write_atomic(&self.sequence_path(), &json)
+    }
+
+    fn batch_path(&self, index: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.bin", index))
+    }
+
+    fn posted_path(&self, index: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.posted", index))
+    }
+
+    fn sequence_path(&self) -> PathBuf {
+        self.dir.join("sequence.json")
+    }
+
+    fn version_path(&self) -> PathBuf {
+        self.dir.join("version.json")
+    }
+
+    /// Write-then-rename so a crash never leaves a truncated file behind.
+    fn write_atomic(&self, path: &Path, bytes: &[u8]) -> Result<(), StoreError> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, bytes)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// `Batch` as version 0 stored it.
+#[derive(Deserialize)]
+struct BatchV0 {
+    index: u64,
+    blocks: Vec<BlockV0>,
+    data: Vec<u8>,
+    uncompressed_size: usize,
+}
+
+#[derive(Deserialize)]
+struct BlockV0 {
+    number: u64,
+    transactions: Vec<BincodeTransaction>,
+    parent_hash: B256,
+    state_root: B256,
+    timestamp: u64,
+}
+
+impl From<BatchV0> for Batch {
+    fn from(batch: BatchV0) -> Self {
+        let blocks = batch
+            .blocks
+            .into_iter()
+            .map(|b| BlockV1::from(b).into())
+            .collect();
+        Batch::new(batch.index, blocks, batch.data, batch.uncompressed_size)
+    }
+}
+
+/// `Batch` as version 1 stored it.
+#[derive(Deserialize)]
+struct BatchV1 {
+    index: u64,
+    blocks: Vec<BlockV1>,
+    data: Vec<u8>,
+    uncompressed_size: usize,
+}
+
+#[derive(Deserialize)]
+struct BlockV1 {
+    number: u64,
+    transactions: Vec<BincodeTransaction>,
+    parent_hash: B256,
+    state_root: B256,
+    timestamp: u64,
+    l1_origin: u64,
+}
+
+impl From<BlockV0> for BlockV1 {
+    fn from(block: BlockV0) -> Self {
+        Self {
+            number: block.number,
+            transactions: block.transactions,
+            parent_hash: block.parent_hash,
+            state_root: block.state_root,
+            timestamp: block.timestamp,
+            l1_origin: 0,
+        }
+    }
+}
+
+impl From<BlockV1> for Block {
+    fn from(block: BlockV1) -> Self {
+        Block::new(
+            block.number,
+            block.transactions.into_iter().map(Into::into).collect(),
+            block.parent_hash,
+            block.state_root,
+            block.timestamp,
+        )
+        .with_l1_origin(block.l1_origin)
+    }
+}
+
+impl From<BatchV1> for Batch {
+    fn from(batch: BatchV1) -> Self {
+        let blocks = batch.blocks.into_iter().map(Into::into).collect();
+        Batch::new(batch.index, blocks, batch.data, batch.uncompressed_size)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<(), StoreError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::L2Transaction;
+    use alloy::primitives::{b256, Address, Bytes, U256};
+
+    fn temp_store(name: &str) -> BatchStore {
+        let dir = std::env::temp_dir().join(format!("batch-store-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        BatchStore::open(&dir).unwrap()
+    }
+
+    fn batch(index: u64) -> Batch {
+        let block = Block::new(index + 1, Vec::new(), B256::ZERO, B256::ZERO, 0);
+        Batch::new(index, vec![block], vec![index as u8; 4], 8)
+    }
+
+    #[test]
+    fn test_save_load_and_posting_records() {
+        let store = temp_store("roundtrip");
+        for index in [0, 1, 2] {
+            store.save(&batch(index)).unwrap();
+        }
+
+        assert_eq!(store.indices().unwrap(), vec![0, 1, 2]);
+        assert_eq!(store.latest().unwrap().unwrap().index, 2);
+        assert_eq!(store.load(1).unwrap().unwrap().data, vec![1; 4]);
+        assert!(store.load(7).unwrap().is_none());
+
+        let posted = PostedBatch {
+            l1_tx_hash: B256::repeat_byte(0xab),
+            l1_block: Some(100),
+            l1_block_hash: Some(B256::repeat_byte(0xcd)),
+        };
+        store.mark_posted(0, &posted).unwrap();
+        store.mark_posted(1, &posted).unwrap();
+        assert_eq!(store.posted(0).unwrap(), Some(posted));
+
+        let unposted: Vec<u64> = store.unposted().unwrap().iter().map(|b| b.index).collect();
+        assert_eq!(unposted, vec![2]);
+
+        // Taken off L1 by a reorg, then rebuilt
+        store.unmark_posted(1).unwrap();
+        store.unmark_posted(1).unwrap();
+        let unposted: Vec<u64> = store.unposted().unwrap().iter().map(|b| b.index).collect();
+        assert_eq!(unposted, vec![1, 2]);
+        store.remove(2).unwrap();
+        assert_eq!(store.indices().unwrap(), vec![0, 1]);
+
+        assert_eq!(store.sequence_reserved().unwrap(), None);
+        store.reserve_sequence(1024).unwrap();
+        assert_eq!(store.sequence_reserved().unwrap(), Some(1024));
+        assert_eq!(store.indices().unwrap(), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_migrates_batches_without_l1_origin() {
+        let store = temp_store("migrate-v0");
+        let dir = store.dir.clone();
+        std::fs::remove_file(store.version_path()).unwrap();
+
+        // A version 0 batch: blocks are number, transactions, parent hash,
+        // state root and timestamp
+        let block = (
+            5u64,
+            Vec::<BincodeTransaction>::new(),
+            B256::repeat_byte(1),
+            B256::ZERO,
+            1_000u64,
+        );
+        let v0 = bincode::serialize(&(3u64, vec![block], vec![0xaau8; 4], 16u64)).unwrap();
+        std::fs::write(store.batch_path(3), v0).unwrap();
+        assert!(store.load(3).is_err());
+
+        let store = BatchStore::open(dir.parent().unwrap()).unwrap();
+        let migrated = store.load(3).unwrap().unwrap();
+        assert_eq!(migrated.index, 3);
+        assert_eq!(migrated.data, vec![0xaa; 4]);
+        assert_eq!(migrated.uncompressed_size, 16);
+        assert_eq!(migrated.blocks[0].number, 5);
+        assert_eq!(migrated.blocks[0].parent_hash, B256::repeat_byte(1));
+        assert_eq!(migrated.blocks[0].timestamp, 1_000);
+        assert_eq!(migrated.blocks[0].l1_origin, 0);
+
+        // Opening again leaves migrated batches alone
+        store.save(&batch(4)).unwrap();
+        let store = BatchStore::open(dir.parent().unwrap()).unwrap();
+        assert_eq!(store.indices().unwrap(), vec![3, 4]);
+
+        std::fs::write(store.version_path(), b"99").unwrap();
+        assert!(matches!(
+            BatchStore::open(dir.parent().unwrap()),
+            Err(StoreError::Version { found: 99, .. })
+        ));
+    }
+
+    #[test]
+    fn test_migrates_transactions_without_deposits() {
+        let store = temp_store("migrate-v1");
+        let dir = store.dir.clone();
+        std::fs::write(store.version_path(), b"1").unwrap();
+
+        let tx = L2Transaction::new(
+            7,
+            Address::repeat_byte(0x11),
+            Some(Address::repeat_byte(0x22)),
+            U256::from(1_000_000u64),
+            Bytes::from(vec![0xde, 0xad, 0xbe, 0xef]),
+            21_000,
+            U256::from(2_000_000_000u64),
+            Some(42161),
+            9,
+            U256::from(5u64),
+        );
+        let block = (
+            5u64,
+            vec![BincodeTransaction::from(&tx)],
+            B256::ZERO,
+            B256::ZERO,
+            1_000u64,
+            40u64,
+        );
+        let v1 = bincode::serialize(&(3u64, vec![block], vec![0xaau8; 4], 16u64)).unwrap();
+        std::fs::write(store.batch_path(3), v1).unwrap();
+
+        let store = BatchStore::open(dir.parent().unwrap()).unwrap();
+        let migrated = store.load(3).unwrap().unwrap();
+        assert_eq!(migrated.blocks[0].l1_origin, 40);
+        let migrated_tx = &migrated.blocks[0].transactions[0];
+        assert!(!migrated_tx.is_deposit());
+        assert_eq!(migrated_tx.hash(), tx.hash());
+        assert_eq!(
+            migrated_tx.hash(),
+            b256!("2b884a7f31baee962aaa5b2f91c2001ba669b863c5cddba8595f11d4f446fb85")
+        );
+    }
+
+    #[test]
+    fn test_reads_records_without_block_hash() {
+        let json = format!(r#"{{"l1_tx_hash":"{}","l1_block":7}}"#, B256::ZERO);
+        let posted: PostedBatch = serde_json::from_str(&json).unwrap();
+        assert_eq!(posted.l1_block_hash, None);
+    }
+}
@@ -0,0 +1,450 @@
+use crate::models::{Batch, Block, SequencingWindow};
+use crate::services::batch_store::{BatchStore, PostedBatch, StoreError};
+use crate::services::l1_head::{L1Head, L1Reorg};
+use crate::services::metrics::Metrics;
+use crate::services::queue_service::QueueHandle;
+use crate::services::status::SequencerStatus;
+use alloy::eips::BlockNumHash;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::Address;
+use alloy::providers::Provider;
+use alloy::rpc::types::{TransactionReceipt, TransactionRequest};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tracing::{error, info, warn, Instrument};
+
+/// Attempts per batch before it's given up on
+const MAX_ATTEMPTS: u32 = 3;
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+/// How often a paused submitter checks held batches against their deadline
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(12);
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubmitError {
+    #[error("Failed to send batch transaction: {0}")]
+    Send(String),
+    #[error("Failed waiting for batch receipt: {0}")]
+    Receipt(String),
+    #[error("Batch transaction {0} reverted")]
+    Reverted(alloy::primitives::B256),
+}
+
+/// Posts each batch's compressed payload to the L1 batch inbox as calldata,
+/// in the order the queue processor built them.
+pub struct BatchSubmitter<P: Provider> {
+    provider: P,
+    inbox: Address,
+    batch_rx: mpsc::Receiver<Batch>,
+    /// Batches left unposted by an earlier run, posted before anything new
+    backlog: VecDeque<Batch>,
+    store: Arc<BatchStore>,
+    metrics: Arc<Metrics>,
+    status: Arc<SequencerStatus>,
+    paused_tx: Arc<watch::Sender<bool>>,
+    paused_rx: watch::Receiver<bool>,
+    l1_head: Arc<L1Head>,
+    window: SequencingWindow,
+    /// L1 blocks before a held batch's deadline at which a pause is ignored
+    window_margin: u64,
+    reorg_rx: Option<mpsc::Receiver<L1Reorg>>,
+    /// Rebuilds unposted blocks after a reorg
+    queue: Option<QueueHandle>,
+    /// Set after a rewind rebuilt batches from this index, until a rebuilt
+    /// one arrives; batches sent before it are dropped
+    stale_from: Option<u64>,
+}
+
+/// Pauses and resumes posting on a running `BatchSubmitter`.
+#[derive(Clone)]
+pub struct BatchSubmitterHandle {
+    paused_tx: Arc<watch::Sender<bool>>,
+}
+
+impl BatchSubmitterHandle {
+    /// While paused, built batches are held in order and posted on resume.
+    pub fn set_paused(&self, paused: bool) {
+        self.paused_tx.send_replace(paused);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused_tx.borrow()
+    }
+}
+
+impl<P: Provider> BatchSubmitter<P> {
+    /// `provider` must have a wallet for the posting key.
+    pub fn new(
+        provider: P,
+        inbox: Address,
+        batch_rx: mpsc::Receiver<Batch>,
+        store: Arc<BatchStore>,
+        metrics: Arc<Metrics>,
+        status: Arc<SequencerStatus>,
+    ) -> Self {
+        let (paused_tx, paused_rx) = watch::channel(false);
+        Self {
+            provider,
+            inbox,
+            batch_rx,
+            backlog: VecDeque::new(),
+            store,
+            metrics,
+            status,
+            paused_tx: Arc::new(paused_tx),
+            paused_rx,
+            l1_head: Arc::new(L1Head::new()),
+            window: SequencingWindow::default(),
+            window_margin: 0,
+            reorg_rx: None,
+            queue: None,
+            stale_from: None,
+        }
+    }
+
+    /// Posts held batches even while paused once the sequencing window of
+    /// the oldest has `margin` L1 blocks left.
+    pub fn with_sequencing_window(
+        mut self,
+        l1_head: Arc<L1Head>,
+        window: SequencingWindow,
+        margin: u64,
+    ) -> Self {
+        self.l1_head = l1_head;
+        self.window = window;
+        self.window_margin = margin;
+        self
+    }
+
+    /// Recovers from the L1 reorgs sent to `reorg_rx`: batches the reorg
+    /// dropped are posted again, and `queue` re-sequences unposted blocks
+    /// whose L1 origin was reorged out.
+    pub fn with_reorg_recovery(
+        mut self,
+        reorg_rx: mpsc::Receiver<L1Reorg>,
+        queue: QueueHandle,
+    ) -> Self {
+        self.reorg_rx = Some(reorg_rx);
+        self.queue = Some(queue);
+        self
+    }
+
+    /// Queues batches from an earlier run (see `BatchStore::unposted`) ahead
+    /// of new ones.
+    pub fn with_backlog(mut self, batches: Vec<Batch>) -> Self {
+        self.backlog.extend(batches);
+        self
+    }
+
+    pub fn handle(&self) -> BatchSubmitterHandle {
+        BatchSubmitterHandle {
+            paused_tx: self.paused_tx.clone(),
+        }
+    }
+
+    /// Posts batches until the queue processor stops and the backlog is
+    /// drained. Batches still held when paused at that point stay in the
+    /// store for the next run.
+    pub async fn run(&mut self) {
+        if !self.backlog.is_empty() {
+            info!(
+                batches = self.backlog.len(),
+                "posting batches left from an earlier run"
+            );
+        }
+        let mut backlog = std::mem::take(&mut self.backlog);
+        let mut closed = false;
+
+        loop {
+            let paused = *self.paused_rx.borrow_and_update();
+            let due = backlog
+                .front()
+                .is_some_and(|batch| self.near_deadline(batch));
+            if paused && due {
+                warn!("posting despite the pause: the oldest held batch's sequencing window is closing");
+            }
+            if !paused || due {
+                if let Some(batch) = backlog.pop_front() {
+                    self.post(batch).await;
+                    continue;
+                }
+            }
+            if closed && (backlog.is_empty() || paused) {
+                if !backlog.is_empty() {
+                    warn!(
+                        batches = backlog.len(),
+                        "stopping with unposted batches; they stay in the store"
+                    );
+                }
+                break;
+            }
+
+            let mut reorg = None;
+            tokio::select! {
+                batch = self.batch_rx.recv(), if !closed => match batch {
+                    Some(batch) => self.hold(batch, &mut backlog),
+                    None => closed = true,
+                },
+                _ = self.paused_rx.changed() => {
+                    info!(paused = *self.paused_rx.borrow(), "L1 posting pause changed");
+                }
+                _ = tokio::time::sleep(DEADLINE_CHECK_INTERVAL), if paused && !backlog.is_empty() => {}
+                Some(event) = next_reorg(&mut self.reorg_rx) => reorg = Some(event),
+            }
+            if let Some(reorg) = reorg {
+                self.recover_from_reorg(reorg, &mut backlog).await;
+            }
+        }
+    }
+
+    /// Queues a batch from the builder for posting, unless a rewind has
+    /// since rebuilt it.
+    fn hold(&mut self, batch: Batch, backlog: &mut VecDeque<Batch>) {
+        if let Some(from) = self.stale_from.filter(|&from| batch.index >= from) {
+            if !self.is_current(&batch) {
+                info!(
+                    batch_index = batch.index,
+                    "dropping batch rebuilt after L1 reorg"
+                );
+                return;
+            }
+            // Everything the builder sends after this was built after the rewind
+            info!(
+                batch_index = batch.index,
+                rewound_from = from,
+                "rebuilt batches arriving"
+            );
+            self.stale_from = None;
+        }
+        backlog.push_back(batch);
+    }
+
+    /// Whether `batch` is still the stored batch at its index.
+    fn is_current(&self, batch: &Batch) -> bool {
+        match self.store.load(batch.index) {
+            Ok(stored) => {
+                stored.and_then(|b| b.blocks.last().map(Block::hash))
+                    == batch.blocks.last().map(Block::hash)
+            }
+            Err(e) => {
+                warn!(batch_index = batch.index, error = %e, "failed to load batch; posting it as held");
+                true
+            }
+        }
+    }
+
+    async fn recover_from_reorg(&mut self, reorg: L1Reorg, backlog: &mut VecDeque<Batch>) {
+        warn!(
+            fork = reorg.fork,
+            l1_head = reorg.head,
+            "recovering from L1 reorg"
+        );
+        let dropped = match self.recheck_posted(reorg.fork).await {
+            Ok(dropped) => dropped,
+            Err(e) => {
+                error!(error = %e, "failed to check posted batches after L1 reorg");
+                Vec::new()
+            }
+        };
+
+        let mut rewound_from = None;
+        if let Some(queue) = &self.queue {
+            let rewind = queue.rewind(reorg.fork);
+            tokio::pin!(rewind);
+            // The queue flushes its built batches here before rewinding
+            loop {
+                tokio::select! {
+                    result = &mut rewind => {
+                        match result {
+                            Ok(from) => rewound_from = from,
+                            Err(e) => error!(error = %e, "failed to rewind unposted blocks"),
+                        }
+                        break;
+                    }
+                    Some(batch) = self.batch_rx.recv() => backlog.push_back(batch),
+                }
+            }
+        }
+
+        // Dropped batches were posted, so they come before everything held
+        for batch in dropped.into_iter().rev() {
+            if rewound_from.is_none_or(|from| batch.index < from) {
+                backlog.push_front(batch);
+            }
+        }
+        if let Some(from) = rewound_from {
+            backlog.retain(|batch| batch.index < from || self.is_current(batch));
+            self.stale_from = Some(from);
+        }
+    }
+
+    /// Has the L1 head tracker watch the block a batch landed in, so a reorg
+    /// of it is noticed even between polls.
+    fn observe_landing(&self, posted: &PostedBatch) {
+        if let (Some(number), Some(hash)) = (posted.l1_block, posted.l1_block_hash) {
+            self.l1_head.observe(BlockNumHash::new(number, hash));
+        }
+    }
+
+    /// Checks every batch recorded as posted at or after L1 block `fork`.
+    /// Records for transactions the new chain includes are updated; the
+    /// rest are removed and their batches returned, oldest first, to post
+    /// again.
+    async fn recheck_posted(&self, fork: u64) -> Result<Vec<Batch>, StoreError> {
+        let mut dropped = Vec::new();
+        for index in self.store.indices()?.into_iter().rev() {
+            let Some(posted) = self.store.posted(index)? else {
+                continue;
+            };
+            if posted.l1_block.is_some_and(|block| block < fork) {
+                break;
+            }
+            match self
+                .provider
+                .get_transaction_receipt(posted.l1_tx_hash)
+                .await
+            {
+                Ok(Some(receipt)) if receipt.status() => {
+                    let included = PostedBatch {
+                        l1_tx_hash: receipt.transaction_hash,
+                        l1_block: receipt.block_number,
+                        l1_block_hash: receipt.block_hash,
+                    };
+                    if included != posted {
+                        info!(
+                            batch_index = index,
+                            l1_block = receipt.block_number,
+                            "batch transaction included again after L1 reorg"
+                        );
+                        self.store.mark_posted(index, &included)?;
+                        self.observe_landing(&included);
+                    }
+                    continue;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!(batch_index = index, error = %e, "failed to fetch batch receipt after L1 reorg");
+                }
+            }
+            // Posting twice is harmless; derivation skips batches it has seen
+            warn!(
+                batch_index = index,
+                l1_tx_hash = %posted.l1_tx_hash,
+                "L1 reorg dropped batch; posting it again"
+            );
+            self.store.unmark_posted(index)?;
+            self.metrics.l1_reorged_batches.inc();
+            dropped.extend(self.store.load(index)?);
+        }
+        dropped.reverse();
+        Ok(dropped)
+    }
+
+    /// L1 block by which `batch` must land, from its earliest block's origin.
+    fn deadline(&self, batch: &Batch) -> Option<u64> {
+        let origin = batch.blocks.iter().map(|b| b.l1_origin).min()?;
+        Some(self.window.deadline(origin))
+    }
+
+    fn near_deadline(&self, batch: &Batch) -> bool {
+        match (self.deadline(batch), self.l1_head.get()) {
+            (Some(deadline), Some(head)) => head.saturating_add(self.window_margin) >= deadline,
+            _ => false,
+        }
+    }
+
+    async fn post(&self, batch: Batch) {
+        let span = tracing::info_span!("post_batch", batch_index = batch.index);
+        self.status.l1_post_started();
+        let posted = self.submit_with_retries(&batch).instrument(span).await;
+        self.status.l1_post_finished(posted);
+    }
+
+    /// Returns whether the batch was posted.
+    async fn submit_with_retries(&self, batch: &Batch) -> bool {
+        if let (Some(deadline), Some(head)) = (self.deadline(batch), self.l1_head.get()) {
+            if head >= deadline {
+                error!(
+                    deadline,
+                    l1_head = head,
+                    "sequencing window closed before the batch was posted; derivation will reject it"
+                );
+            }
+        }
+        for attempt in 1..=MAX_ATTEMPTS {
+            match self.submit(batch).await {
+                Ok(receipt) => {
+                    let posted = PostedBatch {
+                        l1_tx_hash: receipt.transaction_hash,
+                        l1_block: receipt.block_number,
+                        l1_block_hash: receipt.block_hash,
+                    };
+                    if let Err(e) = self.store.mark_posted(batch.index, &posted) {
+                        error!(error = %e, "failed to record posted batch");
+                    }
+                    self.observe_landing(&posted);
+                    info!(
+                        l1_tx_hash = %receipt.transaction_hash,
+                        l1_block = receipt.block_number,
+                        gas_used = receipt.gas_used,
+                        "posted batch to L1"
+                    );
+                    return true;
+                }
+                Err(e) => {
+                    warn!(attempt, max_attempts = MAX_ATTEMPTS, error = %e, "posting batch failed");
+                    self.metrics.l1_posts.with_label_values(&["failed"]).inc();
+                    if attempt < MAX_ATTEMPTS {
+                        tokio::time::sleep(RETRY_DELAY).await;
+                    }
+                }
+            }
+        }
+        error!("giving up on batch");
+        false
+    }
+
+    async fn submit(&self, batch: &Batch) -> Result<TransactionReceipt, SubmitError> {
+        let tx = TransactionRequest::default()
+            .with_to(self.inbox)
+            .with_input(batch.data.clone());
+
+        let start = Instant::now();
+        let pending = self
+            .provider
+            .send_transaction(tx)
+            .await
+            .map_err(|e| SubmitError::Send(e.to_string()))?;
+        let receipt = pending
+            .get_receipt()
+            .await
+            .map_err(|e| SubmitError::Receipt(e.to_string()))?;
+
+        self.metrics
+            .l1_post_latency
+            .observe(start.elapsed().as_secs_f64());
+        // Reverted transactions still pay for gas
+        self.metrics.l1_post_gas_used.inc_by(receipt.gas_used);
+        self.metrics
+            .l1_post_cost_wei
+            .inc_by(receipt.gas_used as f64 * receipt.effective_gas_price as f64);
+
+        if !receipt.status() {
+            return Err(SubmitError::Reverted(receipt.transaction_hash));
+        }
+        self.metrics
+            .l1_posts
+            .with_label_values(&["confirmed"])
+            .inc();
+        Ok(receipt)
+    }
+}
+
+/// The next reorg, or never when there's no reorg detection.
+async fn next_reorg(reorg_rx: &mut Option<mpsc::Receiver<L1Reorg>>) -> Option<L1Reorg> {
+    match reorg_rx {
+        Some(reorg_rx) => reorg_rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
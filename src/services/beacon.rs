@@ -0,0 +1,230 @@
+//! Reads blob sidecars from a beacon node's REST API, for inbox transactions
+//! that carry their batch in blobs rather than calldata.
+
+use alloy::consensus::{Blob, Bytes48, SidecarCoder, SimpleCoder};
+use alloy::eips::eip4844::{kzg_to_versioned_hash, BlobTransactionSidecarItem};
+use alloy::primitives::{Bytes, B256};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use tokio::sync::OnceCell;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BlobError {
+    #[error("Beacon request failed: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Unexpected beacon response: {0}")]
+    Response(String),
+    #[error("blob {0} isn't on the beacon node; it may have been pruned")]
+    Missing(B256),
+    #[error("blob {0} fails its KZG proof")]
+    InvalidProof(B256),
+}
+
+impl BlobError {
+    /// The blobs can't be read, as opposed to the beacon node being
+    /// unreachable or misbehaving.
+    pub fn is_unreadable(&self) -> bool {
+        matches!(self, BlobError::Missing(_) | BlobError::InvalidProof(_))
+    }
+}
+
+/// Every beacon API response wraps its result in `data`.
+#[derive(Deserialize)]
+struct Envelope<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct Genesis {
+    genesis_time: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+struct Spec {
+    seconds_per_slot: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Sidecar {
+    /// Decimal, like every integer in the beacon API
+    pub index: String,
+    pub blob: Bytes,
+    pub kzg_commitment: Bytes48,
+    pub kzg_proof: Bytes48,
+}
+
+/// Maps execution block timestamps to slots and fetches their sidecars.
+pub struct BeaconClient {
+    http: reqwest::Client,
+    url: String,
+    /// Genesis time and seconds per slot, read once
+    clock: OnceCell<(u64, u64)>,
+}
+
+impl BeaconClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            clock: OnceCell::new(),
+        }
+    }
+
+    /// What the blobs behind `versioned_hashes` hold, for a transaction in
+    /// the execution block with `timestamp`. Each blob is checked against
+    /// its versioned hash and KZG proof.
+    pub async fn blob_data(
+        &self,
+        timestamp: u64,
+        versioned_hashes: &[B256],
+    ) -> Result<Vec<u8>, BlobError> {
+        let slot = self.slot(timestamp).await?;
+        let sidecars: Vec<Sidecar> = self
+            .get(&format!("eth/v1/beacon/blob_sidecars/{}", slot))
+            .await?;
+        read_blobs(sidecars, versioned_hashes)
+    }
+
+    async fn slot(&self, timestamp: u64) -> Result<u64, BlobError> {
+        let &(genesis_time, seconds_per_slot) = self
+            .clock
+            .get_or_try_init(|| async {
+                let genesis: Genesis = self.get("eth/v1/beacon/genesis").await?;
+                let spec: Spec = self.get("eth/v1/config/spec").await?;
+                Ok::<_, BlobError>((
+                    parse_int(&genesis.genesis_time)?,
+                    parse_int(&spec.seconds_per_slot)?,
+                ))
+            })
+            .await?;
+        timestamp
+            .checked_sub(genesis_time)
+            .and_then(|elapsed| elapsed.checked_div(seconds_per_slot))
+            .ok_or_else(|| {
+                BlobError::Response(format!(
+                    "no slot for timestamp {} (genesis {}, {}s slots)",
+                    timestamp, genesis_time, seconds_per_slot
+                ))
+            })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, BlobError> {
+        let bytes = self
+            .http
+            .get(format!("{}/{}", self.url, path))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let envelope: Envelope<T> = serde_json::from_slice(&bytes)
+            .map_err(|e| BlobError::Response(format!("{}: {}", path, e)))?;
+        Ok(envelope.data)
+    }
+}
+
+fn parse_int(value: &str) -> Result<u64, BlobError> {
+    value
+        .parse()
+        .map_err(|_| BlobError::Response(format!("{:?} is not an integer", value)))
+}
+
+/// Picks the blobs behind `versioned_hashes` out of a slot's sidecars, in
+/// the transaction's order, and decodes them as a `SidecarBuilder` with
+/// `SimpleCoder` encodes data. Blobs that don't hold data in that encoding
+/// are returned as they are, so they fail to decode as a batch.
+pub fn read_blobs(sidecars: Vec<Sidecar>, versioned_hashes: &[B256]) -> Result<Vec<u8>, BlobError> {
+    let mut items = Vec::with_capacity(sidecars.len());
+    for sidecar in sidecars {
+        let blob = Blob::try_from(sidecar.blob.as_ref()).map_err(|_| {
+            BlobError::Response(format!("blob {} is the wrong size", sidecar.index))
+        })?;
+        items.push(BlobTransactionSidecarItem {
+            index: parse_int(&sidecar.index)?,
+            blob: Box::new(blob),
+            kzg_commitment: sidecar.kzg_commitment,
+            kzg_proof: sidecar.kzg_proof,
+        });
+    }
+
+    let mut blobs = Vec::with_capacity(versioned_hashes.len());
+    for &hash in versioned_hashes {
+        let item = items
+            .iter()
+            .find(|item| kzg_to_versioned_hash(item.kzg_commitment.as_slice()) == hash)
+            .ok_or(BlobError::Missing(hash))?;
+        item.verify_blob_kzg_proof()
+            .map_err(|_| BlobError::InvalidProof(hash))?;
+        blobs.push(*item.blob.clone());
+    }
+
+    Ok(match SimpleCoder::default().decode_all(&blobs) {
+        Some(data) => data.concat(),
+        None => blobs
+            .iter()
+            .flat_map(|blob| blob.as_slice())
+            .copied()
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::consensus::SidecarBuilder;
+
+    fn sidecars(data: &[u8]) -> (Vec<Sidecar>, Vec<B256>) {
+        let sidecar = SidecarBuilder::<SimpleCoder>::from_slice(data)
+            .build()
+            .unwrap();
+        let hashes = sidecar.versioned_hashes().collect();
+        let sidecars = sidecar
+            .into_iter()
+            .map(|item| Sidecar {
+                index: item.index.to_string(),
+                blob: Bytes::copy_from_slice(item.blob.as_slice()),
+                kzg_commitment: item.kzg_commitment,
+                kzg_proof: item.kzg_proof,
+            })
+            .collect();
+        (sidecars, hashes)
+    }
+
+    #[test]
+    fn test_reads_blobs_in_transaction_order() {
+        // Spans two blobs
+        let data: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        let (mut slot, hashes) = sidecars(&data);
+        assert_eq!(hashes.len(), 2);
+        // Another transaction's blob in the same slot, and a shuffled order
+        let (other, other_hashes) = sidecars(b"another batch");
+        slot.extend(other);
+        slot.reverse();
+
+        assert_eq!(read_blobs(slot.clone(), &hashes).unwrap(), data);
+        assert_eq!(
+            read_blobs(slot.clone(), &other_hashes).unwrap(),
+            b"another batch"
+        );
+
+        let missing = B256::repeat_byte(0x01);
+        assert!(matches!(
+            read_blobs(slot.clone(), &[missing]),
+            Err(BlobError::Missing(hash)) if hash == missing
+        ));
+
+        // A blob the commitment doesn't open to
+        let forged = slot
+            .iter()
+            .position(|s| kzg_to_versioned_hash(s.kzg_commitment.as_slice()) == hashes[0])
+            .unwrap();
+        let mut blob = slot[forged].blob.to_vec();
+        blob[100] ^= 1;
+        slot[forged].blob = blob.into();
+        assert!(matches!(
+            read_blobs(slot, &hashes),
+            Err(BlobError::InvalidProof(hash)) if hash == hashes[0]
+        ));
+    }
+}
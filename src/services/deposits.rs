@@ -0,0 +1,386 @@
+use crate::models::{Deposit, L2Transaction};
+use crate::services::batch_store::{BatchStore, StoreError};
+use crate::services::l1_head::L1Head;
+use crate::services::queue_service::QueueHandle;
+use alloy::primitives::{Address, B256, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::{Filter, Log};
+use alloy::sol_types::SolEvent;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// Most L1 blocks read with one `eth_getLogs` call
+const MAX_BLOCK_RANGE: u64 = 1_000;
+
+alloy::sol! {
+    /// Emitted by the L1 bridge for each deposit. A zero `to` creates a
+    /// contract.
+    event DepositInitiated(
+        address indexed from,
+        address indexed to,
+        uint256 mint,
+        uint256 value,
+        uint64 gasLimit,
+        bytes data
+    );
+}
+
+/// The L2 transaction for a `DepositInitiated` log. It has no nonce, gas
+/// price or chain id, and its `l1_block_number` is the block the log is in.
+pub fn deposit_from_log(log: &Log) -> Result<L2Transaction, String> {
+    let (Some(l1_tx_hash), Some(log_index), Some(l1_block)) =
+        (log.transaction_hash, log.log_index, log.block_number)
+    else {
+        return Err("deposit log isn't in a block".to_string());
+    };
+    let event = log
+        .log_decode::<DepositInitiated>()
+        .map_err(|e| format!("undecodable deposit log: {}", e))?
+        .inner
+        .data;
+
+    let to = (event.to != Address::ZERO).then_some(event.to);
+    let deposit = Deposit {
+        l1_tx_hash,
+        log_index,
+        mint: event.mint,
+    };
+    Ok(L2Transaction::new(
+        0,
+        event.from,
+        to,
+        event.value,
+        event.data,
+        event.gasLimit,
+        U256::ZERO,
+        None,
+        l1_block,
+        U256::ZERO,
+    )
+    .with_deposit(deposit))
+}
+
+/// How far the queue processor has read deposits, which bounds the L1
+/// origin of the blocks it seals: a deposit in any later L1 block isn't due
+/// until after it.
+#[derive(Debug)]
+pub struct DepositCursor {
+    deadline: u64,
+    /// First L1 block not read yet; `None` until the first read on a new
+    /// chain
+    next_block: Option<u64>,
+    /// Deposits from `next_block` on that are already in blocks, with their
+    /// L1 block
+    included: HashMap<(B256, u64), u64>,
+}
+
+impl DepositCursor {
+    pub fn new(deadline: u64) -> Self {
+        Self {
+            deadline,
+            next_block: None,
+            included: HashMap::new(),
+        }
+    }
+
+    /// Continues after the stored chain. Its newest block's L1 origin is at
+    /// most `deadline` past the last L1 block read, so reading starts again
+    /// from there, skipping the deposits already included.
+    pub fn resume(store: &BatchStore, deadline: u64) -> Result<Self, StoreError> {
+        let mut cursor = Self::new(deadline);
+        let Some(origin) = store
+            .latest()?
+            .and_then(|batch| batch.blocks.last().map(|block| block.l1_origin))
+        else {
+            return Ok(cursor);
+        };
+        let next_block = (origin + 1).saturating_sub(deadline);
+        cursor.next_block = Some(next_block);
+
+        // A deposit is never in a block with an earlier L1 origin
+        'batches: for index in store.indices()?.into_iter().rev() {
+            let Some(batch) = store.load(index)? else {
+                continue;
+            };
+            for block in batch.blocks.iter().rev() {
+                if block.l1_origin < next_block {
+                    break 'batches;
+                }
+                for tx in &block.transactions {
+                    if let Some(deposit) = &tx.deposit {
+                        if tx.l1_block_number >= next_block {
+                            cursor.included.insert(deposit.id(), tx.l1_block_number);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(cursor)
+    }
+
+    pub fn next_block(&self) -> Option<u64> {
+        self.next_block
+    }
+
+    /// Latest L1 origin a block may have: deposits in L1 blocks not read
+    /// yet are due no earlier than one past it.
+    pub fn origin_limit(&self) -> Option<u64> {
+        self.next_block
+            .map(|next| next.saturating_add(self.deadline) - 1)
+    }
+
+    /// Records that L1 blocks `from` through `through` were read, returning
+    /// the `deposits` in them not already included. `None` if reading had
+    /// moved on from `from`, as it does after a reorg.
+    pub fn advance(
+        &mut self,
+        from: u64,
+        through: u64,
+        mut deposits: Vec<L2Transaction>,
+    ) -> Option<Vec<L2Transaction>> {
+        if self.next_block.is_some_and(|next| next != from) || through < from {
+            return None;
+        }
+        deposits.retain(|tx| {
+            tx.deposit
+                .as_ref()
+                .is_some_and(|deposit| !self.included.contains_key(&deposit.id()))
+        });
+        self.included.retain(|_, l1_block| *l1_block > through);
+        self.next_block = Some(through + 1);
+        Some(deposits)
+    }
+
+    /// Reads L1 again from `fork` on, forgetting included deposits from
+    /// there; the blocks holding them are being rebuilt.
+    pub fn rewind(&mut self, fork: u64) {
+        self.next_block = self.next_block.map(|next| next.min(fork));
+        self.included.retain(|_, l1_block| *l1_block < fork);
+    }
+}
+
+/// Reads deposits from the L1 bridge's logs once they have enough
+/// confirmations, and hands them to the queue processor.
+pub struct DepositWatcher<P: Provider> {
+    provider: P,
+    bridge: Address,
+    queue: QueueHandle,
+    l1_head: Arc<L1Head>,
+    confirmations: u64,
+    interval: Duration,
+}
+
+impl<P: Provider> DepositWatcher<P> {
+    pub fn new(
+        provider: P,
+        bridge: Address,
+        queue: QueueHandle,
+        l1_head: Arc<L1Head>,
+        confirmations: u64,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            provider,
+            bridge,
+            queue,
+            l1_head,
+            confirmations,
+            interval,
+        }
+    }
+
+    /// Polls forever. Failures are logged once until a poll succeeds again;
+    /// the L1 origin of new blocks stops advancing while they last.
+    pub async fn run(&self) {
+        let mut failing = false;
+        loop {
+            match self.poll().await {
+                Ok(()) => failing = false,
+                Err(e) if !failing => {
+                    failing = true;
+                    warn!(error = %e, "failed to read L1 deposits");
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Reads every confirmed L1 block the processor hasn't read yet.
+    async fn poll(&self) -> Result<(), String> {
+        let Some(head) = self.l1_head.get() else {
+            return Ok(());
+        };
+        let confirmed = head.saturating_sub(self.confirmations);
+        loop {
+            let next = self
+                .queue
+                .next_deposit_block()
+                .await
+                .map_err(|e| e.to_string())?;
+            // A new chain starts with deposits confirmed since it began
+            let from = next.unwrap_or(confirmed);
+            if from > confirmed {
+                return Ok(());
+            }
+            let through = confirmed.min(from + MAX_BLOCK_RANGE - 1);
+
+            let deposits = self.read(from, through).await?;
+            match self
+                .queue
+                .submit_deposits(from, through, deposits)
+                .await
+                .map_err(|e| e.to_string())?
+            {
+                Some(queued) if !queued.is_empty() => {
+                    info!(
+                        from_l1_block = from,
+                        to_l1_block = through,
+                        deposits = queued.len(),
+                        "queued L1 deposits"
+                    );
+                }
+                Some(_) => {}
+                None => debug!(
+                    from_l1_block = from,
+                    "deposit reading rewound; reading again"
+                ),
+            }
+            if through == confirmed {
+                return Ok(());
+            }
+        }
+    }
+
+    async fn read(&self, from: u64, through: u64) -> Result<Vec<L2Transaction>, String> {
+        let filter = Filter::new()
+            .address(self.bridge)
+            .event_signature(DepositInitiated::SIGNATURE_HASH)
+            .from_block(from)
+            .to_block(through);
+        let logs = self
+            .provider
+            .get_logs(&filter)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        let mut deposits = Vec::with_capacity(logs.len());
+        for log in &logs {
+            match deposit_from_log(log) {
+                Ok(deposit) => deposits.push(deposit),
+                // Retrying can't fix it, and would stall every later deposit
+                Err(e) => error!(
+                    error = %e,
+                    l1_tx_hash = ?log.transaction_hash,
+                    "skipping deposit log"
+                ),
+            }
+        }
+        Ok(deposits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Batch, Block};
+    use alloy::primitives::Bytes;
+
+    fn log(event: &DepositInitiated, block: u64, index: u64) -> Log {
+        Log {
+            inner: alloy::primitives::Log {
+                address: Address::repeat_byte(0xb1),
+                data: event.encode_log_data(),
+            },
+            block_number: Some(block),
+            transaction_hash: Some(B256::with_last_byte(index as u8)),
+            log_index: Some(index),
+            ..Default::default()
+        }
+    }
+
+    fn deposit(l1_block: u64, index: u64) -> L2Transaction {
+        let event = DepositInitiated {
+            from: Address::repeat_byte(0xaa),
+            to: Address::repeat_byte(0xbb),
+            mint: U256::from(5),
+            value: U256::from(3),
+            gasLimit: 100_000,
+            data: Bytes::new(),
+        };
+        deposit_from_log(&log(&event, l1_block, index)).unwrap()
+    }
+
+    #[test]
+    fn test_converts_bridge_logs() {
+        let event = DepositInitiated {
+            from: Address::repeat_byte(0xaa),
+            to: Address::ZERO,
+            mint: U256::from(10u64.pow(18)),
+            value: U256::from(7),
+            gasLimit: 250_000,
+            data: Bytes::from(vec![0x60, 0x80]),
+        };
+        let tx = deposit_from_log(&log(&event, 1_000, 3)).unwrap();
+        assert_eq!(tx.from, event.from);
+        assert_eq!(tx.to, None);
+        assert_eq!(tx.value, event.value);
+        assert_eq!(tx.gas_limit, 250_000);
+        assert_eq!(tx.data, event.data);
+        assert_eq!(tx.l1_block_number, 1_000);
+        let deposit = tx.deposit.unwrap();
+        assert_eq!(deposit.mint, event.mint);
+        assert_eq!(deposit.id(), (B256::with_last_byte(3), 3));
+
+        let mut pending = log(&event, 1_000, 3);
+        pending.block_number = None;
+        assert!(deposit_from_log(&pending).is_err());
+    }
+
+    #[test]
+    fn test_cursor_resumes_without_repeats() {
+        let data_dir = std::env::temp_dir().join(format!("deposit-cursor-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let store = BatchStore::open(&data_dir).unwrap();
+        let blocks = vec![
+            Block::new(1, vec![deposit(890, 1)], B256::ZERO, B256::ZERO, 0).with_l1_origin(900),
+            Block::new(2, vec![deposit(950, 2)], B256::ZERO, B256::ZERO, 0).with_l1_origin(950),
+        ];
+        store.save(&Batch::new(0, blocks, Vec::new(), 0)).unwrap();
+
+        // Nothing read yet on a new chain
+        let mut cursor = DepositCursor::new(100);
+        assert_eq!(cursor.origin_limit(), None);
+        assert_eq!(
+            cursor
+                .advance(800, 850, vec![deposit(820, 1)])
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(cursor.origin_limit(), Some(950));
+
+        // The origin of 950 allows reading up to 850
+        let mut cursor = DepositCursor::resume(&store, 100).unwrap();
+        assert_eq!(cursor.next_block(), Some(851));
+        assert_eq!(cursor.origin_limit(), Some(950));
+        assert!(cursor.advance(900, 960, Vec::new()).is_none());
+        let queued = cursor
+            .advance(
+                851,
+                960,
+                vec![deposit(890, 1), deposit(950, 2), deposit(955, 3)],
+            )
+            .unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].l1_block_number, 955);
+        assert_eq!(cursor.origin_limit(), Some(1_060));
+
+        cursor.rewind(955);
+        assert_eq!(cursor.next_block(), Some(955));
+        cursor.rewind(2_000);
+        assert_eq!(cursor.next_block(), Some(955));
+    }
+}
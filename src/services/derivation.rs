@@ -0,0 +1,701 @@
+//! Rebuilds the L2 chain from batches posted to the L1 inbox, and checks it
+//! against the batch store. This is what any node following the rollup would
+//! do; running it against the sequencer's own store shows whether what's on
+//! L1 is what was sequenced.
+
+use crate::models::{decode_blocks, Block, PayloadDecoder, SequencingWindow};
+use crate::services::batch_store::{BatchStore, StoreError};
+use crate::services::beacon::{BeaconClient, BlobError};
+use alloy::consensus::Transaction as _;
+use alloy::eips::BlockNumberOrTag;
+use alloy::network::TransactionResponse;
+use alloy::primitives::{Address, B256};
+use alloy::providers::Provider;
+use alloy::rpc::types::BlockTransactionsKind;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::RangeInclusive;
+use tracing::{debug, info};
+
+#[derive(Debug, thiserror::Error)]
+pub enum DerivationError {
+    #[error("L1 request failed: {0}")]
+    L1(String),
+    #[error("L1 block {0} not found")]
+    MissingBlock(u64),
+    #[error(transparent)]
+    Beacon(BlobError),
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+/// A successful transaction to the batch inbox.
+#[derive(Debug, Clone)]
+pub struct InboxPayload {
+    pub l1_block: u64,
+    pub l1_tx_hash: B256,
+    /// Calldata, or what the transaction's blobs hold if it has any
+    pub data: Vec<u8>,
+    /// Why the transaction's blobs couldn't be read, leaving `data` empty
+    pub blobs_not_read: Option<String>,
+}
+
+/// Where the L1 chain and the batch store disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// An inbox transaction that isn't a batch that can be decoded
+    Unreadable { l1_tx_hash: B256, reason: String },
+    /// An inbox transaction whose blobs couldn't be fetched or checked
+    BlobsNotRead { l1_tx_hash: B256, reason: String },
+    /// A batch that doesn't continue from the last derived block
+    Gap {
+        l1_tx_hash: B256,
+        expected: u64,
+        found: u64,
+    },
+    /// A derived block the store doesn't have
+    NotInStore { number: u64 },
+    /// A derived block that differs from the stored one
+    Mismatch {
+        number: u64,
+        stored: B256,
+        derived: B256,
+    },
+    /// A batch recorded as posted in the scanned range with no blocks on L1
+    MissingOnL1 { batch_index: u64, l1_tx_hash: B256 },
+    /// A derived block that breaks the sequencing window rules
+    OutsideWindow { number: u64, reason: String },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Unreadable { l1_tx_hash, reason } => write!(
+                f,
+                "L1 tx {} is not a readable batch: {}",
+                l1_tx_hash, reason
+            ),
+            Divergence::BlobsNotRead { l1_tx_hash, reason } => {
+                write!(f, "blobs of L1 tx {} can't be read: {}", l1_tx_hash, reason)
+            }
+            Divergence::Gap {
+                l1_tx_hash,
+                expected,
+                found,
+            } => write!(
+                f,
+                "L1 tx {} starts at block {}, expected {}",
+                l1_tx_hash, found, expected
+            ),
+            Divergence::NotInStore { number } => {
+                write!(f, "block {} is on L1 but not in the store", number)
+            }
+            Divergence::Mismatch {
+                number,
+                stored,
+                derived,
+            } => write!(
+                f,
+                "block {} is {} in the store but {} on L1",
+                number, stored, derived
+            ),
+            Divergence::MissingOnL1 {
+                batch_index,
+                l1_tx_hash,
+            } => write!(
+                f,
+                "batch {} was recorded as posted in L1 tx {} but isn't on L1",
+                batch_index, l1_tx_hash
+            ),
+            Divergence::OutsideWindow { number, reason } => {
+                write!(
+                    f,
+                    "block {} is outside the sequencing window: {}",
+                    number, reason
+                )
+            }
+        }
+    }
+}
+
+/// Finds batch payloads in L1 blocks.
+pub struct InboxScanner<P: Provider> {
+    provider: P,
+    inbox: Address,
+    /// Only transactions from this address count, when set
+    poster: Option<Address>,
+    /// Where blobs are fetched from; without one they aren't read
+    beacon: Option<BeaconClient>,
+}
+
+impl<P: Provider> InboxScanner<P> {
+    pub fn new(provider: P, inbox: Address, poster: Option<Address>) -> Self {
+        Self {
+            provider,
+            inbox,
+            poster,
+            beacon: None,
+        }
+    }
+
+    /// Reads the batches of blob-carrying inbox transactions from `beacon`.
+    pub fn with_beacon(mut self, beacon: BeaconClient) -> Self {
+        self.beacon = Some(beacon);
+        self
+    }
+
+    pub async fn latest_block(&self) -> Result<u64, DerivationError> {
+        self.provider
+            .get_block_number()
+            .await
+            .map_err(|e| DerivationError::L1(e.to_string()))
+    }
+
+    /// Inbox payloads in `blocks`, in L1 order. Reverted transactions are
+    /// left out.
+    pub async fn scan(
+        &self,
+        blocks: RangeInclusive<u64>,
+    ) -> Result<Vec<InboxPayload>, DerivationError> {
+        let mut payloads = Vec::new();
+        for number in blocks {
+            let block = self
+                .provider
+                .get_block_by_number(
+                    BlockNumberOrTag::Number(number),
+                    BlockTransactionsKind::Full,
+                )
+                .await
+                .map_err(|e| DerivationError::L1(e.to_string()))?
+                .ok_or(DerivationError::MissingBlock(number))?;
+
+            for tx in block.transactions.txns() {
+                if tx.to() != Some(self.inbox) || self.poster.is_some_and(|p| tx.from() != p) {
+                    continue;
+                }
+                let receipt = self
+                    .provider
+                    .get_transaction_receipt(tx.tx_hash())
+                    .await
+                    .map_err(|e| DerivationError::L1(e.to_string()))?;
+                if !receipt.is_some_and(|r| r.status()) {
+                    debug!(l1_tx_hash = %tx.tx_hash(), "skipping reverted inbox transaction");
+                    continue;
+                }
+                let mut payload = InboxPayload {
+                    l1_block: number,
+                    l1_tx_hash: tx.tx_hash(),
+                    data: tx.input().to_vec(),
+                    blobs_not_read: None,
+                };
+                if let Some(hashes) = tx.blob_versioned_hashes().filter(|h| !h.is_empty()) {
+                    payload.data.clear();
+                    match &self.beacon {
+                        None => payload.blobs_not_read = Some("no beacon endpoint".to_string()),
+                        Some(beacon) => {
+                            match beacon.blob_data(block.header.timestamp, hashes).await {
+                                Ok(data) => payload.data = data,
+                                Err(e) if e.is_unreadable() => {
+                                    payload.blobs_not_read = Some(e.to_string())
+                                }
+                                Err(e) => return Err(DerivationError::Beacon(e)),
+                            }
+                        }
+                    }
+                }
+                payloads.push(payload);
+            }
+        }
+        Ok(payloads)
+    }
+}
+
+/// Rebuilds blocks from inbox payloads, one payload at a time in L1 order.
+pub struct Derivation {
+    decoder: PayloadDecoder,
+    window: SequencingWindow,
+    head_number: u64,
+    head_hash: B256,
+    head_l1_origin: u64,
+    blocks: Vec<Block>,
+    divergences: Vec<Divergence>,
+}
+
+impl Derivation {
+    /// Starts from genesis, like a fresh queue.
+    pub fn new(decoder: PayloadDecoder) -> Self {
+        Self {
+            decoder,
+            window: SequencingWindow::default(),
+            head_number: 0,
+            head_hash: B256::ZERO,
+            head_l1_origin: 0,
+            blocks: Vec::new(),
+            divergences: Vec::new(),
+        }
+    }
+
+    /// The window the sequencer ran with; blocks outside it are reported.
+    pub fn with_sequencing_window(mut self, window: SequencingWindow) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Continues the chain after `head`, for scans that don't start at the
+    /// first batch.
+    pub fn resume_from(&mut self, head: &Block) {
+        self.head_number = head.number;
+        self.head_hash = head.hash();
+        self.head_l1_origin = head.l1_origin;
+    }
+
+    pub fn apply(&mut self, payload: &InboxPayload) {
+        if let Some(reason) = &payload.blobs_not_read {
+            self.divergences.push(Divergence::BlobsNotRead {
+                l1_tx_hash: payload.l1_tx_hash,
+                reason: reason.clone(),
+            });
+            return;
+        }
+        let blocks = match decode_blocks(&payload.data, &self.decoder, self.head_hash) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                self.divergences.push(Divergence::Unreadable {
+                    l1_tx_hash: payload.l1_tx_hash,
+                    reason: e.to_string(),
+                });
+                return;
+            }
+        };
+        let (Some(first), Some(last)) = (blocks.first(), blocks.last()) else {
+            return;
+        };
+
+        // Posting is retried after timeouts, so a batch can land twice
+        let Some(expected) = self
+            .head_number
+            .checked_add(1)
+            .filter(|&next| last.number >= next)
+        else {
+            debug!(l1_tx_hash = %payload.l1_tx_hash, "skipping batch that was already derived");
+            return;
+        };
+        if first.number != expected {
+            self.divergences.push(Divergence::Gap {
+                l1_tx_hash: payload.l1_tx_hash,
+                expected,
+                found: first.number,
+            });
+        }
+        for block in &blocks {
+            if let Err(reason) = self.check_window(block, payload.l1_block) {
+                self.divergences.push(Divergence::OutsideWindow {
+                    number: block.number,
+                    reason,
+                });
+            }
+            self.head_l1_origin = block.l1_origin;
+        }
+        self.head_number = last.number;
+        self.head_hash = last.hash();
+        self.blocks.extend(blocks);
+    }
+
+    /// A block's L1 origin never goes backwards, is older than the L1 block
+    /// its batch landed in, and is at most a window newer than that, and its
+    /// transactions are within the window behind it. Deposits come first and
+    /// only mustn't be newer than the origin.
+    fn check_window(&self, block: &Block, l1_block: u64) -> Result<(), String> {
+        if block.l1_origin < self.head_l1_origin {
+            return Err(format!(
+                "L1 origin {} is before the previous block's {}",
+                block.l1_origin, self.head_l1_origin
+            ));
+        }
+        if block.l1_origin >= l1_block {
+            return Err(format!(
+                "L1 origin {} isn't before the L1 block {} the batch landed in",
+                block.l1_origin, l1_block
+            ));
+        }
+        if l1_block > self.window.deadline(block.l1_origin) {
+            return Err(format!(
+                "batch landed in L1 block {}, after the deadline {}",
+                l1_block,
+                self.window.deadline(block.l1_origin)
+            ));
+        }
+        let deposits = block
+            .transactions
+            .iter()
+            .take_while(|tx| tx.is_deposit())
+            .count();
+        for (i, tx) in block.transactions.iter().enumerate() {
+            if !tx.is_deposit() {
+                self.window
+                    .check(tx.l1_block_number, block.l1_origin)
+                    .map_err(|e| e.to_string())?;
+            } else if i >= deposits {
+                return Err(format!("deposit {} follows other transactions", tx.hash()));
+            } else if tx.l1_block_number > block.l1_origin {
+                // Old deposits are still due; only the future is off limits
+                return Err(format!(
+                    "deposit {} from L1 block {} is ahead of the L1 origin",
+                    tx.hash(),
+                    tx.l1_block_number
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Compares the derived blocks with the store and returns every
+    /// divergence, including those found while deriving. `scanned` is the L1
+    /// range the payloads came from; stored batches posted inside it must
+    /// have been derived.
+    pub fn verify(
+        self,
+        store: &BatchStore,
+        scanned: RangeInclusive<u64>,
+    ) -> Result<Vec<Divergence>, StoreError> {
+        let mut divergences = self.divergences;
+        let derived: HashMap<u64, B256> =
+            self.blocks.iter().map(|b| (b.number, b.hash())).collect();
+        let mut stored = HashMap::new();
+
+        for index in store.indices()? {
+            let Some(batch) = store.load(index)? else {
+                continue;
+            };
+            let posted = store.posted(index)?;
+            if let Some(posted) =
+                posted.filter(|p| p.l1_block.is_some_and(|b| scanned.contains(&b)))
+            {
+                if batch
+                    .blocks
+                    .iter()
+                    .any(|b| !derived.contains_key(&b.number))
+                {
+                    divergences.push(Divergence::MissingOnL1 {
+                        batch_index: index,
+                        l1_tx_hash: posted.l1_tx_hash,
+                    });
+                }
+            }
+            stored.extend(batch.blocks.iter().map(|b| (b.number, b.hash())));
+        }
+
+        for block in &self.blocks {
+            let derived = block.hash();
+            match stored.get(&block.number) {
+                None => divergences.push(Divergence::NotInStore {
+                    number: block.number,
+                }),
+                Some(&stored) if stored != derived => divergences.push(Divergence::Mismatch {
+                    number: block.number,
+                    stored,
+                    derived,
+                }),
+                Some(_) => {}
+            }
+        }
+        Ok(divergences)
+    }
+}
+
+/// Where a verifier run starts: the earliest posting record at or after
+/// `from` (all of them when unset), and the stored block just before that
+/// batch to chain onto.
+pub fn starting_point(
+    store: &BatchStore,
+    from: Option<u64>,
+) -> Result<(u64, Option<Block>), StoreError> {
+    let mut start = None;
+    for index in store.indices()? {
+        let Some(l1_block) = store.posted(index)?.and_then(|p| p.l1_block) else {
+            continue;
+        };
+        if from.is_none_or(|from| l1_block >= from) {
+            start = Some((index, l1_block));
+            break;
+        }
+    }
+
+    let Some((index, l1_block)) = start else {
+        return Ok((from.unwrap_or(0), None));
+    };
+    let head = match index.checked_sub(1) {
+        Some(previous) => store
+            .load(previous)?
+            .and_then(|batch| batch.blocks.last().cloned()),
+        None => None,
+    };
+    Ok((from.unwrap_or(l1_block), head))
+}
+
+/// Derives the chain from L1 blocks `from..=to` (`to` defaults to the
+/// latest) and returns where it diverges from the store.
+pub async fn verify_posted_batches<P: Provider>(
+    scanner: &InboxScanner<P>,
+    mut derivation: Derivation,
+    store: &BatchStore,
+    from: Option<u64>,
+    to: Option<u64>,
+) -> Result<Vec<Divergence>, DerivationError> {
+    let (from, head) = starting_point(store, from)?;
+    let to = match to {
+        Some(to) => to,
+        None => scanner.latest_block().await?,
+    };
+
+    if let Some(head) = &head {
+        derivation.resume_from(head);
+    }
+    info!(
+        from,
+        to,
+        after_block = head.map(|b| b.number),
+        "scanning L1 for batches"
+    );
+
+    let payloads = scanner.scan(from..=to).await?;
+    for payload in &payloads {
+        derivation.apply(payload);
+    }
+    info!(
+        payloads = payloads.len(),
+        blocks = derivation.blocks().len(),
+        "derived blocks from L1"
+    );
+    Ok(derivation.verify(store, from..=to)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::codec::Brotli;
+    use crate::models::{compress_span_batch, Batch, Deposit, L2Transaction, WindowPolicy};
+    use crate::services::batch_store::PostedBatch;
+    use alloy::primitives::{Bytes, U256};
+
+    fn temp_store(name: &str) -> BatchStore {
+        let dir = std::env::temp_dir().join(format!("derivation-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        BatchStore::open(&dir).unwrap()
+    }
+
+    fn transaction(nonce: u64) -> L2Transaction {
+        L2Transaction::new(
+            nonce,
+            Address::repeat_byte(0x11),
+            Some(Address::repeat_byte(0x22)),
+            U256::from(nonce),
+            Bytes::new(),
+            21000,
+            U256::from(1_000_000_000u64),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    /// Stores `count` single-block batches of two transactions, batch `i`
+    /// posted in L1 block `100 + i`, and returns their inbox payloads.
+    fn post_batches(store: &BatchStore, count: u64) -> Vec<InboxPayload> {
+        let codec = Brotli::default();
+        let mut parent_hash = B256::ZERO;
+        let mut payloads = Vec::new();
+        for index in 0..count {
+            let txs = (index * 2..index * 2 + 2).map(transaction).collect();
+            let block = Block::new(index + 1, txs, parent_hash, B256::ZERO, 1_000 + index);
+            parent_hash = block.hash();
+            let compressed = compress_span_batch(std::slice::from_ref(&block), &codec).unwrap();
+            let batch = Batch::new(
+                index,
+                vec![block],
+                compressed.data,
+                compressed.uncompressed_size,
+            );
+            store.save(&batch).unwrap();
+
+            let l1_tx_hash = B256::with_last_byte(index as u8 + 1);
+            store
+                .mark_posted(
+                    index,
+                    &PostedBatch {
+                        l1_tx_hash,
+                        l1_block: Some(100 + index),
+                        l1_block_hash: None,
+                    },
+                )
+                .unwrap();
+            payloads.push(InboxPayload {
+                l1_block: 100 + index,
+                l1_tx_hash,
+                data: batch.data,
+                blobs_not_read: None,
+            });
+        }
+        payloads
+    }
+
+    fn derive(payloads: &[InboxPayload]) -> Derivation {
+        let mut derivation = Derivation::new(PayloadDecoder::default());
+        for payload in payloads {
+            derivation.apply(payload);
+        }
+        derivation
+    }
+
+    #[test]
+    fn test_matches_store() {
+        let store = temp_store("matches");
+        let mut payloads = post_batches(&store, 4);
+        // A retried post landing twice is harmless
+        payloads.insert(2, payloads[1].clone());
+
+        let derivation = derive(&payloads);
+        assert_eq!(derivation.blocks().len(), 4);
+        assert_eq!(derivation.verify(&store, 0..=200).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn test_reports_divergence() {
+        let store = temp_store("diverges");
+        let payloads = post_batches(&store, 4);
+
+        // Batch 1 never made it and batch 3's payload is garbage
+        let mut on_l1 = vec![
+            payloads[0].clone(),
+            payloads[2].clone(),
+            payloads[3].clone(),
+        ];
+        on_l1[2].data.truncate(10);
+        let mut blob_tx = payloads[0].clone();
+        blob_tx.l1_tx_hash = B256::repeat_byte(0xbb);
+        blob_tx.data = Vec::new();
+        blob_tx.blobs_not_read = Some("no beacon endpoint".to_string());
+        on_l1.push(blob_tx);
+        let divergences = derive(&on_l1).verify(&store, 0..=200).unwrap();
+
+        assert!(divergences.contains(&Divergence::Gap {
+            l1_tx_hash: payloads[2].l1_tx_hash,
+            expected: 2,
+            found: 3,
+        }));
+        assert!(divergences.iter().any(|d| matches!(
+            d,
+            Divergence::Unreadable { l1_tx_hash, .. } if *l1_tx_hash == payloads[3].l1_tx_hash
+        )));
+        assert!(divergences.contains(&Divergence::BlobsNotRead {
+            l1_tx_hash: B256::repeat_byte(0xbb),
+            reason: "no beacon endpoint".to_string(),
+        }));
+        let missing: Vec<_> = divergences
+            .iter()
+            .filter_map(|d| match d {
+                Divergence::MissingOnL1 { batch_index, .. } => Some(*batch_index),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(missing, vec![1, 3]);
+        // Block 3 chains onto block 1, so its hash can't match
+        assert!(divergences
+            .iter()
+            .any(|d| matches!(d, Divergence::Mismatch { number: 3, .. })));
+    }
+
+    #[test]
+    fn test_checks_sequencing_window() {
+        let store = temp_store("window");
+        // Blocks have L1 origin 0 and land in L1 blocks 100 and 101
+        let payloads = post_batches(&store, 2);
+        let window = SequencingWindow {
+            size: 100,
+            policy: WindowPolicy::Reject,
+        };
+
+        let mut derivation =
+            Derivation::new(PayloadDecoder::default()).with_sequencing_window(window);
+        for payload in &payloads {
+            derivation.apply(payload);
+        }
+        let divergences = derivation.verify(&store, 0..=200).unwrap();
+        assert_eq!(divergences.len(), 1, "{:?}", divergences);
+        assert!(matches!(
+            divergences[0],
+            Divergence::OutsideWindow { number: 2, .. }
+        ));
+
+        // A transaction ahead of its block's origin
+        let mut ahead = transaction(0);
+        ahead.l1_block_number = 4;
+        let block = Block::new(1, vec![ahead], B256::ZERO, B256::ZERO, 1_000).with_l1_origin(3);
+        let payload = InboxPayload {
+            l1_block: 5,
+            l1_tx_hash: B256::repeat_byte(0xee),
+            data: compress_span_batch(&[block], &Brotli::default())
+                .unwrap()
+                .data,
+            blobs_not_read: None,
+        };
+        let derivation = derive(&[payload]);
+        assert!(matches!(
+            derivation.divergences[..],
+            [Divergence::OutsideWindow { number: 1, .. }]
+        ));
+
+        // Deposits from before the window are still due, but go first
+        let deposit = transaction(1).with_deposit(Deposit {
+            l1_tx_hash: B256::repeat_byte(0xdd),
+            log_index: 0,
+            mint: U256::from(1),
+        });
+        let mut recent = transaction(2);
+        recent.l1_block_number = 250;
+        for (txs, valid) in [
+            (vec![deposit.clone(), recent.clone()], true),
+            (vec![recent, deposit], false),
+        ] {
+            let block = Block::new(1, txs, B256::ZERO, B256::ZERO, 1_000).with_l1_origin(300);
+            let payload = InboxPayload {
+                l1_block: 301,
+                l1_tx_hash: B256::repeat_byte(0xef),
+                data: compress_span_batch(&[block], &Brotli::default())
+                    .unwrap()
+                    .data,
+                blobs_not_read: None,
+            };
+            let mut derivation =
+                Derivation::new(PayloadDecoder::default()).with_sequencing_window(window);
+            derivation.apply(&payload);
+            assert_eq!(derivation.divergences.is_empty(), valid);
+        }
+    }
+
+    #[test]
+    fn test_resumes_mid_chain() {
+        let store = temp_store("resume");
+        let payloads = post_batches(&store, 4);
+
+        let (from, head) = starting_point(&store, Some(102)).unwrap();
+        assert_eq!(from, 102);
+        let head = head.unwrap();
+        assert_eq!(head.number, 2);
+
+        let mut derivation = Derivation::new(PayloadDecoder::default());
+        derivation.resume_from(&head);
+        for payload in &payloads[2..] {
+            derivation.apply(payload);
+        }
+        assert_eq!(derivation.verify(&store, 102..=200).unwrap(), Vec::new());
+
+        let (from, head) = starting_point(&store, None).unwrap();
+        assert_eq!(from, 100);
+        assert!(head.is_none());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::models::{Batch, Block, L2Transaction};
+use alloy::primitives::{Address, Selector, B256, U256};
+use serde::{Deserialize, Serialize};
+
+/// Events published to `/transaction_feed` subscribers.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedEvent {
+    Transaction(L2Transaction),
+    /// A transaction already published here that an admin removed from the
+    /// queue; it won't be in any block.
+    Evicted(L2Transaction),
+    Block(BlockSummary),
+    Batch(BatchSummary),
+}
+
+impl FeedEvent {
+    pub fn stream(&self) -> Stream {
+        match self {
+            FeedEvent::Transaction(_) | FeedEvent::Evicted(_) => Stream::Transactions,
+            FeedEvent::Block(_) => Stream::Blocks,
+            FeedEvent::Batch(_) => Stream::Batches,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockSummary {
+    pub number: u64,
+    pub hash: B256,
+    pub parent_hash: B256,
+    pub state_root: B256,
+    pub timestamp: u64,
+    pub transactions: Vec<B256>,
+}
+
+impl From<&Block> for BlockSummary {
+    fn from(block: &Block) -> Self {
+        Self {
+            number: block.number,
+            hash: block.hash(),
+            parent_hash: block.parent_hash,
+            state_root: block.state_root,
+            timestamp: block.timestamp,
+            transactions: block.transactions.iter().map(|tx| tx.hash()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchSummary {
+    pub index: u64,
+    pub blocks: Vec<u64>,
+    pub transaction_count: usize,
+    pub size: usize,
+}
+
+impl From<&Batch> for BatchSummary {
+    fn from(batch: &Batch) -> Self {
+        Self {
+            index: batch.index,
+            blocks: batch.blocks.iter().map(|b| b.number).collect(),
+            transaction_count: batch.transaction_count(),
+            size: batch.data.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stream {
+    Transactions,
+    Blocks,
+    Batches,
+}
+
+/// Messages a feed client may send after connecting.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Subscribe(Subscription),
+    Auth { token: String },
+}
+
+/// Which streams a client receives and how transactions are filtered.
+///
+/// Until a client sends a subscription it receives every transaction, which
+/// matches the feed's original behaviour.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Subscription {
+    #[serde(default = "default_streams")]
+    pub streams: Vec<Stream>,
+    #[serde(default)]
+    pub filter: TransactionFilter,
+}
+
+fn default_streams() -> Vec<Stream> {
+    vec![Stream::Transactions]
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            streams: default_streams(),
+            filter: TransactionFilter::default(),
+        }
+    }
+}
+
+impl Subscription {
+    pub fn matches(&self, event: &FeedEvent) -> bool {
+        if !self.streams.contains(&event.stream()) {
+            return false;
+        }
+        match event {
+            FeedEvent::Transaction(tx) | FeedEvent::Evicted(tx) => self.filter.matches(tx),
+            FeedEvent::Block(_) | FeedEvent::Batch(_) => true,
+        }
+    }
+}
+
+/// Transaction filter. Each non-empty list must contain a match; empty lists
+/// match everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct TransactionFilter {
+    pub from: Vec<Address>,
+    pub to: Vec<Address>,
+    /// Transactions sent to, or deploying, any of these contracts, value
+    /// transfers included
+    pub contract: Vec<Address>,
+    /// First four bytes of calldata
+    pub selector: Vec<Selector>,
+    pub min_value: Option<U256>,
+}
+
+impl TransactionFilter {
+    pub fn matches(&self, tx: &L2Transaction) -> bool {
+        if !self.from.is_empty() && !self.from.contains(&tx.from) {
+            return false;
+        }
+
+        if !self.to.is_empty() && !tx.to.is_some_and(|to| self.to.contains(&to)) {
+            return false;
+        }
+
+        if !self.contract.is_empty() {
+            let contract = tx.to.unwrap_or_else(|| tx.from.create(tx.nonce));
+            if !self.contract.contains(&contract) {
+                return false;
+            }
+        }
+
+        if !self.selector.is_empty() {
+            let matches = tx.to.is_some()
+                && tx.data.len() >= 4
+                && self.selector.iter().any(|s| s.as_slice() == &tx.data[..4]);
+            if !matches {
+                return false;
+            }
+        }
+
+        if let Some(min_value) = self.min_value {
+            if tx.value < min_value {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::{address, Bytes};
+
+    fn create_tx(to: Option<Address>, data: &'static [u8], value: u64) -> L2Transaction {
+        L2Transaction::new(
+            3,
+            address!("1111111111111111111111111111111111111111"),
+            to,
+            U256::from(value),
+            Bytes::from_static(data),
+            21000,
+            U256::from(1),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    fn parse(json: &str) -> Subscription {
+        match serde_json::from_str(json).unwrap() {
+            ClientMessage::Subscribe(subscription) => subscription,
+            other => panic!("Expected subscription, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_default_subscription_receives_all_transactions() {
+        let subscription = Subscription::default();
+        let tx = create_tx(None, b"", 0);
+        assert!(subscription.matches(&FeedEvent::Transaction(tx.clone())));
+        assert!(subscription.matches(&FeedEvent::Evicted(tx)));
+    }
+
+    #[test]
+    fn test_stream_selection() {
+        let subscription = parse(r#"{"type":"subscribe","streams":["batches"]}"#);
+        let tx = create_tx(None, b"", 0);
+        assert!(!subscription.matches(&FeedEvent::Transaction(tx)));
+        assert!(subscription.matches(&FeedEvent::Batch(BatchSummary {
+            index: 0,
+            blocks: vec![1],
+            transaction_count: 1,
+            size: 10,
+        })));
+    }
+
+    #[test]
+    fn test_selector_and_value_filter() {
+        let token = address!("2222222222222222222222222222222222222222");
+        let subscription = parse(
+            r#"{"type":"subscribe","filter":{"selector":["0xa9059cbb"],"min_value":"0x10"}}"#,
+        );
+
+        let transfer = create_tx(Some(token), &[0xa9, 0x05, 0x9c, 0xbb, 0x00], 0x10);
+        assert!(subscription.matches(&FeedEvent::Transaction(transfer)));
+
+        let too_small = create_tx(Some(token), &[0xa9, 0x05, 0x9c, 0xbb], 0x0f);
+        assert!(!subscription.matches(&FeedEvent::Transaction(too_small)));
+
+        let other_call = create_tx(Some(token), &[0x09, 0x5e, 0xa7, 0xb3], 0x10);
+        assert!(!subscription.matches(&FeedEvent::Transaction(other_call)));
+    }
+
+    #[test]
+    fn test_contract_filter_matches_calls_transfers_and_deployments() {
+        let from = address!("1111111111111111111111111111111111111111");
+        let deployed = from.create(3);
+        let filter = TransactionFilter {
+            contract: vec![deployed],
+            ..Default::default()
+        };
+
+        assert!(filter.matches(&create_tx(None, b"\x60\x80", 0)));
+        assert!(filter.matches(&create_tx(Some(deployed), b"\x01\x02\x03\x04", 0)));
+        // A plain value transfer to the contract
+        assert!(filter.matches(&create_tx(Some(deployed), b"", 1)));
+        assert!(!filter.matches(&create_tx(Some(from), b"", 1)));
+    }
+}
@@ -0,0 +1,323 @@
+use crate::services::metrics::Metrics;
+use alloy::eips::{BlockNumHash, BlockNumberOrTag};
+use alloy::primitives::B256;
+use alloy::providers::Provider;
+use alloy::rpc::types::BlockTransactionsKind;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// The latest L1 block number the sequencer has seen, shared by admission,
+/// the queue processor and the batch submitter.
+pub struct L1Head {
+    /// `u64::MAX` until the first successful poll
+    number: AtomicU64,
+    /// Blocks seen elsewhere, for the tracker to check for reorgs
+    observed: Mutex<Vec<BlockNumHash>>,
+    /// Furthest `sequencing_head` may go; `u64::MAX` when unlimited
+    limit: AtomicU64,
+}
+
+impl L1Head {
+    pub fn new() -> Self {
+        Self {
+            number: AtomicU64::new(u64::MAX),
+            observed: Mutex::new(Vec::new()),
+            limit: AtomicU64::new(u64::MAX),
+        }
+    }
+
+    /// Has the tracker watch `block`, such as one a batch landed in, even
+    /// if it's reorged out before the next poll.
+    pub fn observe(&self, block: BlockNumHash) {
+        self.observed.lock().unwrap().push(block);
+    }
+
+    pub fn get(&self) -> Option<u64> {
+        match self.number.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            number => Some(number),
+        }
+    }
+
+    pub fn set(&self, number: u64) {
+        self.number.store(number, Ordering::Relaxed);
+    }
+
+    /// The head new blocks take their L1 origin from and admission checks
+    /// `l1_block_number`s against: `get()`, but no further than `hold_at`.
+    pub fn sequencing_head(&self) -> Option<u64> {
+        self.get()
+            .map(|head| head.min(self.limit.load(Ordering::Relaxed)))
+    }
+
+    /// Keeps `sequencing_head` at or before `limit`.
+    pub fn hold_at(&self, limit: u64) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+}
+
+impl Default for L1Head {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// L1 replaced every block from `fork` on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct L1Reorg {
+    pub fork: u64,
+    pub head: u64,
+}
+
+/// Hashes of the most recent canonical L1 blocks.
+#[derive(Debug)]
+pub struct RecentBlocks {
+    blocks: BTreeMap<u64, B256>,
+    depth: usize,
+}
+
+impl RecentBlocks {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            blocks: BTreeMap::new(),
+            depth,
+        }
+    }
+
+    /// Records a canonical block outside of `sync`, replacing whatever was
+    /// recorded at its height.
+    pub fn record(&mut self, block: BlockNumHash) {
+        if self
+            .blocks
+            .first_key_value()
+            .is_some_and(|(&oldest, _)| block.number < oldest)
+        {
+            return;
+        }
+        self.blocks.insert(block.number, block.hash);
+        while self.blocks.len() > self.depth {
+            self.blocks.pop_first();
+        }
+    }
+
+    /// Records `latest` as the head, fetching its ancestors with `fetch`
+    /// back to the newest block already recorded. Returns the first block
+    /// number whose recorded hash is no longer canonical, if any.
+    pub async fn sync<F, Fut, E>(
+        &mut self,
+        latest: BlockNumHash,
+        mut fetch: F,
+    ) -> Result<Option<u64>, E>
+    where
+        F: FnMut(u64) -> Fut,
+        Fut: Future<Output = Result<BlockNumHash, E>>,
+    {
+        // After a long outage there's too much to walk back through
+        let resume = self
+            .blocks
+            .range(..=latest.number)
+            .next_back()
+            .is_some_and(|(&number, _)| latest.number - number <= self.depth as u64);
+
+        let mut fork = None;
+        let mut canonical = Vec::new();
+        let mut block = latest;
+        if resume {
+            loop {
+                match self.blocks.get(&block.number) {
+                    Some(&hash) if hash == block.hash => break,
+                    Some(_) => fork = Some(block.number),
+                    None => {}
+                }
+                canonical.push(block);
+                if block.number == 0 || self.blocks.range(..block.number).next().is_none() {
+                    break;
+                }
+                block = fetch(block.number - 1).await?;
+            }
+        } else {
+            self.blocks.clear();
+            canonical.push(latest);
+        }
+
+        // Blocks past the new head were dropped without replacement
+        if self.blocks.range(latest.number + 1..).next().is_some() {
+            self.blocks.split_off(&(latest.number + 1));
+            fork = Some(fork.unwrap_or(latest.number + 1));
+        }
+        for block in canonical {
+            self.blocks.insert(block.number, block.hash);
+        }
+        while self.blocks.len() > self.depth {
+            self.blocks.pop_first();
+        }
+        Ok(fork)
+    }
+}
+
+/// Polls the L1 node for its latest block, and notices when L1 reorgs.
+pub struct L1HeadTracker<P: Provider> {
+    provider: P,
+    head: Arc<L1Head>,
+    interval: Duration,
+    metrics: Arc<Metrics>,
+    recent: RecentBlocks,
+    /// Where detected reorgs are sent, when something recovers from them
+    reorg_tx: Option<mpsc::Sender<L1Reorg>>,
+}
+
+impl<P: Provider> L1HeadTracker<P> {
+    pub fn new(provider: P, head: Arc<L1Head>, interval: Duration, metrics: Arc<Metrics>) -> Self {
+        Self {
+            provider,
+            head,
+            interval,
+            metrics,
+            recent: RecentBlocks::new(64),
+            reorg_tx: None,
+        }
+    }
+
+    /// Keeps `depth` block hashes and sends each reorg to `reorg_tx`.
+    pub fn with_reorg_detection(mut self, depth: usize, reorg_tx: mpsc::Sender<L1Reorg>) -> Self {
+        self.recent = RecentBlocks::new(depth);
+        self.reorg_tx = Some(reorg_tx);
+        self
+    }
+
+    /// Polls forever. Failures keep the last known head and are logged once
+    /// until a poll succeeds again.
+    pub async fn run(&mut self) {
+        let mut failing = false;
+        loop {
+            match self.poll().await {
+                Ok(number) => {
+                    if failing || self.head.get().is_none() {
+                        info!(l1_head = number, "tracking L1 head");
+                    }
+                    failing = false;
+                }
+                Err(e) if !failing => {
+                    failing = true;
+                    warn!(error = %e, last_known = self.head.get(), "failed to poll L1 head");
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    async fn poll(&mut self) -> Result<u64, String> {
+        let latest = block_ref(&self.provider, BlockNumberOrTag::Latest).await?;
+        // Blocks past what this node reports as the head wait for a later poll
+        let mut observed = std::mem::take(&mut *self.head.observed.lock().unwrap());
+        observed.retain(|block| {
+            if block.number > latest.number {
+                return true;
+            }
+            self.recent.record(*block);
+            false
+        });
+        self.head.observed.lock().unwrap().extend(observed);
+
+        let provider = &self.provider;
+        let fork = self
+            .recent
+            .sync(latest, |number| {
+                block_ref(provider, BlockNumberOrTag::Number(number))
+            })
+            .await?;
+
+        self.head.set(latest.number);
+        self.metrics.l1_head.set(latest.number as i64);
+        if let Some(fork) = fork {
+            warn!(fork, l1_head = latest.number, "L1 reorg");
+            self.metrics.l1_reorgs.inc();
+            if let Some(reorg_tx) = &self.reorg_tx {
+                let _ = reorg_tx
+                    .send(L1Reorg {
+                        fork,
+                        head: latest.number,
+                    })
+                    .await;
+            }
+        }
+        Ok(latest.number)
+    }
+}
+
+async fn block_ref<P: Provider>(
+    provider: &P,
+    number: BlockNumberOrTag,
+) -> Result<BlockNumHash, String> {
+    let block = provider
+        .get_block_by_number(number, BlockTransactionsKind::Hashes)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("L1 block {} not found", number))?;
+    Ok(BlockNumHash::new(block.header.number, block.header.hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::keccak256;
+    use std::convert::Infallible;
+
+    /// Block `number` on the chain left by reorgs at each of `forks`.
+    fn block(number: u64, forks: &[u64]) -> BlockNumHash {
+        let branch = forks
+            .iter()
+            .rposition(|&fork| fork <= number)
+            .map_or(0, |i| i + 1);
+        let hash = keccak256([number.to_be_bytes(), (branch as u64).to_be_bytes()].concat());
+        BlockNumHash::new(number, hash)
+    }
+
+    async fn sync(recent: &mut RecentBlocks, head: u64, forks: &[u64]) -> Option<u64> {
+        recent
+            .sync(block(head, forks), |number| async move {
+                Ok::<_, Infallible>(block(number, forks))
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_detects_reorgs() {
+        let mut recent = RecentBlocks::new(8);
+        let mut forks = Vec::new();
+
+        assert_eq!(sync(&mut recent, 100, &forks).await, None);
+        // Fills in the blocks it missed between polls
+        assert_eq!(sync(&mut recent, 104, &forks).await, None);
+        assert_eq!(sync(&mut recent, 104, &forks).await, None);
+
+        // 103 and 104 replaced by a longer branch
+        forks.push(103);
+        assert_eq!(sync(&mut recent, 106, &forks).await, Some(103));
+        assert_eq!(sync(&mut recent, 107, &forks).await, None);
+
+        // 106 and 107 dropped for a shorter branch
+        forks.push(106);
+        assert_eq!(sync(&mut recent, 105, &forks).await, Some(106));
+
+        // Deeper than the blocks kept: reported at the oldest one
+        forks.push(50);
+        assert_eq!(sync(&mut recent, 106, &forks).await, Some(100));
+
+        // A block recorded between polls is checked like any other
+        recent.record(block(107, &forks));
+        forks.push(107);
+        assert_eq!(sync(&mut recent, 108, &forks).await, Some(107));
+
+        // Too far ahead to walk back: starts over
+        forks.push(300);
+        assert_eq!(sync(&mut recent, 500, &forks).await, None);
+    }
+}
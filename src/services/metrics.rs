@@ -0,0 +1,258 @@
+use prometheus::{
+    exponential_buckets, Counter, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge,
+    Opts, Registry, TextEncoder,
+};
+
+/// Prometheus metrics shared by the API, the queue processor and the L1
+/// batch submitter.
+pub struct Metrics {
+    registry: Registry,
+    /// Submissions by `outcome` (accepted/rejected) and API error `code`
+    pub submissions: IntCounterVec,
+    pub admission_latency: Histogram,
+    pub queue_depth: IntGauge,
+    pub batches: IntCounter,
+    pub batch_uncompressed_bytes: IntCounter,
+    pub batch_compressed_bytes: IntCounter,
+    /// Uncompressed size over compressed size, per batch
+    pub compression_ratio: Histogram,
+    pub feed_subscribers: IntGauge,
+    /// Feed events skipped because a subscriber fell behind the broadcast
+    pub feed_lagged_events: IntCounter,
+    /// L1 batch posts by `outcome` (confirmed/failed)
+    pub l1_posts: IntCounterVec,
+    /// Time from sending a batch transaction to its receipt
+    pub l1_post_latency: Histogram,
+    pub l1_post_gas_used: IntCounter,
+    pub l1_post_cost_wei: Counter,
+    pub l1_head: IntGauge,
+    /// Transactions admitted with `l1_block_number` moved into the
+    /// sequencing window
+    pub l1_block_clamped: IntCounter,
+    pub l1_reorgs: IntCounter,
+    /// Posted batches an L1 reorg dropped, which were posted again
+    pub l1_reorged_batches: IntCounter,
+    /// Unposted L2 blocks re-sequenced because their L1 origin was reorged
+    pub rewound_blocks: IntCounter,
+    /// Deposits read from the L1 bridge and sealed into blocks
+    pub deposits: IntCounter,
+    /// Withdrawals to L1 found in built batches
+    pub withdrawals: IntCounter,
+    /// Output roots proposed to the L1 oracle
+    pub outputs_proposed: IntCounter,
+    pub finalized_output_block: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let metrics = Self {
+            submissions: IntCounterVec::new(
+                Opts::new(
+                    "sequencer_submissions_total",
+                    "Transaction submissions by outcome and error code",
+                ),
+                &["outcome", "code"],
+            )
+            .unwrap(),
+            admission_latency: Histogram::with_opts(HistogramOpts::new(
+                "sequencer_admission_latency_seconds",
+                "Time to accept or reject a transaction submission",
+            ))
+            .unwrap(),
+            queue_depth: IntGauge::new(
+                "sequencer_queue_depth",
+                "Transactions waiting to be batched",
+            )
+            .unwrap(),
+            batches: IntCounter::new("sequencer_batches_total", "Batches built").unwrap(),
+            batch_uncompressed_bytes: IntCounter::new(
+                "sequencer_batch_uncompressed_bytes_total",
+                "Batch payload bytes before compression",
+            )
+            .unwrap(),
+            batch_compressed_bytes: IntCounter::new(
+                "sequencer_batch_compressed_bytes_total",
+                "Batch payload bytes after compression",
+            )
+            .unwrap(),
+            compression_ratio: Histogram::with_opts(
+                HistogramOpts::new(
+                    "sequencer_batch_compression_ratio",
+                    "Uncompressed over compressed batch size",
+                )
+                .buckets(vec![1.0, 1.5, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0, 16.0]),
+            )
+            .unwrap(),
+            feed_subscribers: IntGauge::new(
+                "sequencer_feed_subscribers",
+                "Connected transaction feed clients",
+            )
+            .unwrap(),
+            feed_lagged_events: IntCounter::new(
+                "sequencer_feed_lagged_events_total",
+                "Feed events dropped for subscribers that fell behind",
+            )
+            .unwrap(),
+            l1_posts: IntCounterVec::new(
+                Opts::new("sequencer_l1_posts_total", "Batch posts to L1 by outcome"),
+                &["outcome"],
+            )
+            .unwrap(),
+            l1_post_latency: Histogram::with_opts(
+                HistogramOpts::new(
+                    "sequencer_l1_post_latency_seconds",
+                    "Time from sending a batch to L1 until its receipt",
+                )
+                .buckets(exponential_buckets(0.5, 2.0, 10).unwrap()),
+            )
+            .unwrap(),
+            l1_post_gas_used: IntCounter::new(
+                "sequencer_l1_post_gas_used_total",
+                "L1 gas used posting batches",
+            )
+            .unwrap(),
+            l1_post_cost_wei: Counter::new(
+                "sequencer_l1_post_cost_wei_total",
+                "L1 fees paid posting batches, in wei",
+            )
+            .unwrap(),
+            l1_head: IntGauge::new("sequencer_l1_head_block", "Latest L1 block seen").unwrap(),
+            l1_block_clamped: IntCounter::new(
+                "sequencer_l1_block_clamped_total",
+                "Transactions whose l1_block_number was clamped into the sequencing window",
+            )
+            .unwrap(),
+            l1_reorgs: IntCounter::new("sequencer_l1_reorgs_total", "L1 reorgs detected").unwrap(),
+            l1_reorged_batches: IntCounter::new(
+                "sequencer_l1_reorged_batches_total",
+                "Posted batches dropped by an L1 reorg and posted again",
+            )
+            .unwrap(),
+            rewound_blocks: IntCounter::new(
+                "sequencer_rewound_blocks_total",
+                "Unposted L2 blocks re-sequenced after their L1 origin was reorged out",
+            )
+            .unwrap(),
+            deposits: IntCounter::new(
+                "sequencer_deposits_total",
+                "Deposits read from the L1 bridge and sealed into blocks",
+            )
+            .unwrap(),
+            withdrawals: IntCounter::new(
+                "sequencer_withdrawals_total",
+                "Withdrawals to L1 found in built batches",
+            )
+            .unwrap(),
+            outputs_proposed: IntCounter::new(
+                "sequencer_outputs_proposed_total",
+                "Output roots proposed to the L1 oracle",
+            )
+            .unwrap(),
+            finalized_output_block: IntGauge::new(
+                "sequencer_finalized_output_block",
+                "L2 block of the latest output finalized on L1",
+            )
+            .unwrap(),
+            registry,
+        };
+
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.submissions.clone()),
+            Box::new(metrics.admission_latency.clone()),
+            Box::new(metrics.queue_depth.clone()),
+            Box::new(metrics.batches.clone()),
+            Box::new(metrics.batch_uncompressed_bytes.clone()),
+            Box::new(metrics.batch_compressed_bytes.clone()),
+            Box::new(metrics.compression_ratio.clone()),
+            Box::new(metrics.feed_subscribers.clone()),
+            Box::new(metrics.feed_lagged_events.clone()),
+            Box::new(metrics.l1_posts.clone()),
+            Box::new(metrics.l1_post_latency.clone()),
+            Box::new(metrics.l1_post_gas_used.clone()),
+            Box::new(metrics.l1_post_cost_wei.clone()),
+            Box::new(metrics.l1_head.clone()),
+            Box::new(metrics.l1_block_clamped.clone()),
+            Box::new(metrics.l1_reorgs.clone()),
+            Box::new(metrics.l1_reorged_batches.clone()),
+            Box::new(metrics.rewound_blocks.clone()),
+            Box::new(metrics.deposits.clone()),
+            Box::new(metrics.withdrawals.clone()),
+            Box::new(metrics.outputs_proposed.clone()),
+            Box::new(metrics.finalized_output_block.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Metric names are unique");
+        }
+
+        metrics
+    }
+
+    /// Records one built batch and how well it compressed.
+    pub fn record_batch(&self, uncompressed: usize, compressed: usize) {
+        self.batches.inc();
+        self.batch_uncompressed_bytes.inc_by(uncompressed as u64);
+        self.batch_compressed_bytes.inc_by(compressed as u64);
+        if compressed > 0 {
+            self.compression_ratio
+                .observe(uncompressed as f64 / compressed as f64);
+        }
+    }
+
+    /// Counts a feed subscriber for as long as the guard is alive.
+    pub fn feed_subscriber(&self) -> SubscriberGuard {
+        self.feed_subscribers.inc();
+        SubscriberGuard(self.feed_subscribers.clone())
+    }
+
+    /// Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SubscriberGuard(IntGauge);
+
+impl Drop for SubscriberGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_includes_recorded_values() {
+        let metrics = Metrics::new();
+        metrics
+            .submissions
+            .with_label_values(&["rejected", "RATE_LIMITED"])
+            .inc();
+        metrics.record_batch(1000, 250);
+        {
+            let _subscriber = metrics.feed_subscriber();
+            assert_eq!(metrics.feed_subscribers.get(), 1);
+        }
+        assert_eq!(metrics.feed_subscribers.get(), 0);
+
+        let rendered = metrics.render();
+        assert!(rendered
+            .contains(r#"sequencer_submissions_total{code="RATE_LIMITED",outcome="rejected"} 1"#));
+        assert!(rendered.contains("sequencer_batch_uncompressed_bytes_total 1000"));
+        assert!(rendered.contains("sequencer_batch_compression_ratio_sum 4"));
+    }
+}
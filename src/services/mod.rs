@@ -1,2 +1,14 @@
+pub mod batch_builder;
+pub mod batch_store;
+pub mod batch_submitter;
+pub mod beacon;
+pub mod deposits;
+pub mod derivation;
+pub mod feed;
+pub mod l1_head;
+pub mod metrics;
+pub mod outbox;
+pub mod outputs;
 pub mod parser;
 pub mod queue_service;
+pub mod status;
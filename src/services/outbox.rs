@@ -0,0 +1,164 @@
+use crate::models::outbox::{self, OutboxTree, Withdrawal};
+use crate::models::Batch;
+use crate::services::batch_store::{BatchStore, PostedBatch, StoreError};
+use alloy::primitives::B256;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// What L1 needs to prove a withdrawal: its leaf, the path to the outbox
+/// root committed in its batch, and where that batch was posted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WithdrawalProof {
+    pub withdrawal: Withdrawal,
+    pub withdrawal_hash: B256,
+    pub batch_index: u64,
+    pub leaf_index: u64,
+    pub proof: Vec<B256>,
+    pub outbox_root: B256,
+    /// Missing until the batch is on L1
+    pub posted: Option<PostedBatch>,
+}
+
+/// Finds the batch holding a withdrawal, by its hash or its L2 transaction's
+/// hash. Proofs are computed from the stored batch on each lookup, so a
+/// batch rebuilt after a reorg is never proven against its old contents.
+pub struct WithdrawalIndex {
+    store: Arc<BatchStore>,
+    batches: RwLock<HashMap<B256, u64>>,
+}
+
+impl WithdrawalIndex {
+    /// Indexes every stored batch.
+    pub fn load(store: Arc<BatchStore>) -> Result<Self, StoreError> {
+        let index = Self {
+            store,
+            batches: RwLock::new(HashMap::new()),
+        };
+        for batch_index in index.store.indices()? {
+            if let Some(batch) = index.store.load(batch_index)? {
+                index.record(&batch);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Indexes a newly stored batch, returning how many withdrawals it has.
+    pub fn record(&self, batch: &Batch) -> usize {
+        let withdrawals = outbox::withdrawals(&batch.blocks);
+        let mut batches = self.batches.write().unwrap();
+        for withdrawal in &withdrawals {
+            batches.insert(withdrawal.hash(), batch.index);
+            batches.insert(withdrawal.l2_tx_hash, batch.index);
+        }
+        withdrawals.len()
+    }
+
+    /// The proof for the withdrawal with `hash`, or made by the L2
+    /// transaction with `hash`.
+    pub fn proof(&self, hash: B256) -> Result<Option<WithdrawalProof>, StoreError> {
+        let Some(batch_index) = self.batches.read().unwrap().get(&hash).copied() else {
+            return Ok(None);
+        };
+        let Some(batch) = self.store.load(batch_index)? else {
+            return Ok(None);
+        };
+
+        let withdrawals = outbox::withdrawals(&batch.blocks);
+        let leaves: Vec<B256> = withdrawals.iter().map(Withdrawal::hash).collect();
+        let Some(leaf_index) = withdrawals
+            .iter()
+            .zip(&leaves)
+            .position(|(withdrawal, leaf)| *leaf == hash || withdrawal.l2_tx_hash == hash)
+        else {
+            return Ok(None);
+        };
+        let withdrawal_hash = leaves[leaf_index];
+        let tree = OutboxTree::new(leaves);
+        Ok(Some(WithdrawalProof {
+            withdrawal: withdrawals[leaf_index].clone(),
+            withdrawal_hash,
+            batch_index,
+            leaf_index: leaf_index as u64,
+            proof: tree.proof(leaf_index).expect("leaf is in the tree"),
+            outbox_root: tree.root(),
+            posted: self.store.posted(batch_index)?,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outbox::{initiateWithdrawalCall, verify_proof, OUTBOX_ADDRESS};
+    use crate::models::{Block, BlockFrames, L2Transaction};
+    use alloy::primitives::{Address, Bytes, U256};
+    use alloy::sol_types::SolCall;
+
+    fn withdrawal(nonce: u64) -> L2Transaction {
+        let call = initiateWithdrawalCall {
+            target: Address::repeat_byte(0xbb),
+            gasLimit: U256::from(100_000),
+            data: Bytes::new(),
+        };
+        L2Transaction::new(
+            nonce,
+            Address::repeat_byte(0xaa),
+            Some(OUTBOX_ADDRESS),
+            U256::from(1),
+            call.abi_encode().into(),
+            100_000,
+            U256::from(1),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    #[test]
+    fn test_proves_stored_withdrawals() {
+        let data_dir = std::env::temp_dir().join(format!("outbox-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let store = Arc::new(BatchStore::open(&data_dir).unwrap());
+        let blocks = vec![Block::new(
+            1,
+            (0..3).map(withdrawal).collect(),
+            B256::ZERO,
+            B256::ZERO,
+            0,
+        )];
+        let tx_hash = blocks[0].transactions[2].hash();
+        store
+            .save(&Batch::new(4, blocks.clone(), Vec::new(), 0))
+            .unwrap();
+
+        // Found after a restart, by transaction or withdrawal hash
+        let index = WithdrawalIndex::load(store.clone()).unwrap();
+        let proof = index.proof(tx_hash).unwrap().unwrap();
+        assert_eq!((proof.batch_index, proof.leaf_index), (4, 2));
+        assert_eq!(
+            Some(proof.outbox_root),
+            BlockFrames::of(&blocks).outbox_root
+        );
+        assert!(verify_proof(
+            proof.withdrawal_hash,
+            proof.leaf_index,
+            &proof.proof,
+            proof.outbox_root
+        ));
+        let by_hash = index.proof(proof.withdrawal_hash).unwrap().unwrap();
+        assert_eq!(by_hash.proof, proof.proof);
+        assert!(index.proof(B256::ZERO).unwrap().is_none());
+
+        // Rebuilt without it, as after a reorg
+        let rebuilt = Batch::new(
+            4,
+            vec![Block::new(1, Vec::new(), B256::ZERO, B256::ZERO, 0)],
+            Vec::new(),
+            0,
+        );
+        store.save(&rebuilt).unwrap();
+        assert_eq!(index.record(&rebuilt), 0);
+        assert!(index.proof(tx_hash).unwrap().is_none());
+    }
+}
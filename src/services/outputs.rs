@@ -0,0 +1,412 @@
+use crate::models::{Batch, OutboxTree};
+use crate::services::batch_store::{BatchStore, StoreError};
+use crate::services::metrics::Metrics;
+use alloy::eips::BlockNumberOrTag;
+use alloy::network::TransactionBuilder;
+use alloy::primitives::{keccak256, Address, B256, U256};
+use alloy::providers::Provider;
+use alloy::rpc::types::{BlockTransactionsKind, TransactionRequest};
+use alloy::sol_types::{SolCall, SolValue};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Version of the output root preimage layout
+const OUTPUT_VERSION: B256 = B256::ZERO;
+
+alloy::sol! {
+    /// Records `outputRoot` as the L2 state at `l2BlockNumber`.
+    function proposeL2Output(bytes32 outputRoot, uint256 l2BlockNumber);
+}
+
+/// `keccak256(abi.encode(version, stateRoot, blockHash, withdrawalRoot))`,
+/// what L1 proves L2 state and withdrawals against.
+pub fn output_root(state_root: B256, block_hash: B256, withdrawal_root: B256) -> B256 {
+    keccak256((OUTPUT_VERSION, state_root, block_hash, withdrawal_root).abi_encode())
+}
+
+/// Where an output proposal landed on L1.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub l1_tx_hash: B256,
+    pub l1_block: u64,
+}
+
+/// The state commitment for the last block of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Output {
+    pub l2_block: u64,
+    pub block_hash: B256,
+    pub state_root: B256,
+    /// The batch's outbox root
+    pub withdrawal_root: B256,
+    pub output_root: B256,
+    pub batch_index: u64,
+    /// Missing until the proposal is on L1
+    pub proposal: Option<Proposal>,
+    /// Set once the proposal's L1 block is finalized
+    pub finalized: bool,
+}
+
+impl Output {
+    /// The output at the end of `batch`, or `None` if it has no blocks.
+    pub fn of(batch: &Batch) -> Option<Self> {
+        let block = batch.blocks.last()?;
+        let block_hash = block.hash();
+        let withdrawal_root = OutboxTree::of(&batch.blocks).root();
+        Some(Self {
+            l2_block: block.number,
+            block_hash,
+            state_root: block.state_root,
+            withdrawal_root,
+            output_root: output_root(block.state_root, block_hash, withdrawal_root),
+            batch_index: batch.index,
+            proposal: None,
+            finalized: false,
+        })
+    }
+}
+
+/// Every output the proposer has computed, as `<l2_block>.json` under
+/// `<data_dir>/outputs`.
+pub struct OutputStore {
+    dir: PathBuf,
+}
+
+impl OutputStore {
+    pub fn open(data_dir: &Path) -> Result<Self, StoreError> {
+        let dir = data_dir.join("outputs");
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    pub fn save(&self, output: &Output) -> Result<(), StoreError> {
+        let path = self.path(output.l2_block);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(output)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load(&self, l2_block: u64) -> Result<Option<Output>, StoreError> {
+        match std::fs::read(self.path(l2_block)) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// L2 blocks with an output, in ascending order.
+    pub fn blocks(&self) -> Result<Vec<u64>, StoreError> {
+        let mut blocks = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(block) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .and_then(|stem| stem.parse().ok())
+                {
+                    blocks.push(block);
+                }
+            }
+        }
+        blocks.sort_unstable();
+        Ok(blocks)
+    }
+
+    /// Outputs not finalized yet, oldest first.
+    pub fn unfinalized(&self) -> Result<Vec<Output>, StoreError> {
+        let mut outputs = Vec::new();
+        for block in self.blocks()? {
+            outputs.extend(self.load(block)?.filter(|output| !output.finalized));
+        }
+        Ok(outputs)
+    }
+
+    fn path(&self, l2_block: u64) -> PathBuf {
+        self.dir.join(format!("{:020}.json", l2_block))
+    }
+}
+
+/// Every `interval`, proposes the output at the end of each batch that
+/// reached L1 since the last round to the oracle, in order, then follows
+/// each proposal until L1 finalizes it.
+pub struct OutputProposer<P: Provider> {
+    provider: P,
+    oracle: Address,
+    batches: Arc<BatchStore>,
+    outputs: Arc<OutputStore>,
+    metrics: Arc<Metrics>,
+    interval: Duration,
+}
+
+impl<P: Provider> OutputProposer<P> {
+    /// `provider` must have a wallet for the proposer key.
+    pub fn new(
+        provider: P,
+        oracle: Address,
+        batches: Arc<BatchStore>,
+        outputs: Arc<OutputStore>,
+        metrics: Arc<Metrics>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            provider,
+            oracle,
+            batches,
+            outputs,
+            metrics,
+            interval,
+        }
+    }
+
+    /// Proposes forever. Failures are logged once until a round succeeds
+    /// again; unproposed outputs are retried each round.
+    pub async fn run(&self) {
+        let mut failing = false;
+        loop {
+            match self.poll().await {
+                Ok(()) => failing = false,
+                Err(e) if !failing => {
+                    failing = true;
+                    warn!(error = %e, "failed to propose outputs");
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    async fn poll(&self) -> Result<(), String> {
+        for output in self.next_outputs().map_err(|e| e.to_string())? {
+            // Saved first so a restart retries it rather than skipping it
+            self.outputs.save(&output).map_err(|e| e.to_string())?;
+        }
+        for output in self.outputs.unfinalized().map_err(|e| e.to_string())? {
+            if output.proposal.is_none() {
+                self.propose(output).await?;
+            }
+        }
+        self.track_finalization().await
+    }
+
+    /// Outputs for the posted batches past the last output, oldest first.
+    /// Each batch's withdrawal root only covers its own withdrawals, so none
+    /// may be skipped; batches after one that isn't on L1 yet wait for it.
+    fn next_outputs(&self) -> Result<Vec<Output>, StoreError> {
+        let proposed = self.outputs.blocks()?.last().copied();
+        let mut outputs = Vec::new();
+        for index in self.batches.indices()?.into_iter().rev() {
+            if self.batches.posted(index)?.is_none() {
+                outputs.clear();
+                continue;
+            }
+            let Some(output) = self.batches.load(index)?.as_ref().and_then(Output::of) else {
+                continue;
+            };
+            if proposed >= Some(output.l2_block) {
+                break;
+            }
+            outputs.push(output);
+        }
+        outputs.reverse();
+        Ok(outputs)
+    }
+
+    async fn propose(&self, mut output: Output) -> Result<(), String> {
+        let call = proposeL2OutputCall {
+            outputRoot: output.output_root,
+            l2BlockNumber: U256::from(output.l2_block),
+        };
+        let tx = TransactionRequest::default()
+            .with_to(self.oracle)
+            .with_input(call.abi_encode());
+        let receipt = self
+            .provider
+            .send_transaction(tx)
+            .await
+            .map_err(|e| e.to_string())?
+            .get_receipt()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !receipt.status() {
+            return Err(format!(
+                "output proposal {} reverted",
+                receipt.transaction_hash
+            ));
+        }
+        let l1_block = receipt
+            .block_number
+            .ok_or("output proposal receipt has no block")?;
+
+        info!(
+            l2_block = output.l2_block,
+            output_root = %output.output_root,
+            l1_tx_hash = %receipt.transaction_hash,
+            l1_block,
+            "proposed output"
+        );
+        output.proposal = Some(Proposal {
+            l1_tx_hash: receipt.transaction_hash,
+            l1_block,
+        });
+        self.outputs.save(&output).map_err(|e| e.to_string())?;
+        self.metrics.outputs_proposed.inc();
+        Ok(())
+    }
+
+    /// Marks proposals at or below L1's finalized block as final, and drops
+    /// ones an L1 reorg took out so they're proposed again.
+    async fn track_finalization(&self) -> Result<(), String> {
+        let finalized = self
+            .provider
+            .get_block_by_number(BlockNumberOrTag::Finalized, BlockTransactionsKind::Hashes)
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|block| block.header.number);
+
+        for mut output in self.outputs.unfinalized().map_err(|e| e.to_string())? {
+            let Some(proposal) = &output.proposal else {
+                continue;
+            };
+            let receipt = self
+                .provider
+                .get_transaction_receipt(proposal.l1_tx_hash)
+                .await
+                .map_err(|e| e.to_string())?;
+            match receipt.and_then(|receipt| receipt.block_number) {
+                None => {
+                    warn!(
+                        l2_block = output.l2_block,
+                        "output proposal dropped from L1; proposing again"
+                    );
+                    output.proposal = None;
+                }
+                Some(l1_block) if finalized.is_some_and(|f| l1_block <= f) => {
+                    info!(l2_block = output.l2_block, l1_block, "output finalized");
+                    output.finalized = true;
+                    self.metrics
+                        .finalized_output_block
+                        .set(output.l2_block as i64);
+                }
+                Some(l1_block) => {
+                    if let Some(proposal) = &mut output.proposal {
+                        proposal.l1_block = l1_block;
+                    }
+                }
+            }
+            self.outputs.save(&output).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::outbox::{initiateWithdrawalCall, OUTBOX_ADDRESS};
+    use crate::models::{Block, L2Transaction};
+    use crate::services::batch_store::PostedBatch;
+    use alloy::primitives::Bytes;
+    use alloy::providers::ProviderBuilder;
+
+    #[test]
+    fn test_commits_to_the_last_block() {
+        let call = initiateWithdrawalCall {
+            target: Address::repeat_byte(0xbb),
+            gasLimit: U256::from(100_000),
+            data: Bytes::new(),
+        };
+        let withdrawal = L2Transaction::new(
+            0,
+            Address::repeat_byte(0xaa),
+            Some(OUTBOX_ADDRESS),
+            U256::from(1),
+            call.abi_encode().into(),
+            100_000,
+            U256::from(1),
+            Some(42161),
+            0,
+            U256::ZERO,
+        );
+        let first = Block::new(1, vec![withdrawal], B256::ZERO, B256::ZERO, 0);
+        let last = Block::new(2, Vec::new(), first.hash(), B256::repeat_byte(0x5e), 0);
+        let batch = Batch::new(3, vec![first, last.clone()], Vec::new(), 0);
+
+        let output = Output::of(&batch).unwrap();
+        assert_eq!((output.l2_block, output.batch_index), (2, 3));
+        assert_eq!(output.block_hash, last.hash());
+        assert_ne!(output.withdrawal_root, B256::ZERO);
+        assert_eq!(
+            output.output_root,
+            output_root(last.state_root, last.hash(), output.withdrawal_root)
+        );
+        assert_ne!(
+            output.output_root,
+            output_root(last.state_root, last.hash(), B256::ZERO)
+        );
+        assert!(Output::of(&Batch::new(4, Vec::new(), Vec::new(), 0)).is_none());
+
+        let data_dir = std::env::temp_dir().join(format!("outputs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let store = OutputStore::open(&data_dir).unwrap();
+        store.save(&output).unwrap();
+        let mut finalized = Output::of(&Batch::new(2, vec![last], Vec::new(), 0)).unwrap();
+        finalized.l2_block = 1;
+        finalized.finalized = true;
+        store.save(&finalized).unwrap();
+        assert_eq!(store.blocks().unwrap(), vec![1, 2]);
+        assert_eq!(store.load(2).unwrap(), Some(output.clone()));
+        assert_eq!(store.unfinalized().unwrap(), vec![output]);
+        assert_eq!(store.load(9).unwrap(), None);
+    }
+
+    #[test]
+    fn test_proposes_every_posted_batch() {
+        let data_dir = std::env::temp_dir().join(format!("outputs-every-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let batches = Arc::new(BatchStore::open(&data_dir).unwrap());
+        let outputs = Arc::new(OutputStore::open(&data_dir).unwrap());
+        let proposer = OutputProposer::new(
+            ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap()),
+            Address::ZERO,
+            batches.clone(),
+            outputs.clone(),
+            Arc::new(Metrics::new()),
+            Duration::from_secs(300),
+        );
+        let posted = PostedBatch {
+            l1_tx_hash: B256::ZERO,
+            l1_block: Some(1),
+            l1_block_hash: None,
+        };
+        let blocks_of = |outputs: Vec<Output>| -> Vec<u64> {
+            outputs.iter().map(|output| output.l2_block).collect()
+        };
+        for index in 0..5 {
+            let block = Block::new(index * 2 + 2, Vec::new(), B256::ZERO, B256::ZERO, 0);
+            batches
+                .save(&Batch::new(index, vec![block], Vec::new(), 0))
+                .unwrap();
+        }
+
+        // Two batches posted within one interval
+        batches.mark_posted(0, &posted).unwrap();
+        batches.mark_posted(1, &posted).unwrap();
+        let next = proposer.next_outputs().unwrap();
+        assert_eq!(blocks_of(next.clone()), vec![2, 4]);
+        for output in &next {
+            outputs.save(output).unwrap();
+        }
+        assert!(proposer.next_outputs().unwrap().is_empty());
+
+        // Batch 3 waits for batch 2
+        batches.mark_posted(3, &posted).unwrap();
+        assert!(proposer.next_outputs().unwrap().is_empty());
+        batches.mark_posted(2, &posted).unwrap();
+        assert_eq!(blocks_of(proposer.next_outputs().unwrap()), vec![6, 8]);
+    }
+}
@@ -1,7 +1,7 @@
 use crate::models::L2Transaction;
 use alloy::{
     hex,
-    primitives::{Address, Bytes, ChainId, U256},
+    primitives::{Address, Bytes, U256},
 };
 use serde::Deserialize;
 
@@ -19,26 +19,41 @@ pub struct RawTransactionData {
     submission_fee: String,
 }
 
-pub fn parse_raw_transaction(tx_data: &RawTransactionData) -> Result<L2Transaction, String> {
+/// A field of `RawTransactionData` that couldn't be decoded.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{message}")]
+pub struct ParseError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(field: &'static str, message: String) -> Self {
+        Self { field, message }
+    }
+}
+
+pub fn parse_raw_transaction(tx_data: &RawTransactionData) -> Result<L2Transaction, ParseError> {
     let nonce = u64::from_str_radix(tx_data.nonce.trim_start_matches("0x"), 16)
-        .map_err(|e| format!("Invalid nonce: {}", e))?;
+        .map_err(|e| ParseError::new("nonce", format!("Invalid nonce: {}", e)))?;
 
-    let from = parse_address(&tx_data.from)?;
+    let from = parse_address(&tx_data.from).map_err(|e| ParseError::new("from", e))?;
     let to = match &tx_data.to {
-        Some(addr) => Some(parse_address(addr)?),
+        Some(addr) => Some(parse_address(addr).map_err(|e| ParseError::new("to", e))?),
         None => None,
     };
 
-    let value = parse_u256(&tx_data.value)?;
-    let gas_price = parse_u256(&tx_data.gas_price)?;
-    let submission_fee = parse_u256(&tx_data.submission_fee)?;
+    let value = parse_u256(&tx_data.value).map_err(|e| ParseError::new("value", e))?;
+    let gas_price = parse_u256(&tx_data.gas_price).map_err(|e| ParseError::new("gas_price", e))?;
+    let submission_fee =
+        parse_u256(&tx_data.submission_fee).map_err(|e| ParseError::new("submission_fee", e))?;
 
-    let data = parse_bytes(&tx_data.data)?;
+    let data = parse_bytes(&tx_data.data).map_err(|e| ParseError::new("data", e))?;
 
     let gas_limit = u64::from_str_radix(tx_data.gas_limit.trim_start_matches("0x"), 16)
-        .map_err(|e| format!("Invalid gas limit: {}", e))?;
+        .map_err(|e| ParseError::new("gas_limit", format!("Invalid gas limit: {}", e)))?;
 
-    let chain_id = tx_data.chain_id.map(ChainId::from);
+    let chain_id = tx_data.chain_id;
 
     Ok(L2Transaction::new(
         nonce,
@@ -81,7 +96,7 @@ fn parse_bytes(hex_str: &str) -> Result<Bytes, String> {
 mod tests {
     use super::*;
     use alloy::consensus::Transaction;
-    use alloy::primitives::TxKind;
+    use alloy::primitives::{ChainId, TxKind};
 
     fn create_valid_tx_data() -> RawTransactionData {
         RawTransactionData {
@@ -170,7 +185,9 @@ mod tests {
 
         let result = parse_raw_transaction(&tx_data);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid address"));
+        let err = result.unwrap_err();
+        assert_eq!(err.field, "from");
+        assert!(err.message.contains("Invalid address"));
     }
 
     #[test]
@@ -180,7 +197,9 @@ mod tests {
 
         let result = parse_raw_transaction(&tx_data);
         assert!(result.is_err());
-        assert!(result.unwrap_err().contains("Invalid nonce"));
+        let err = result.unwrap_err();
+        assert_eq!(err.field, "nonce");
+        assert!(err.message.contains("Invalid nonce"));
     }
 
     #[test]
@@ -1,111 +1,1345 @@
-use crate::models::{L2Transaction, Queue};
+use crate::config::Config;
+use crate::models::{BatchCodec, Block, L2Transaction, Queue, SealedBatch};
+use crate::services::batch_builder::{BatchBuilder, BuildCommand};
+use crate::services::batch_store::{BatchStore, StoreError};
+use crate::services::deposits::DepositCursor;
+use crate::services::feed::FeedEvent;
+use crate::services::l1_head::L1Head;
+use crate::services::metrics::Metrics;
+use crate::services::status::SequencerStatus;
+use alloy::primitives::Address;
 use alloy::providers::Provider;
+use serde::{Deserialize, Serialize};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::error::TrySendError;
 use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{error, info, info_span, warn, Span};
+
+/// Most commands the processor takes off its channel per wakeup
+const COMMAND_CHUNK: usize = 256;
+
+/// Sequence numbers reserved in the store at a time. A restart skips what's
+/// left of the reservation rather than reissue a number that was signed.
+const SEQUENCE_RESERVATION: u64 = 1024;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum QueueError {
+    #[error("Queue is full")]
+    Full,
+    #[error("Queue processor has stopped")]
+    Closed,
+    #[error("Transaction admission is paused")]
+    Paused,
+    #[error("Sequencer is shutting down")]
+    ShuttingDown,
+    #[error("Failed to reserve sequence numbers")]
+    Unsequenced,
+}
+
+/// Runtime-adjustable batching parameters.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct BatchParams {
+    pub max_transactions: usize,
+}
+
+/// Fields left as `None` keep their current value.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchParamsUpdate {
+    pub max_transactions: Option<usize>,
+}
+
+/// Where the queue processor put an admitted transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sequenced {
+    /// Admission order, which transactions are included in; deposits aren't
+    /// numbered. Never reissued, though a restart can skip some.
+    pub sequence_number: u64,
+    /// Block the transaction is expected in, estimated at admission.
+    /// Deposits and byte-limited batches can push it later; evictions ahead
+    /// of it or a smaller batch size can bring it earlier.
+    pub target_block: u64,
+}
+
+/// Which pending transactions to list; unset fields match everything.
+#[derive(Debug, Clone, Default)]
+pub struct PendingFilter {
+    pub from: Option<Address>,
+    pub to: Option<Address>,
+    pub limit: usize,
+}
+
+impl PendingFilter {
+    fn matches(&self, tx: &L2Transaction) -> bool {
+        self.from.is_none_or(|from| tx.from == from) && self.to.is_none_or(|to| tx.to == Some(to))
+    }
+}
 
 // Commands that can be sent to the queue
 #[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
 pub enum QueueCommand {
     SubmitTransaction {
         transaction: L2Transaction,
-        response: oneshot::Sender<Result<(), String>>,
+        /// The submitting request's span, so queue work shows up in its trace
+        span: Span,
+        response: oneshot::Sender<Result<Sequenced, QueueError>>,
     },
+    /// Queues every transaction back to back, with nothing interleaved
+    SubmitTransactions {
+        transactions: Vec<L2Transaction>,
+        span: Span,
+        response: oneshot::Sender<Result<Vec<Sequenced>, QueueError>>,
+    },
+    /// Seals whatever is queued into a batch, replying with its index
     InitiateBatch {
-        response: oneshot::Sender<Result<(), String>>,
+        response: oneshot::Sender<Result<Option<u64>, QueueError>>,
+    },
+    ListPending {
+        filter: PendingFilter,
+        response: oneshot::Sender<Vec<L2Transaction>>,
+    },
+    /// Evicts the pending transactions with this sender and nonce
+    EvictTransaction {
+        from: Address,
+        nonce: u64,
+        response: oneshot::Sender<Vec<L2Transaction>>,
+    },
+    UpdateBatchParams {
+        update: BatchParamsUpdate,
+        response: oneshot::Sender<BatchParams>,
     },
+    /// Replies with the next L1 block to read deposits from
+    NextDepositBlock {
+        response: oneshot::Sender<Option<u64>>,
+    },
+    /// Seals deposits read from L1 blocks `from` through `through` ahead of
+    /// everything pending, replying with those not already included
+    SubmitDeposits {
+        from: u64,
+        through: u64,
+        deposits: Vec<L2Transaction>,
+        response: oneshot::Sender<Option<Vec<L2Transaction>>>,
+    },
+    /// Re-sequences unposted blocks after an L1 reorg at `fork`, replying
+    /// with the index of the first batch rebuilt
+    Rewind {
+        fork: u64,
+        response: oneshot::Sender<Option<u64>>,
+    },
+    /// Batches everything still queued, replies with the final batch
+    /// indices, then stops the processor. Commands behind it are refused.
+    Shutdown { response: oneshot::Sender<Vec<u64>> },
 }
 
 #[derive(Clone)]
 pub struct QueueHandle {
     command_tx: mpsc::Sender<QueueCommand>,
-    feed_tx: broadcast::Sender<L2Transaction>,
+    feed_tx: broadcast::Sender<FeedEvent>,
+    admission_paused: Arc<AtomicBool>,
+    /// Held for reading from the admission check until the submission is on
+    /// the channel, so none can land behind `Shutdown`
+    shutting_down: Arc<RwLock<bool>>,
 }
 
 impl QueueHandle {
     pub fn new(
         command_tx: mpsc::Sender<QueueCommand>,
-        feed_tx: broadcast::Sender<L2Transaction>,
+        feed_tx: broadcast::Sender<FeedEvent>,
     ) -> Self {
         Self {
             command_tx,
             feed_tx,
+            admission_paused: Arc::new(AtomicBool::new(false)),
+            shutting_down: Arc::new(RwLock::new(false)),
         }
     }
 
-    pub async fn submit_transaction(&self, transaction: L2Transaction) -> Result<(), String> {
+    /// Fails fast with `QueueError::Full` instead of waiting for space, so
+    /// callers can shed load. Once this returns `Ok` the transaction is
+    /// admitted; the processor queues it in order with every other command,
+    /// and replies on the returned receiver with where it put it.
+    pub fn submit_transaction(
+        &self,
+        transaction: L2Transaction,
+    ) -> Result<oneshot::Receiver<Result<Sequenced, QueueError>>, QueueError> {
+        let tx = transaction.clone();
         let (response_tx, response_rx) = oneshot::channel();
 
-        let tx = transaction.clone();
+        self.admit(QueueCommand::SubmitTransaction {
+            transaction,
+            span: Span::current(),
+            response: response_tx,
+        })?;
 
-        self.command_tx
-            .send(QueueCommand::SubmitTransaction {
-                transaction,
-                response: response_tx,
-            })
-            .await
-            .map_err(|e| e.to_string())?;
+        let _ = self.feed_tx.send(FeedEvent::Transaction(tx));
+        Ok(response_rx)
+    }
+
+    /// Admits all of `transactions` or, on error, none of them. Fails fast
+    /// like `submit_transaction`.
+    pub fn submit_transactions(
+        &self,
+        transactions: Vec<L2Transaction>,
+    ) -> Result<oneshot::Receiver<Result<Vec<Sequenced>, QueueError>>, QueueError> {
+        let txs = transactions.clone();
+        let (response_tx, response_rx) = oneshot::channel();
+
+        self.admit(QueueCommand::SubmitTransactions {
+            transactions,
+            span: Span::current(),
+            response: response_tx,
+        })?;
+
+        for tx in txs {
+            let _ = self.feed_tx.send(FeedEvent::Transaction(tx));
+        }
+        Ok(response_rx)
+    }
+
+    /// Sends a submission unless admission is closed.
+    fn admit(&self, command: QueueCommand) -> Result<(), QueueError> {
+        let shutting_down = self.shutting_down.read().unwrap_or_else(|e| e.into_inner());
+        if *shutting_down {
+            return Err(QueueError::ShuttingDown);
+        }
+        if self.is_admission_paused() {
+            return Err(QueueError::Paused);
+        }
+        self.command_tx.try_send(command).map_err(|e| match e {
+            TrySendError::Full(_) => QueueError::Full,
+            TrySendError::Closed(_) => QueueError::Closed,
+        })
+    }
+
+    /// Returns the index of the batch built, or `None` if nothing was queued.
+    pub async fn batch_transactions(&self) -> Result<Option<u64>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::InitiateBatch {
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)?
+    }
+
+    pub async fn list_pending(
+        &self,
+        filter: PendingFilter,
+    ) -> Result<Vec<L2Transaction>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::ListPending {
+            filter,
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)
+    }
+
+    /// Removes the pending transactions from `from` with `nonce` and tells
+    /// feed subscribers, who were sent them when they were queued. Keyed by
+    /// sender and nonce rather than hash, since identical resubmissions
+    /// share a hash.
+    pub async fn evict_transaction(
+        &self,
+        from: Address,
+        nonce: u64,
+    ) -> Result<Vec<L2Transaction>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::EvictTransaction {
+            from,
+            nonce,
+            response: response_tx,
+        })
+        .await?;
+        let evicted = response_rx.await.map_err(|_| QueueError::Closed)?;
+        for tx in &evicted {
+            let _ = self.feed_tx.send(FeedEvent::Evicted(tx.clone()));
+        }
+        Ok(evicted)
+    }
+
+    /// Applies `update` and returns the resulting parameters.
+    pub async fn update_batch_params(
+        &self,
+        update: BatchParamsUpdate,
+    ) -> Result<BatchParams, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::UpdateBatchParams {
+            update,
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)
+    }
+
+    /// The next L1 block to read bridge deposits from; `None` until the
+    /// first read on a new chain, or when deposits are off.
+    pub async fn next_deposit_block(&self) -> Result<Option<u64>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::NextDepositBlock {
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)
+    }
+
+    /// Seals the deposits read from L1 blocks `from` through `through` into
+    /// the next blocks, ahead of pending transactions. Deposits bypass
+    /// admission, so they're taken while it's paused. Returns those queued,
+    /// leaving out any already included, or `None` if reading has moved on
+    /// from `from`, as it does after a reorg.
+    pub async fn submit_deposits(
+        &self,
+        from: u64,
+        through: u64,
+        deposits: Vec<L2Transaction>,
+    ) -> Result<Option<Vec<L2Transaction>>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::SubmitDeposits {
+            from,
+            through,
+            deposits,
+            response: response_tx,
+        })
+        .await?;
+        let queued = response_rx.await.map_err(|_| QueueError::Closed)?;
+        for tx in queued.iter().flatten() {
+            let _ = self.feed_tx.send(FeedEvent::Transaction(tx.clone()));
+        }
+        Ok(queued)
+    }
 
-        let _ = self.feed_tx.send(tx);
+    /// Rebuilds the unposted blocks whose L1 origin is at or after `fork`,
+    /// along with every block after them, from their transactions. Returns
+    /// the index of the first batch rebuilt, if any were.
+    pub async fn rewind(&self, fork: u64) -> Result<Option<u64>, QueueError> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::Rewind {
+            fork,
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)
+    }
 
-        response_rx.await.map_err(|e| e.to_string())?
+    /// Rejects all further submissions with `QueueError::ShuttingDown`.
+    /// Every submission admitted before this returns is ahead of a
+    /// `Shutdown` sent after it.
+    pub fn begin_shutdown(&self) {
+        *self
+            .shutting_down
+            .write()
+            .unwrap_or_else(|e| e.into_inner()) = true;
     }
 
-    pub async fn batch_transactions(&self) -> Result<(), String> {
+    /// Flushes the queue into final batches and stops the processor,
+    /// returning the indices of the batches it built.
+    pub async fn shutdown(&self) -> Result<Vec<u64>, QueueError> {
+        self.begin_shutdown();
         let (response_tx, response_rx) = oneshot::channel();
+        self.send_command(QueueCommand::Shutdown {
+            response: response_tx,
+        })
+        .await?;
+        response_rx.await.map_err(|_| QueueError::Closed)
+    }
 
+    /// Operator commands wait for queue space rather than failing fast.
+    async fn send_command(&self, command: QueueCommand) -> Result<(), QueueError> {
         self.command_tx
-            .send(QueueCommand::InitiateBatch {
-                response: response_tx,
-            })
+            .send(command)
             .await
-            .map_err(|e| e.to_string())?;
+            .map_err(|_| QueueError::Closed)
+    }
+
+    /// While paused, submissions fail with `QueueError::Paused`; queued
+    /// transactions are still batched.
+    pub fn set_admission_paused(&self, paused: bool) {
+        self.admission_paused.store(paused, Ordering::Relaxed);
+    }
 
-        response_rx.await.map_err(|e| e.to_string())?
+    pub fn is_admission_paused(&self) -> bool {
+        self.admission_paused.load(Ordering::Relaxed)
     }
 
-    pub fn subscribe(&self) -> broadcast::Receiver<L2Transaction> {
+    pub fn subscribe(&self) -> broadcast::Receiver<FeedEvent> {
         self.feed_tx.subscribe()
     }
+
+    /// False once the processor task has exited or panicked.
+    pub fn is_processor_alive(&self) -> bool {
+        !self.command_tx.is_closed()
+    }
 }
 
+/// Owns the queue. Only orders and seals transactions; building the sealed
+/// batches is left to the `BatchBuilder` so admission never waits on
+/// compression.
 pub struct QueueProcessor<T: Provider> {
     queue: Queue<T>,
     command_rx: mpsc::Receiver<QueueCommand>,
+    build_tx: mpsc::Sender<BuildCommand>,
+    store: Arc<BatchStore>,
+    metrics: Arc<Metrics>,
+    status: Arc<SequencerStatus>,
+    l1_head: Arc<L1Head>,
+    /// Set when deposits are read from an L1 bridge
+    deposits: Option<DepositCursor>,
+    next_sequence_number: u64,
+    /// Numbers from here on aren't reserved in the store yet
+    sequence_reserved: u64,
 }
 
 impl<T: Provider> QueueProcessor<T> {
-    pub fn new(provider: T, command_rx: mpsc::Receiver<QueueCommand>) -> Self {
+    pub fn new(
+        queue: Queue<T>,
+        command_rx: mpsc::Receiver<QueueCommand>,
+        build_tx: mpsc::Sender<BuildCommand>,
+        store: Arc<BatchStore>,
+        metrics: Arc<Metrics>,
+        status: Arc<SequencerStatus>,
+    ) -> Self {
         Self {
-            queue: Queue::new(provider),
+            queue,
             command_rx,
+            build_tx,
+            store,
+            metrics,
+            status,
+            l1_head: Arc::new(L1Head::new()),
+            deposits: None,
+            next_sequence_number: 0,
+            sequence_reserved: 0,
         }
     }
 
+    /// Where sealed blocks get their L1 origin from.
+    pub fn with_l1_head(mut self, l1_head: Arc<L1Head>) -> Self {
+        self.l1_head = l1_head;
+        self
+    }
+
+    /// Takes deposits, and holds the L1 origin back so none can be sealed
+    /// after its deadline.
+    pub fn with_deposits(mut self, cursor: DepositCursor) -> Self {
+        self.deposits = Some(cursor);
+        self
+    }
+
+    /// Numbers admitted transactions from `next`, continuing a stored chain.
+    /// `next` must be past every number handed out before.
+    pub fn with_next_sequence_number(mut self, next: u64) -> Self {
+        self.next_sequence_number = next;
+        self.sequence_reserved = next;
+        self
+    }
+
     pub async fn run(&mut self) {
-        while let Some(cmd) = self.command_rx.recv().await {
-            match cmd {
-                QueueCommand::SubmitTransaction {
-                    transaction,
-                    response,
-                } => {
-                    self.queue.queue_transaction(&transaction);
-                    let _ = response.send(Ok(()));
-                    self.queue.print_queue_state();
+        self.hold_for_deposits();
+        let mut commands = Vec::with_capacity(COMMAND_CHUNK);
+        while self
+            .command_rx
+            .recv_many(&mut commands, COMMAND_CHUNK)
+            .await
+            > 0
+        {
+            self.queue.set_l1_head(self.l1_head.sequencing_head());
+            let mut commands = commands.drain(..);
+            while let Some(command) = commands.next() {
+                if self.handle_command(command).await.is_break() {
+                    // Nothing behind `Shutdown` is carried out, but every
+                    // caller hears about it
+                    self.command_rx.close();
+                    let mut refused = commands.map(refuse).count();
+                    while let Ok(command) = self.command_rx.try_recv() {
+                        refuse(command);
+                        refused += 1;
+                    }
+                    if refused > 0 {
+                        warn!(refused, "refused commands sent after shutdown");
+                    }
+                    return;
                 }
-                QueueCommand::InitiateBatch { response } => {
-                    let _ = self.queue.batch_transactions();
-                    let _ = response.send(Ok(()));
-                    println!("Batch sent!");
-                    self.queue.print_queue_state();
+            }
+            self.update_queue_status();
+        }
+    }
+
+    /// Reserves the next `count` sequence numbers in the store, unless
+    /// they already are. Transactions are only numbered once their numbers
+    /// are; a failure rejects them, unless nobody waits for the numbers.
+    fn reserve_sequence(&mut self, count: u64) -> Result<(), QueueError> {
+        if self.next_sequence_number + count > self.sequence_reserved {
+            let reserved = self.next_sequence_number + SEQUENCE_RESERVATION.max(count);
+            if let Err(e) = self.store.reserve_sequence(reserved) {
+                error!(error = %e, "failed to reserve sequence numbers");
+                return Err(QueueError::Unsequenced);
+            }
+            self.sequence_reserved = reserved;
+        }
+        Ok(())
+    }
+
+    /// Numbers the transaction about to be queued, from numbers already
+    /// reserved. Only a full batch of pending transactions pushes it past
+    /// the next block.
+    fn sequence(&mut self) -> Sequenced {
+        let sequenced = Sequenced {
+            sequence_number: self.next_sequence_number,
+            target_block: self.queue.head_number()
+                + 1
+                + (self.queue.len() / self.queue.batch_size()) as u64,
+        };
+        self.next_sequence_number += 1;
+        sequenced
+    }
+
+    async fn handle_command(&mut self, command: QueueCommand) -> ControlFlow<()> {
+        match command {
+            QueueCommand::SubmitTransaction {
+                transaction,
+                span,
+                response,
+            } => {
+                match self.reserve_sequence(1) {
+                    Ok(()) => {
+                        let _ = response.send(Ok(self.sequence()));
+                    }
+                    // Without a confirmation to sign nobody waits for the
+                    // number, and the caller was already told it's queued
+                    Err(_) if response.is_closed() => {}
+                    Err(e) => {
+                        let _ = response.send(Err(e));
+                        return ControlFlow::Continue(());
+                    }
                 }
+                if let Some(batch) = self.queue.queue_transaction(transaction) {
+                    self.build_batch(batch, span).await;
+                }
+            }
+            QueueCommand::SubmitTransactions {
+                transactions,
+                span,
+                response,
+            } => {
+                let numbered = match self.reserve_sequence(transactions.len() as u64) {
+                    Ok(()) => true,
+                    Err(_) if response.is_closed() => false,
+                    Err(e) => {
+                        let _ = response.send(Err(e));
+                        return ControlFlow::Continue(());
+                    }
+                };
+                let mut sequenced = Vec::with_capacity(transactions.len());
+                for transaction in transactions {
+                    if numbered {
+                        sequenced.push(self.sequence());
+                    }
+                    if let Some(batch) = self.queue.queue_transaction(transaction) {
+                        self.build_batch(batch, span.clone()).await;
+                    }
+                }
+                let _ = response.send(Ok(sequenced));
+            }
+            QueueCommand::InitiateBatch { response } => {
+                let batch = self.queue.batch_transactions();
+                let index = batch.as_ref().map(|b| b.index);
+                if let Some(batch) = batch {
+                    self.build_batch(batch, info_span!("initiate_batch")).await;
+                }
+                let _ = response.send(Ok(index));
+            }
+            QueueCommand::ListPending { filter, response } => {
+                let pending = self
+                    .queue
+                    .pending()
+                    .iter()
+                    .filter(|tx| filter.matches(tx))
+                    .take(filter.limit)
+                    .cloned()
+                    .collect();
+                let _ = response.send(pending);
+            }
+            QueueCommand::EvictTransaction {
+                from,
+                nonce,
+                response,
+            } => {
+                let evicted = self.queue.evict(from, nonce);
+                if !evicted.is_empty() {
+                    info!(%from, nonce, count = evicted.len(), "evicted pending transactions");
+                }
+                let _ = response.send(evicted);
             }
+            QueueCommand::UpdateBatchParams { update, response } => {
+                if let Some(max_transactions) = update.max_transactions {
+                    info!(max_transactions, "updated batch parameters");
+                    self.queue.set_batch_size(max_transactions);
+                    // A backlog over the new size goes out now, not a batch per trigger
+                    let span = info_span!("resize_batches");
+                    while let Some(batch) = self.queue.seal_full_batch() {
+                        self.build_batch(batch, span.clone()).await;
+                    }
+                }
+                let _ = response.send(BatchParams {
+                    max_transactions: self.queue.batch_size(),
+                });
+            }
+            QueueCommand::NextDepositBlock { response } => {
+                let next = self.deposits.as_ref().and_then(DepositCursor::next_block);
+                let _ = response.send(next);
+            }
+            QueueCommand::SubmitDeposits {
+                from,
+                through,
+                deposits,
+                response,
+            } => {
+                let queued = self
+                    .deposits
+                    .as_mut()
+                    .and_then(|cursor| cursor.advance(from, through, deposits));
+                if let Some(queued) = &queued {
+                    self.seal_deposits(queued.clone(), from, through).await;
+                }
+                let _ = response.send(queued);
+            }
+            QueueCommand::Rewind { fork, response } => {
+                let rewound = match self.rewind(fork).await {
+                    Ok(rewound) => rewound,
+                    Err(e) => {
+                        error!(error = %e, "failed to rewind after L1 reorg");
+                        None
+                    }
+                };
+                self.update_queue_status();
+                let _ = response.send(rewound);
+            }
+            QueueCommand::Shutdown { response } => {
+                let span = info_span!("final_batches");
+                let mut indices = Vec::new();
+                while let Some(batch) = self.queue.batch_transactions() {
+                    indices.push(batch.index);
+                    self.build_batch(batch, span.clone()).await;
+                }
+                self.update_queue_status();
+
+                // Reply once the final batches are persisted
+                self.flush_builder().await;
+                info!(batches = ?indices, "queue flushed for shutdown");
+                let _ = response.send(indices);
+                // Dropping the processor stops the builder, then the submitter
+                return ControlFlow::Break(());
+            }
+        }
+        ControlFlow::Continue(())
+    }
+
+    /// Waits until the builder has stored every batch sent to it.
+    async fn flush_builder(&self) {
+        let (flushed_tx, flushed_rx) = oneshot::channel();
+        if self
+            .build_tx
+            .send(BuildCommand::Flush {
+                response: flushed_tx,
+            })
+            .await
+            .is_ok()
+        {
+            let _ = flushed_rx.await;
+        }
+    }
+
+    /// Unposted batches from the first with a block whose L1 origin is at
+    /// or after `fork` are deleted, and their transactions sealed again
+    /// against the current L1 head.
+    async fn rewind(&mut self, fork: u64) -> Result<Option<u64>, StoreError> {
+        self.flush_builder().await;
+        // Deposits from the fork on may be gone, or moved to other blocks
+        if let Some(cursor) = &mut self.deposits {
+            cursor.rewind(fork);
+            self.hold_for_deposits();
+        }
+
+        // Posting is in order, so the unposted batches are the newest ones
+        let mut unposted = Vec::new();
+        for index in self.store.indices()?.into_iter().rev() {
+            if self.store.posted(index)?.is_some() {
+                break;
+            }
+            unposted.extend(self.store.load(index)?);
+        }
+        while unposted
+            .last()
+            .is_some_and(|batch| batch.blocks.iter().all(|b| b.l1_origin < fork))
+        {
+            unposted.pop();
+        }
+        unposted.reverse();
+        let Some(from_index) = unposted.first().map(|batch| batch.index) else {
+            return Ok(None);
+        };
+        let last_kept = match from_index.checked_sub(1) {
+            Some(index) => match self.store.load(index)? {
+                Some(batch) => Some(batch),
+                None => {
+                    error!(
+                        batch_index = index,
+                        "batch before the rewind is missing; not rewinding"
+                    );
+                    return Ok(None);
+                }
+            },
+            None => None,
+        };
+
+        let blocks: Vec<Block> = unposted.into_iter().flat_map(|b| b.blocks).collect();
+        for index in from_index..self.queue.next_batch_index() {
+            self.store.remove(index)?;
+        }
+        let (reorged, transactions): (Vec<L2Transaction>, Vec<L2Transaction>) = blocks
+            .iter()
+            .flat_map(|b| b.transactions.iter().cloned())
+            .partition(|tx| tx.is_deposit() && tx.l1_block_number >= fork);
+        let count = transactions.len();
+        self.queue.set_l1_head(self.l1_head.sequencing_head());
+        let rejected = self.queue.rewind(last_kept.as_ref(), transactions);
+        for tx in &rejected {
+            warn!(tx_hash = %tx.hash(), l1_block_number = tx.l1_block_number, "dropping rewound transaction outside the sequencing window");
+        }
+        warn!(
+            fork,
+            from_batch = from_index,
+            from_block = blocks.first().map(|b| b.number),
+            blocks = blocks.len(),
+            rejected = rejected.len(),
+            reread_deposits = reorged.len(),
+            "L1 origin reorged out; re-sequencing unposted blocks"
+        );
+        self.metrics.rewound_blocks.inc_by(blocks.len() as u64);
+
+        // Sealed straight away so they keep their place ahead of new ones
+        let span = info_span!("rewind", fork);
+        let mut remaining = count - rejected.len();
+        while remaining > 0 {
+            let Some(batch) = self.queue.batch_transactions() else {
+                break;
+            };
+            let sealed: usize = batch.blocks.iter().map(|b| b.transactions.len()).sum();
+            remaining = remaining.saturating_sub(sealed);
+            self.build_batch(batch, span.clone()).await;
+        }
+        Ok(Some(from_index))
+    }
+
+    /// Seals `deposits` into as many blocks as they take, ahead of the
+    /// pending transactions, once reading has reached `through`.
+    async fn seal_deposits(&mut self, deposits: Vec<L2Transaction>, from: u64, through: u64) {
+        self.hold_for_deposits();
+        self.queue.set_l1_head(self.l1_head.sequencing_head());
+        if deposits.is_empty() {
+            return;
+        }
+
+        self.metrics.deposits.inc_by(deposits.len() as u64);
+        self.queue.queue_deposits(deposits);
+        let span = info_span!("deposits", from, through);
+        while self.queue.pending_deposits() > 0 {
+            let Some(batch) = self.queue.batch_transactions() else {
+                break;
+            };
+            self.build_batch(batch, span.clone()).await;
+        }
+    }
+
+    /// Keeps the L1 origin where every deposit not read yet is still due.
+    fn hold_for_deposits(&self) {
+        if let Some(limit) = self.deposits.as_ref().and_then(DepositCursor::origin_limit) {
+            self.l1_head.hold_at(limit);
+        }
+    }
+
+    fn update_queue_status(&self) {
+        self.metrics.queue_depth.set(self.queue.len() as i64);
+        self.status
+            .set_queue(self.queue.head_number(), self.queue.len());
+    }
+
+    async fn build_batch(&self, batch: SealedBatch, span: Span) {
+        // Waits for the builder if it's behind, pushing back on admission
+        if self
+            .build_tx
+            .send(BuildCommand::Build { batch, span })
+            .await
+            .is_err()
+        {
+            error!("batch builder has stopped; batch lost");
         }
     }
 }
 
-pub fn setup_queue<T: Provider>(provider: T) -> (QueueHandle, QueueProcessor<T>) {
-    let (command_tx, command_rx) = mpsc::channel(100);
-    let (feed_tx, _feed_rx) = broadcast::channel(100);
-    let handle = QueueHandle::new(command_tx, feed_tx);
-    let processor = QueueProcessor::new(provider, command_rx);
-    (handle, processor)
+/// Answers a command that arrived after `Shutdown`. Those without an error
+/// reply see their response dropped, which callers report as
+/// `QueueError::Closed`.
+fn refuse(command: QueueCommand) {
+    match command {
+        QueueCommand::SubmitTransaction { response, .. } => {
+            let _ = response.send(Err(QueueError::ShuttingDown));
+        }
+        QueueCommand::SubmitTransactions { response, .. } => {
+            let _ = response.send(Err(QueueError::ShuttingDown));
+        }
+        QueueCommand::InitiateBatch { response } => {
+            let _ = response.send(Err(QueueError::ShuttingDown));
+        }
+        _ => {}
+    }
+}
+
+/// Builds the queue processor and the batch builder it feeds; spawn both.
+pub fn setup_queue<T: Provider>(
+    provider: T,
+    config: &Config,
+    store: Arc<BatchStore>,
+    metrics: Arc<Metrics>,
+    status: Arc<SequencerStatus>,
+    codec: Arc<dyn BatchCodec>,
+) -> Result<(QueueHandle, QueueProcessor<T>, BatchBuilder), StoreError> {
+    let (command_tx, command_rx) = mpsc::channel(config.channels.command_capacity);
+    let (feed_tx, _feed_rx) = broadcast::channel(config.channels.feed_capacity);
+    let (build_tx, build_rx) = mpsc::channel(config.channels.batch_capacity);
+    let handle = QueueHandle::new(command_tx, feed_tx.clone());
+
+    let mut queue = Queue::new(provider, config.batch.max_transactions)
+        .with_max_compressed_bytes(config.batch.max_compressed_bytes, codec.clone())
+        .with_sequencing_window(config.l1.sequencing_window());
+    if let Some(last) = store.latest()? {
+        info!(batch_index = last.index, "resuming after stored batch");
+        queue.resume_from(&last);
+        status.set_last_batch(last.index);
+        status.set_queue(queue.head_number(), 0);
+    }
+
+    let mut processor = QueueProcessor::new(
+        queue,
+        command_rx,
+        build_tx,
+        store.clone(),
+        metrics.clone(),
+        status.clone(),
+    );
+    if config.l1.bridge.is_some() {
+        let cursor = DepositCursor::resume(&store, config.l1.deposit_deadline)?;
+        if let Some(next_block) = cursor.next_block() {
+            info!(l1_block = next_block, "resuming deposits");
+        }
+        processor = processor.with_deposits(cursor);
+    }
+    // Counting stored transactions misses numbers given to ones evicted,
+    // rewound away or still pending at a crash, so the reservation wins
+    let next_sequence_number = match store.sequence_reserved()? {
+        Some(reserved) => reserved,
+        None => {
+            let mut admitted = 0;
+            for index in store.indices()? {
+                if let Some(batch) = store.load(index)? {
+                    admitted += batch
+                        .blocks
+                        .iter()
+                        .flat_map(|block| &block.transactions)
+                        .filter(|tx| !tx.is_deposit())
+                        .count() as u64;
+                }
+            }
+            admitted
+        }
+    };
+    let processor = processor.with_next_sequence_number(next_sequence_number);
+    let builder = BatchBuilder::new(
+        build_rx,
+        feed_tx,
+        store,
+        metrics,
+        status,
+        codec,
+        config.batch.format,
+    );
+    Ok((handle, processor, builder))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::codec::PayloadFormat;
+    use crate::models::{decode_transactions, BatchFormat, Deposit};
+    use crate::services::batch_store::PostedBatch;
+    use alloy::primitives::{Bytes, B256, U256};
+    use alloy::providers::ProviderBuilder;
+    use std::time::Duration;
+
+    fn start_queue(name: &str) -> (QueueHandle, Arc<BatchStore>) {
+        start_queue_with(name, &Config::default(), Arc::new(L1Head::new()))
+    }
+
+    fn start_queue_with(
+        name: &str,
+        config: &Config,
+        l1_head: Arc<L1Head>,
+    ) -> (QueueHandle, Arc<BatchStore>) {
+        // Never contacted; the queue doesn't call the provider yet
+        let provider = ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap());
+        let data_dir = std::env::temp_dir().join(format!("queue-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let store = Arc::new(BatchStore::open(&data_dir).unwrap());
+        let status = SequencerStatus::new(data_dir, Duration::from_secs(60));
+
+        let (handle, processor, mut builder) = setup_queue(
+            provider,
+            config,
+            store.clone(),
+            Arc::new(Metrics::new()),
+            Arc::new(status),
+            config.batch.codec().unwrap(),
+        )
+        .unwrap();
+        let mut processor = processor.with_l1_head(l1_head);
+        tokio::spawn(async move { processor.run().await });
+        tokio::spawn(async move { builder.run().await });
+        (handle, store)
+    }
+
+    fn transaction(from: Address, nonce: u64) -> L2Transaction {
+        L2Transaction::new(
+            nonce,
+            from,
+            Some(Address::repeat_byte(0xcc)),
+            U256::ZERO,
+            Bytes::new(),
+            21000,
+            U256::from(1),
+            Some(42161),
+            0,
+            U256::ZERO,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_admin_commands() {
+        let (queue, _store) = start_queue("admin");
+        let alice = Address::repeat_byte(0xaa);
+        let bob = Address::repeat_byte(0xbb);
+        for tx in [
+            transaction(alice, 0),
+            transaction(bob, 0),
+            transaction(alice, 1),
+        ] {
+            queue.submit_transaction(tx).unwrap();
+        }
+
+        let from_alice = PendingFilter {
+            from: Some(alice),
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            queue.list_pending(from_alice.clone()).await.unwrap().len(),
+            2
+        );
+
+        // An identical resubmission goes with the original
+        queue.submit_transaction(transaction(alice, 0)).unwrap();
+        let hash = transaction(alice, 0).hash();
+        let mut feed = queue.subscribe();
+        assert_eq!(queue.evict_transaction(alice, 0).await.unwrap().len(), 2);
+        assert!(queue.evict_transaction(alice, 0).await.unwrap().is_empty());
+        for _ in 0..2 {
+            assert!(matches!(feed.try_recv(), Ok(FeedEvent::Evicted(tx)) if tx.hash() == hash));
+        }
+        assert!(feed.try_recv().is_err());
+        assert_eq!(queue.list_pending(from_alice).await.unwrap().len(), 1);
+
+        let params = queue
+            .update_batch_params(BatchParamsUpdate {
+                max_transactions: Some(5),
+            })
+            .await
+            .unwrap();
+        assert_eq!(params.max_transactions, 5);
+
+        assert_eq!(queue.batch_transactions().await.unwrap(), Some(0));
+        assert_eq!(queue.batch_transactions().await.unwrap(), None);
+
+        // Lowering the size seals every full batch of the backlog at once
+        for nonce in 1..=4 {
+            queue.submit_transaction(transaction(bob, nonce)).unwrap();
+        }
+        queue
+            .update_batch_params(BatchParamsUpdate {
+                max_transactions: Some(2),
+            })
+            .await
+            .unwrap();
+        assert!(queue
+            .list_pending(PendingFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(queue.batch_transactions().await.unwrap(), None);
+
+        queue.set_admission_paused(true);
+        assert!(matches!(
+            queue.submit_transaction(transaction(bob, 1)),
+            Err(QueueError::Paused)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_blocks_follow_l1_head() {
+        let mut config = Config::default();
+        config.l1.sequencing_window = 100;
+        let l1_head = Arc::new(L1Head::new());
+        let (queue, store) = start_queue_with("l1-origin", &config, l1_head.clone());
+        let alice = Address::repeat_byte(0xaa);
+
+        // Until the head is known blocks keep the genesis origin
+        queue.submit_transaction(transaction(alice, 0)).unwrap();
+        assert_eq!(queue.batch_transactions().await.unwrap(), Some(0));
+
+        l1_head.set(1_000);
+        let mut tx = transaction(alice, 1);
+        tx.l1_block_number = 950;
+        queue.submit_transaction(tx).unwrap();
+        assert_eq!(queue.batch_transactions().await.unwrap(), Some(1));
+
+        // Held back so the L1 block 950 transaction stays in the window
+        l1_head.set(1_200);
+        let mut tx = transaction(alice, 2);
+        tx.l1_block_number = 950;
+        queue.submit_transaction(tx).unwrap();
+        assert_eq!(queue.batch_transactions().await.unwrap(), Some(2));
+        queue.shutdown().await.unwrap();
+
+        let origins: Vec<u64> = (0..3)
+            .map(|index| store.load(index).unwrap().unwrap().blocks[0].l1_origin)
+            .collect();
+        assert_eq!(origins, vec![0, 1_000, 1_050]);
+    }
+
+    #[tokio::test]
+    async fn test_rewinds_blocks_with_reorged_origin() {
+        let mut config = Config::default();
+        config.l1.sequencing_window = 100;
+        let l1_head = Arc::new(L1Head::new());
+        let (queue, store) = start_queue_with("rewind", &config, l1_head.clone());
+        let alice = Address::repeat_byte(0xaa);
+        let submit = |nonce| {
+            let mut tx = transaction(alice, nonce);
+            tx.l1_block_number = 990;
+            queue.submit_transaction(tx).unwrap();
+        };
+
+        l1_head.set(990);
+        submit(0);
+        assert_eq!(queue.batch_transactions().await.unwrap(), Some(0));
+        l1_head.set(1_000);
+        for nonce in [1, 2] {
+            submit(nonce);
+            queue.batch_transactions().await.unwrap();
+        }
+        submit(3);
+
+        // Nothing unposted has an origin from 1_001 on
+        assert_eq!(queue.rewind(1_001).await.unwrap(), None);
+
+        let posted = PostedBatch {
+            l1_tx_hash: B256::repeat_byte(1),
+            l1_block: Some(991),
+            l1_block_hash: None,
+        };
+        store.mark_posted(0, &posted).unwrap();
+        l1_head.set(998);
+        assert_eq!(queue.rewind(995).await.unwrap(), Some(1));
+        queue.shutdown().await.unwrap();
+
+        assert_eq!(store.indices().unwrap(), vec![0, 1]);
+        let kept = store.load(0).unwrap().unwrap();
+        let rebuilt = store.load(1).unwrap().unwrap();
+        let block = &rebuilt.blocks[0];
+        assert_eq!(block.number, 2);
+        assert_eq!(block.parent_hash, kept.blocks[0].hash());
+        assert_eq!(block.l1_origin, 998);
+        // The rewound transactions keep their place ahead of the pending one
+        let nonces: Vec<u64> = block.transactions.iter().map(|tx| tx.nonce).collect();
+        assert_eq!(nonces, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_deposits_go_first() {
+        let mut config = Config::default();
+        config.l1.bridge = Some(Address::repeat_byte(0xb1));
+        config.l1.deposit_deadline = 50;
+        let l1_head = Arc::new(L1Head::new());
+        let (queue, store) = start_queue_with("deposits", &config, l1_head.clone());
+        let alice = Address::repeat_byte(0xaa);
+        l1_head.set(1_000);
+        queue.submit_transaction(transaction(alice, 0)).unwrap();
+
+        // A new chain reads from wherever the watcher starts
+        assert_eq!(queue.next_deposit_block().await.unwrap(), None);
+        let mut deposit = transaction(Address::repeat_byte(0xdd), 0).with_deposit(Deposit {
+            l1_tx_hash: B256::repeat_byte(0xd0),
+            log_index: 0,
+            mint: U256::from(1),
+        });
+        deposit.l1_block_number = 900;
+        // Taken even while admission is paused
+        queue.set_admission_paused(true);
+        let queued = queue
+            .submit_deposits(900, 900, vec![deposit])
+            .await
+            .unwrap();
+        assert_eq!(queued.map(|q| q.len()), Some(1));
+        assert_eq!(queue.next_deposit_block().await.unwrap(), Some(901));
+        assert!(queue
+            .submit_deposits(900, 910, Vec::new())
+            .await
+            .unwrap()
+            .is_none());
+        queue.shutdown().await.unwrap();
+
+        let batch = store.load(0).unwrap().unwrap();
+        let deposits: Vec<bool> = batch.blocks[0]
+            .transactions
+            .iter()
+            .map(L2Transaction::is_deposit)
+            .collect();
+        assert_eq!(deposits, vec![true, false]);
+        // Held back while a deposit from L1 block 901 would be due
+        assert_eq!(batch.blocks[0].l1_origin, 950);
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_queue() {
+        let (queue, store) = start_queue("shutdown");
+        for nonce in 0..3 {
+            queue
+                .submit_transaction(transaction(Address::repeat_byte(0xaa), nonce))
+                .unwrap();
+        }
+
+        // Commands that got on the channel behind `Shutdown` are refused,
+        // not silently dropped
+        let (shutdown_tx, shutdown_rx) = oneshot::channel();
+        let (late_batch_tx, late_batch_rx) = oneshot::channel();
+        let (late_submit_tx, late_submit_rx) = oneshot::channel();
+        queue.begin_shutdown();
+        for command in [
+            QueueCommand::Shutdown {
+                response: shutdown_tx,
+            },
+            QueueCommand::InitiateBatch {
+                response: late_batch_tx,
+            },
+            QueueCommand::SubmitTransaction {
+                transaction: transaction(Address::repeat_byte(0xaa), 3),
+                span: Span::none(),
+                response: late_submit_tx,
+            },
+        ] {
+            queue.command_tx.send(command).await.unwrap();
+        }
+        assert_eq!(shutdown_rx.await.unwrap(), vec![0]);
+        assert!(matches!(
+            late_batch_rx.await,
+            Ok(Err(QueueError::ShuttingDown))
+        ));
+        assert!(matches!(
+            late_submit_rx.await,
+            Ok(Err(QueueError::ShuttingDown))
+        ));
+        assert_eq!(store.latest().unwrap().unwrap().transaction_count(), 3);
+        assert!(matches!(
+            queue.submit_transaction(transaction(Address::repeat_byte(0xaa), 3)),
+            Err(QueueError::ShuttingDown)
+        ));
+        assert!(matches!(
+            queue.batch_transactions().await,
+            Err(QueueError::Closed)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_submit_transactions_together() {
+        let (queue, store) = start_queue("many");
+        queue
+            .update_batch_params(BatchParamsUpdate {
+                max_transactions: Some(2),
+            })
+            .await
+            .unwrap();
+
+        let mut feed = queue.subscribe();
+        let alice = Address::repeat_byte(0xaa);
+        let txs: Vec<_> = (0..3).map(|nonce| transaction(alice, nonce)).collect();
+        let sequenced = queue
+            .submit_transactions(txs)
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        let numbered: Vec<_> = sequenced
+            .iter()
+            .map(|s| (s.sequence_number, s.target_block))
+            .collect();
+        assert_eq!(numbered, vec![(0, 1), (1, 1), (2, 2)]);
+
+        // Batches are stored before they're published
+        loop {
+            if let FeedEvent::Batch(_) = feed.recv().await.unwrap() {
+                break;
+            }
+        }
+        assert_eq!(store.latest().unwrap().unwrap().transaction_count(), 2);
+        let pending = queue
+            .list_pending(PendingFilter {
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].nonce, 2);
+
+        // Numbering continues after a restart, past every number that might
+        // have been handed out, including the evicted transaction's
+        assert_eq!(
+            queue
+                .evict_transaction(pending[0].from, pending[0].nonce)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        queue.shutdown().await.unwrap();
+        let provider = ProviderBuilder::new().on_http("http://localhost:8545".parse().unwrap());
+        let data_dir = std::env::temp_dir().join(format!("queue-many-{}", std::process::id()));
+        let (queue, mut processor, mut builder) = setup_queue(
+            provider,
+            &Config::default(),
+            store,
+            Arc::new(Metrics::new()),
+            Arc::new(SequencerStatus::new(data_dir, Duration::from_secs(60))),
+            Config::default().batch.codec().unwrap(),
+        )
+        .unwrap();
+        tokio::spawn(async move { processor.run().await });
+        tokio::spawn(async move { builder.run().await });
+        let sequenced = queue
+            .submit_transaction(transaction(alice, 3))
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sequenced.sequence_number, SEQUENCE_RESERVATION);
+        assert_eq!(sequenced.target_block, 2);
+    }
+
+    #[tokio::test]
+    async fn test_unreserved_submissions_are_not_queued() {
+        let (queue, _store) = start_queue("unreserved");
+        // A directory where the reservation goes makes writing it fail
+        let reservation = std::env::temp_dir()
+            .join(format!("queue-unreserved-{}", std::process::id()))
+            .join("batches/sequence.json");
+        std::fs::create_dir_all(reservation.join("blocked")).unwrap();
+
+        let alice = Address::repeat_byte(0xaa);
+        let single = queue.submit_transaction(transaction(alice, 0)).unwrap();
+        assert!(matches!(single.await, Ok(Err(QueueError::Unsequenced))));
+        let many = queue
+            .submit_transactions((1..3).map(|nonce| transaction(alice, nonce)).collect())
+            .unwrap();
+        assert!(matches!(many.await, Ok(Err(QueueError::Unsequenced))));
+        let pending = PendingFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        assert!(queue
+            .list_pending(pending.clone())
+            .await
+            .unwrap()
+            .is_empty());
+
+        // Nobody waits for a number without a signer, so it's queued unnumbered
+        drop(queue.submit_transaction(transaction(alice, 0)).unwrap());
+        assert_eq!(queue.list_pending(pending).await.unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&reservation).unwrap();
+        let sequenced = queue
+            .submit_transaction(transaction(alice, 1))
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(sequenced.sequence_number, 0);
+    }
+
+    #[tokio::test]
+    async fn test_span_batches() {
+        let mut config = Config::default();
+        config.batch.format = BatchFormat::Span;
+        config.batch.max_transactions = 5;
+        let (queue, store) = start_queue_with("span", &config, Arc::new(L1Head::new()));
+
+        let mut feed = queue.subscribe();
+        let alice = Address::repeat_byte(0xaa);
+        let txs: Vec<_> = (0..5).map(|nonce| transaction(alice, nonce)).collect();
+        queue.submit_transactions(txs.clone()).unwrap();
+        loop {
+            if let FeedEvent::Batch(_) = feed.recv().await.unwrap() {
+                break;
+            }
+        }
+
+        let batch = store.latest().unwrap().unwrap();
+        assert_eq!(batch.data[0], PayloadFormat::Span as u8);
+        let decoded = decode_transactions(&batch.data, &config.batch.decoder().unwrap()).unwrap();
+        let hashes: Vec<_> = decoded.iter().map(L2Transaction::hash).collect();
+        assert_eq!(
+            hashes,
+            txs.iter().map(L2Transaction::hash).collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_byte_target_closes_batches() {
+        let mut config = Config::default();
+        config.batch.max_compressed_bytes = Some(200);
+        let (queue, store) = start_queue_with("bytes", &config, Arc::new(L1Head::new()));
+
+        let mut feed = queue.subscribe();
+        for nonce in 0..40 {
+            queue
+                .submit_transaction(transaction(Address::repeat_byte(0xaa), nonce))
+                .unwrap();
+        }
+        let final_batches = queue.shutdown().await.unwrap();
+        assert_eq!(final_batches.len(), 1);
+
+        let mut transactions = 0;
+        while let Ok(event) = feed.try_recv() {
+            if let FeedEvent::Batch(summary) = event {
+                let batch = store.load(summary.index).unwrap().unwrap();
+                assert!(batch.data.len() <= 200);
+                transactions += batch.transaction_count();
+            }
+        }
+        assert!(store.indices().unwrap().len() > 1);
+        assert_eq!(transactions, 40);
+    }
 }
@@ -0,0 +1,185 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Live sequencer state for `/status` and `/readyz`, updated by the queue
+/// processor and the L1 batch submitter.
+pub struct SequencerStatus {
+    started_at: Instant,
+    data_dir: PathBuf,
+    head_block: AtomicU64,
+    pending_transactions: AtomicUsize,
+    /// `u64::MAX` until the first batch
+    last_batch_index: AtomicU64,
+    l1: Mutex<L1PostingState>,
+    l1_stall_threshold: Duration,
+    /// `/readyz`'s last RPC and storage probes, and when they ran
+    probes: tokio::sync::Mutex<Option<(Instant, Probes)>>,
+}
+
+/// Outcome of `/readyz`'s RPC and storage probes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probes {
+    pub rpc: Result<(), String>,
+    pub storage: Result<(), String>,
+}
+
+#[derive(Default)]
+struct L1PostingState {
+    /// When the submitter started on the batch it's still working on
+    busy_since: Option<Instant>,
+    last_posted: Option<Instant>,
+}
+
+impl SequencerStatus {
+    pub fn new(data_dir: PathBuf, l1_stall_threshold: Duration) -> Self {
+        Self {
+            started_at: Instant::now(),
+            data_dir,
+            head_block: AtomicU64::new(0),
+            pending_transactions: AtomicUsize::new(0),
+            last_batch_index: AtomicU64::new(u64::MAX),
+            l1: Mutex::new(L1PostingState::default()),
+            l1_stall_threshold,
+            probes: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn data_dir(&self) -> &Path {
+        &self.data_dir
+    }
+
+    pub fn head_block(&self) -> u64 {
+        self.head_block.load(Ordering::Relaxed)
+    }
+
+    pub fn pending_transactions(&self) -> usize {
+        self.pending_transactions.load(Ordering::Relaxed)
+    }
+
+    pub fn last_batch_index(&self) -> Option<u64> {
+        match self.last_batch_index.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            index => Some(index),
+        }
+    }
+
+    pub fn set_queue(&self, head_block: u64, pending_transactions: usize) {
+        self.head_block.store(head_block, Ordering::Relaxed);
+        self.pending_transactions
+            .store(pending_transactions, Ordering::Relaxed);
+    }
+
+    pub fn set_last_batch(&self, index: u64) {
+        self.last_batch_index.store(index, Ordering::Relaxed);
+    }
+
+    pub fn l1_post_started(&self) {
+        self.l1_state().busy_since = Some(Instant::now());
+    }
+
+    /// Called when the submitter is done with a batch, posted or not.
+    pub fn l1_post_finished(&self, posted: bool) {
+        let mut l1 = self.l1_state();
+        l1.busy_since = None;
+        if posted {
+            l1.last_posted = Some(Instant::now());
+        }
+    }
+
+    pub fn last_l1_post(&self) -> Option<Instant> {
+        self.l1_state().last_posted
+    }
+
+    /// How long the submitter has been stuck on one batch, if longer than
+    /// the threshold.
+    pub fn l1_stalled_for(&self) -> Option<Duration> {
+        self.l1_stalled_at(Instant::now())
+    }
+
+    fn l1_stalled_at(&self, now: Instant) -> Option<Duration> {
+        let busy_for = now.saturating_duration_since(self.l1_state().busy_since?);
+        (busy_for > self.l1_stall_threshold).then_some(busy_for)
+    }
+
+    /// Probes from the last `ttl`, or runs `probe`. Concurrent callers share
+    /// one run rather than each calling the RPC provider and writing to disk.
+    pub async fn probes(&self, ttl: Duration, probe: impl Future<Output = Probes>) -> Probes {
+        let mut cached = self.probes.lock().await;
+        if let Some((at, probes)) = cached.as_ref() {
+            if at.elapsed() < ttl {
+                return probes.clone();
+            }
+        }
+        let probes = probe.await;
+        *cached = Some((Instant::now(), probes.clone()));
+        probes
+    }
+
+    fn l1_state(&self) -> std::sync::MutexGuard<'_, L1PostingState> {
+        self.l1.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_l1_stall_detection() {
+        let status = SequencerStatus::new(PathBuf::from("data"), Duration::from_secs(60));
+        let now = Instant::now();
+        assert_eq!(status.l1_stalled_at(now), None);
+
+        status.l1_post_started();
+        assert_eq!(status.l1_stalled_at(now + Duration::from_secs(30)), None);
+        assert!(status
+            .l1_stalled_at(now + Duration::from_secs(120))
+            .is_some());
+
+        status.l1_post_finished(true);
+        assert_eq!(status.l1_stalled_at(now + Duration::from_secs(120)), None);
+        assert!(status.last_l1_post().is_some());
+    }
+
+    #[test]
+    fn test_last_batch_index() {
+        let status = SequencerStatus::new(PathBuf::from("data"), Duration::from_secs(60));
+        assert_eq!(status.last_batch_index(), None);
+        status.set_last_batch(0);
+        assert_eq!(status.last_batch_index(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_probes_are_cached() {
+        let status = SequencerStatus::new(PathBuf::from("data"), Duration::from_secs(60));
+        let probe = |rpc: Result<(), String>| async move {
+            Probes {
+                rpc,
+                storage: Ok(()),
+            }
+        };
+
+        let ttl = Duration::from_secs(60);
+        assert_eq!(status.probes(ttl, probe(Ok(()))).await.rpc, Ok(()));
+        // Within the TTL the earlier result is served
+        assert_eq!(
+            status.probes(ttl, probe(Err("down".into()))).await.rpc,
+            Ok(())
+        );
+        // Past it, the probes run again
+        assert_eq!(
+            status
+                .probes(Duration::ZERO, probe(Err("down".into())))
+                .await
+                .rpc,
+            Err("down".to_string())
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use crate::config::{LogFormat, LoggingConfig};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Flushes exported spans when dropped; keep it alive for the life of the
+/// process.
+pub struct TelemetryGuard {
+    #[cfg(feature = "otlp")]
+    tracer_provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "otlp")]
+        if let Some(provider) = self.tracer_provider.take() {
+            let _ = provider.shutdown();
+        }
+    }
+}
+
+/// Installs the global subscriber: `RUST_LOG`-style filtering, pretty or JSON
+/// output, and OTLP export when an endpoint is configured.
+pub fn init(config: &LoggingConfig) -> Result<TelemetryGuard, Box<dyn std::error::Error>> {
+    let filter = EnvFilter::try_new(&config.filter)?;
+
+    let fmt = match config.format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer().json().boxed(),
+    };
+    #[cfg_attr(not(feature = "otlp"), allow(unused_mut))]
+    let mut layers: Vec<BoxedLayer> = vec![fmt];
+
+    #[cfg(feature = "otlp")]
+    let tracer_provider = match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let (layer, provider) = otlp_layer(endpoint)?;
+            layers.push(layer);
+            Some(provider)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()?;
+
+    #[cfg(not(feature = "otlp"))]
+    if config.otlp_endpoint.is_some() {
+        tracing::warn!("logging.otlp_endpoint is set but this build lacks the `otlp` feature");
+    }
+
+    Ok(TelemetryGuard {
+        #[cfg(feature = "otlp")]
+        tracer_provider,
+    })
+}
+
+#[cfg(feature = "otlp")]
+fn otlp_layer(
+    endpoint: &str,
+) -> Result<(BoxedLayer, opentelemetry_sdk::trace::SdkTracerProvider), Box<dyn std::error::Error>> {
+    use opentelemetry::trace::TracerProvider;
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name("sequencer").build())
+        .build();
+
+    let layer = tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("sequencer"))
+        .boxed();
+    Ok((layer, provider))
+}
@@ -1,9 +1,21 @@
+use crate::api::auth::Authenticator;
+use crate::config::SubmissionConfig;
+use crate::models::SequencingWindow;
+use crate::services::batch_submitter::BatchSubmitterHandle;
+use crate::services::l1_head::L1Head;
+use crate::services::metrics::Metrics;
+use crate::services::outbox::WithdrawalIndex;
+use crate::services::outputs::OutputStore;
 use crate::services::queue_service::QueueHandle;
+use crate::services::status::SequencerStatus;
 use alloy::network::Ethereum;
 use alloy::providers::fillers::{
     BlobGasFiller, ChainIdFiller, FillProvider, GasFiller, JoinFill, NonceFiller,
 };
 use alloy::providers::{Identity, RootProvider};
+use alloy::signers::local::PrivateKeySigner;
+use std::sync::Arc;
+use tokio::sync::watch;
 
 pub type L2Provider = FillProvider<
     JoinFill<
@@ -18,4 +30,21 @@ pub type L2Provider = FillProvider<
 pub struct AppState {
     pub queue: QueueHandle,
     pub provider: L2Provider,
+    pub auth: Arc<Authenticator>,
+    /// Admit feed connections without credentials
+    pub public_feed: bool,
+    pub submission: SubmissionConfig,
+    /// Signs a confirmation for each admitted transaction, when configured
+    pub signer: Option<PrivateKeySigner>,
+    pub l1_head: Arc<L1Head>,
+    /// Bounds on submitted `l1_block_number`s, once the L1 head is known
+    pub sequencing_window: SequencingWindow,
+    pub metrics: Arc<Metrics>,
+    pub status: Arc<SequencerStatus>,
+    pub withdrawals: Arc<WithdrawalIndex>,
+    pub outputs: Arc<OutputStore>,
+    /// Set when L1 batch posting is enabled
+    pub l1_submitter: Option<BatchSubmitterHandle>,
+    /// Flips to `true` when the sequencer starts shutting down
+    pub shutdown: watch::Receiver<bool>,
 }
@@ -0,0 +1,186 @@
+//! Runs the batch submitter against an anvil node at `L1_ENDPOINT` and
+//! reorgs it with `anvil_reorg`. Skipped when `L1_ENDPOINT` isn't set.
+
+use alloy::eips::BlockNumberOrTag;
+use alloy::network::EthereumWallet;
+use alloy::primitives::{Address, Bytes, U256};
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::signers::local::PrivateKeySigner;
+use dotenv::dotenv;
+use sequencer::config::Config;
+use sequencer::models::L2Transaction;
+use sequencer::services::batch_store::BatchStore;
+use sequencer::services::batch_submitter::BatchSubmitter;
+use sequencer::services::derivation::{verify_posted_batches, Derivation, InboxScanner};
+use sequencer::services::l1_head::{L1Head, L1HeadTracker};
+use sequencer::services::metrics::Metrics;
+use sequencer::services::queue_service::setup_queue;
+use sequencer::services::status::SequencerStatus;
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// anvil's first dev account
+const ANVIL_KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+async fn wait_for<F, Fut>(what: &str, mut done: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..100 {
+        if done().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("timed out waiting for {}", what);
+}
+
+#[tokio::test]
+async fn test_recovers_from_l1_reorg() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    let Ok(endpoint) = env::var("L1_ENDPOINT") else {
+        eprintln!("skipping: L1_ENDPOINT isn't set");
+        return Ok(());
+    };
+    let endpoint: reqwest::Url = endpoint.parse()?;
+    let l1 = ProviderBuilder::new().on_http(endpoint.clone());
+    let signer: PrivateKeySigner = ANVIL_KEY.parse()?;
+    let poster = signer.address();
+    let wallet_provider = ProviderBuilder::new()
+        .wallet(EthereumWallet::from(signer))
+        .on_http(endpoint);
+
+    // A fresh inbox, so batches from earlier runs aren't derived
+    let inbox = Address::left_padding_from(&std::process::id().to_be_bytes());
+    let mut config = Config::default();
+    config.l1.sequencing_window = 100;
+    config.l1.window_margin = 10;
+    let data_dir = env::temp_dir().join(format!("reorg-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&data_dir);
+    let store = Arc::new(BatchStore::open(&data_dir)?);
+    let metrics = Arc::new(Metrics::new());
+    let status = Arc::new(SequencerStatus::new(data_dir, Duration::from_secs(60)));
+    let l1_head = Arc::new(L1Head::new());
+
+    let (queue, processor, builder) = setup_queue(
+        l1.clone(),
+        &config,
+        store.clone(),
+        metrics.clone(),
+        status.clone(),
+        config.batch.codec()?,
+    )?;
+    let (batch_tx, batch_rx) = mpsc::channel(config.channels.batch_capacity);
+    let (reorg_tx, reorg_rx) = mpsc::channel(16);
+    let mut tracker = L1HeadTracker::new(
+        l1.clone(),
+        l1_head.clone(),
+        Duration::from_millis(100),
+        metrics.clone(),
+    )
+    .with_reorg_detection(config.l1.reorg_depth, reorg_tx);
+    let mut processor = processor.with_l1_head(l1_head.clone());
+    let mut builder = builder.with_batch_submitter(batch_tx);
+    let mut submitter = BatchSubmitter::new(
+        wallet_provider,
+        inbox,
+        batch_rx,
+        store.clone(),
+        metrics.clone(),
+        status,
+    )
+    .with_sequencing_window(
+        l1_head.clone(),
+        config.l1.sequencing_window(),
+        config.l1.window_margin,
+    )
+    .with_reorg_recovery(reorg_rx, queue.clone());
+    let posting = submitter.handle();
+    tokio::spawn(async move { tracker.run().await });
+    tokio::spawn(async move { processor.run().await });
+    tokio::spawn(async move { builder.run().await });
+    tokio::spawn(async move { submitter.run().await });
+    wait_for("the L1 head", || async { l1_head.get().is_some() }).await;
+
+    let submit = |nonce| {
+        let tx = L2Transaction::new(
+            nonce,
+            Address::repeat_byte(0xaa),
+            Some(Address::repeat_byte(0xbb)),
+            U256::ZERO,
+            Bytes::new(),
+            21000,
+            U256::from(1),
+            Some(42161),
+            l1_head.get().unwrap(),
+            U256::ZERO,
+        );
+        queue.submit_transaction(tx).unwrap();
+    };
+
+    // Batch 0 lands in the newest L1 block
+    submit(0);
+    assert_eq!(queue.batch_transactions().await?, Some(0));
+    wait_for("batch 0 to be posted", || async {
+        store.posted(0).unwrap().is_some()
+    })
+    .await;
+    let posted = store.posted(0)?.unwrap();
+    let landed = posted.l1_block.unwrap();
+    wait_for("the tracker to see batch 0's block", || async {
+        l1_head.get() >= Some(landed)
+    })
+    .await;
+
+    // Batch 1 is held with that block as its L1 origin
+    posting.set_paused(true);
+    submit(1);
+    assert_eq!(queue.batch_transactions().await?, Some(1));
+    wait_for("batch 1 to be stored", || async {
+        store.load(1).unwrap().is_some()
+    })
+    .await;
+    let held = store.load(1)?.unwrap();
+    assert!(held.blocks[0].l1_origin >= landed);
+
+    // Replace every block from the one batch 0 landed in
+    let head = l1.get_block_number().await?;
+    let _: serde_json::Value = l1
+        .raw_request(
+            "anvil_reorg".into(),
+            (head - landed + 1, Vec::<serde_json::Value>::new()),
+        )
+        .await?;
+    let replaced = l1
+        .get_block_by_number(
+            BlockNumberOrTag::Number(landed),
+            alloy::rpc::types::BlockTransactionsKind::Hashes,
+        )
+        .await?
+        .unwrap();
+    assert_ne!(Some(replaced.header.hash), posted.l1_block_hash);
+
+    wait_for("the held batch to be rebuilt", || async {
+        metrics.rewound_blocks.get() == 1
+    })
+    .await;
+    assert_eq!(metrics.l1_reorged_batches.get(), 1);
+    posting.set_paused(false);
+    wait_for("both batches to be posted again", || async {
+        let reposted = store.posted(0).unwrap();
+        reposted.is_some_and(|p| p.l1_block_hash != posted.l1_block_hash)
+            && store.posted(1).unwrap().is_some()
+    })
+    .await;
+
+    let scanner = InboxScanner::new(l1, inbox, Some(poster));
+    let derivation = Derivation::new(config.batch.decoder()?)
+        .with_sequencing_window(config.l1.sequencing_window());
+    let divergences = verify_posted_batches(&scanner, derivation, &store, None, None).await?;
+    assert!(divergences.is_empty(), "{:?}", divergences);
+    Ok(())
+}
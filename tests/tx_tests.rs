@@ -1,6 +1,8 @@
 use alloy::primitives::{Address, Bytes, U256};
 use dotenv::dotenv;
+use sequencer::api::handler::ItemStatus;
 use sequencer::client::{ClientError, L2Client};
+use sequencer::config::SubmissionMode;
 use sequencer::models::L2Transaction;
 use std::env;
 use std::str::FromStr;
@@ -11,7 +13,12 @@ async fn setup_client() -> Result<L2Client, Box<dyn std::error::Error>> {
     let endpoint = env::var("L2_ENDPOINT").unwrap_or_else(|_| "http://localhost:3000".to_string());
     let auth_token = env::var("AUTH_TOKEN").map_err(|_| "AUTH_TOKEN must be set in environment")?;
 
-    Ok(L2Client::new(endpoint, auth_token))
+    let client = L2Client::new(endpoint, auth_token);
+    // Checks confirmations when the sequencer signs them
+    match env::var("SEQUENCER_ADDRESS") {
+        Ok(sequencer) => Ok(client.with_sequencer(sequencer.parse()?)),
+        Err(_) => Ok(client),
+    }
 }
 
 #[tokio::test]
@@ -91,6 +98,7 @@ async fn test_multiple_transactions() -> Result<(), Box<dyn std::error::Error>>
 async fn test_invalid_transaction() -> Result<(), Box<dyn std::error::Error>> {
     let client = setup_client().await?;
 
+    // Below the 21000 intrinsic gas minimum
     let tx = L2Transaction::new(
         0,
         Address::from_str("0x1111111111111111111111111111111111111111")?,
@@ -99,7 +107,7 @@ async fn test_invalid_transaction() -> Result<(), Box<dyn std::error::Error>> {
         )?),
         U256::from_str("999999999999999999999999999999999999999")?,
         Bytes::from_str("0x68656c6c6f")?,
-        21000,
+        20000,
         U256::from_str("30000000000")?,
         Some(42161),
         0,
@@ -113,6 +121,8 @@ async fn test_invalid_transaction() -> Result<(), Box<dyn std::error::Error>> {
                 "Expected error received - status: {}, body: {}",
                 status, body
             );
+            assert_eq!(status, 422);
+            assert!(body.contains("VALIDATION_ERROR"));
             Ok(())
         }
         Ok(_) => Err("Expected transaction to fail but it succeeded".into()),
@@ -122,3 +132,58 @@ async fn test_invalid_transaction() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 }
+
+#[tokio::test]
+async fn test_send_transactions_modes() -> Result<(), Box<dyn std::error::Error>> {
+    let client = setup_client().await?;
+
+    let tx = |nonce: u64, gas_limit: u64| -> Result<L2Transaction, Box<dyn std::error::Error>> {
+        Ok(L2Transaction::new(
+            nonce,
+            Address::from_str("0x3333333333333333333333333333333333333333")?,
+            Some(Address::from_str(
+                "0x2222222222222222222222222222222222222222",
+            )?),
+            U256::from_str("1000")?,
+            Bytes::new(),
+            gas_limit,
+            U256::from_str("30000000000")?,
+            Some(42161),
+            0,
+            U256::ZERO,
+        ))
+    };
+    // The middle one is below the 21000 intrinsic gas minimum
+    let txs = vec![tx(0, 21000)?, tx(1, 20000)?, tx(2, 21000)?];
+
+    let atomic = client
+        .send_transactions(&txs, Some(SubmissionMode::Atomic))
+        .await?;
+    assert_eq!(atomic.queued, 0);
+    let statuses: Vec<_> = atomic.results.iter().map(|r| r.status).collect();
+    assert_eq!(
+        statuses,
+        [
+            ItemStatus::Skipped,
+            ItemStatus::Rejected,
+            ItemStatus::Skipped
+        ]
+    );
+    let error = atomic.results[1].error.as_ref().unwrap();
+    assert_eq!(error.code, "VALIDATION_ERROR");
+
+    let individual = client
+        .send_transactions(&txs, Some(SubmissionMode::Individual))
+        .await?;
+    assert_eq!(individual.queued, 2);
+    assert_eq!(individual.results[0].tx_hash, Some(txs[0].hash()));
+    assert_eq!(individual.results[0].status, ItemStatus::Queued);
+    assert_eq!(individual.results[1].status, ItemStatus::Rejected);
+
+    match client.send_transactions(&[], None).await {
+        Err(ClientError::ServerError { status, .. }) => assert_eq!(status, 422),
+        other => return Err(format!("Expected an empty request to fail: {:?}", other).into()),
+    }
+
+    Ok(())
+}